@@ -1,11 +1,17 @@
 // https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-class-power
-use std::{fmt::Display, fs, io, path::PathBuf, str::FromStr};
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use gi_core::{Error, Seconds};
+use gi_core::{Error, Seconds, units::Unit};
+use serde::{Deserialize, Serialize};
 
 const SYS_BATTERIES_PATH: &str = "/sys/class/power_supply";
 
-pub type Capacity = f32;
+pub type Capacity = f64;
 pub type MicroAmpHours = i32;
 pub type MicroAmp = i32;
 
@@ -14,13 +20,15 @@ pub struct Batteries {
     items: Vec<Battery>,
 }
 
+#[derive(Clone)]
 pub struct Battery {
     pub path: PathBuf,
     pub name: String,
     pub charge_full: MicroAmpHours,
 }
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum BatteryInfoName {
     ChargeFull,
     ChargeNow,
@@ -30,7 +38,7 @@ pub enum BatteryInfoName {
     TimeRemaining,
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq)]
 pub enum BatteryStatus {
     Unknown,
     Charging,
@@ -39,7 +47,41 @@ pub enum BatteryStatus {
     Full,
 }
 
+/// Values of a battery read at the same point in time.
+#[derive(Clone, PartialEq)]
+pub struct BatterySnapshot {
+    pub charge_full: MicroAmpHours,
+    pub charge_now: MicroAmpHours,
+    pub current_now: MicroAmp,
+    pub status: BatteryStatus,
+}
+
+impl BatterySnapshot {
+    pub fn capacity(&self) -> Capacity {
+        capacity(self.charge_now, self.charge_full)
+    }
+
+    /// Time until the battery is full or empty, or 0 if it is neither charging nor discharging.
+    pub fn time_remaining(&self) -> Seconds {
+        match self.status {
+            BatteryStatus::Unknown | BatteryStatus::NotCharging | BatteryStatus::Full => 0,
+            BatteryStatus::Charging | BatteryStatus::Discharging => {
+                time_remaining(self.charge_now, self.current_now)
+            }
+        }
+    }
+}
+
 impl Battery {
+    pub fn snapshot(&self) -> Result<BatterySnapshot, Error> {
+        Ok(BatterySnapshot {
+            charge_full: self.get_charge_full(),
+            charge_now: self.get_charge_now()?,
+            current_now: self.get_current_now()?,
+            status: self.get_status()?,
+        })
+    }
+
     pub fn get_charge_full(&self) -> MicroAmpHours {
         self.charge_full
     }
@@ -51,7 +93,7 @@ impl Battery {
     }
 
     pub fn get_capacity(&self) -> Result<Capacity, Error> {
-        Ok(self.get_charge_now()? as f32 / self.get_charge_full() as f32)
+        Ok(capacity(self.get_charge_now()?, self.get_charge_full()))
     }
 
     pub fn get_current_now(&self) -> Result<MicroAmp, Error> {
@@ -62,26 +104,58 @@ impl Battery {
         self.read_from_sysfs("status")?.parse::<BatteryStatus>()
     }
 
+    /// Same as [`BatterySnapshot::time_remaining`], so 0 if the battery is neither charging nor
+    /// discharging.
     pub fn get_time_remaining(&self) -> Result<Seconds, Error> {
-        let hours = (self.get_charge_now()? as f32 / self.get_current_now()? as f32).abs();
-        if hours.is_infinite() {
-            return Ok(0);
-        }
-        let secs = hours * 3600.0;
+        Ok(self.snapshot()?.time_remaining())
+    }
 
-        // Discard milliseconds
-        Ok(secs as u64)
+    fn read_from_sysfs(&self, file_name: &str) -> Result<String, Error> {
+        read_from_sysfs(&self.path, &self.name, file_name)
     }
+}
 
-    fn read_from_sysfs(&self, file_name: &str) -> io::Result<String> {
-        let file_path = self.path.join(file_name);
-        Ok(fs::read_to_string(file_path)?.trim_end().to_string())
+fn capacity(charge_now: MicroAmpHours, charge_full: MicroAmpHours) -> Capacity {
+    charge_now as f64 / charge_full as f64
+}
+
+fn time_remaining(charge_now: MicroAmpHours, current_now: MicroAmp) -> Seconds {
+    let hours = (charge_now as f32 / current_now as f32).abs();
+    if hours.is_infinite() {
+        return 0;
     }
+    let secs = hours * 3600.0;
+
+    // Discard milliseconds
+    secs as u64
+}
+
+/// Missing files mean that the battery doesn't support that info, as some batteries only provide
+/// e.g. `energy_now` instead of `charge_now`.
+fn read_from_sysfs(battery_path: &Path, name: &str, file_name: &str) -> Result<String, Error> {
+    let file_path = battery_path.join(file_name);
+    match fs::read_to_string(&file_path) {
+        Ok(contents) => Ok(contents.trim_end().to_string()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(Error::UnsupportedBatteryInfo {
+            name: name.to_string(),
+            info: file_name.to_string(),
+        }),
+        Err(err) => Err(Error::read_file(file_path, err)),
+    }
+}
+
+fn read_batteries_dir() -> Result<fs::ReadDir, Error> {
+    fs::read_dir(SYS_BATTERIES_PATH).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => Error::NoBatteriesFound {
+            path: SYS_BATTERIES_PATH.to_string(),
+        },
+        _ => Error::read_file(SYS_BATTERIES_PATH, err),
+    })
 }
 
 impl Batteries {
     pub fn init() -> Result<Batteries, Error> {
-        let sys_dir = fs::read_dir(SYS_BATTERIES_PATH)?;
+        let sys_dir = read_batteries_dir()?;
         let battery_dirs = sys_dir
             .filter_map(|dir| {
                 if let Ok(dir) = dir {
@@ -105,9 +179,8 @@ impl Batteries {
                 continue;
             }
 
-            let charge_full = fs::read_to_string(path.join("charge_full"))?
-                .trim_end()
-                .parse::<MicroAmpHours>()?;
+            let charge_full =
+                read_from_sysfs(&path, &name, "charge_full")?.parse::<MicroAmpHours>()?;
 
             battery_infos.push(Battery {
                 path,
@@ -134,6 +207,10 @@ impl Batteries {
             .find(|battery| battery.name == *self.main_battery_name)
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Battery> {
+        self.items.iter()
+    }
+
     pub fn get_battery(&self, battery_name: &str) -> Option<&Battery> {
         self.items
             .iter()
@@ -142,7 +219,7 @@ impl Batteries {
 }
 
 pub fn get_main_battery_name() -> Result<String, Error> {
-    let sys_dir = fs::read_dir(SYS_BATTERIES_PATH)?;
+    let sys_dir = read_batteries_dir()?;
     let battery_dirs = sys_dir
         .filter_map(|dir| {
             if let Ok(dir) = dir {
@@ -228,6 +305,15 @@ impl Display for BatteryStatus {
 }
 
 impl BatteryInfoName {
+    pub const ALL: &[BatteryInfoName] = &[
+        BatteryInfoName::ChargeFull,
+        BatteryInfoName::ChargeNow,
+        BatteryInfoName::Capacity,
+        BatteryInfoName::CurrentNow,
+        BatteryInfoName::Status,
+        BatteryInfoName::TimeRemaining,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             BatteryInfoName::ChargeFull => "charge_full",
@@ -238,23 +324,46 @@ impl BatteryInfoName {
             BatteryInfoName::TimeRemaining => "time_remaining",
         }
     }
+
+    /// Other names that are accepted for this info, e.g. `charge` for `charge_now`.
+    pub fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            BatteryInfoName::ChargeFull => &[],
+            BatteryInfoName::ChargeNow => &["charge"],
+            BatteryInfoName::Capacity => &["charge_percentage", "percentage", "percent"],
+            BatteryInfoName::CurrentNow => &["current"],
+            BatteryInfoName::Status => &[],
+            BatteryInfoName::TimeRemaining => &["remaining", "time"],
+        }
+    }
+
+    /// The unit that the info's value is in when read, or `None` if it isn't numeric.
+    pub fn unit(&self) -> Option<Unit> {
+        match self {
+            BatteryInfoName::ChargeFull | BatteryInfoName::ChargeNow => Some(Unit::MICRO_AMP_HOUR),
+            BatteryInfoName::Capacity => Some(Unit::FRACTION),
+            BatteryInfoName::CurrentNow => Some(Unit::MICRO_AMP),
+            BatteryInfoName::Status => None,
+            BatteryInfoName::TimeRemaining => Some(Unit::SECOND),
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.unit().is_some()
+    }
 }
 
 impl FromStr for BatteryInfoName {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "charge_now" | "charge" => Ok(Self::ChargeNow),
-            "capacity" | "charge_percentage" | "percentage" | "percent" => Ok(Self::Capacity),
-            "charge_full" => Ok(Self::ChargeFull),
-            "current_now" | "current" => Ok(Self::CurrentNow),
-            "time_remaining" | "remaining" | "time" => Ok(Self::TimeRemaining),
-            "status" => Ok(Self::Status),
-            _ => Err(Self::Err::InvalidInfoName {
+        BatteryInfoName::ALL
+            .iter()
+            .find(|info_name| info_name.as_str() == s || info_name.aliases().contains(&s))
+            .cloned()
+            .ok_or_else(|| Self::Err::InvalidInfoName {
                 name: s.to_string(),
-            }),
-        }
+            })
     }
 }
 
@@ -0,0 +1,98 @@
+//! Formatting points in time, e.g. for `--timestamp`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `time` in UTC as RFC 3339 with second precision, e.g. `2025-06-01T12:34:56Z`.
+///
+/// UTC is used rather than the local time zone, so that timestamps can be compared and sorted
+/// without knowing where they were recorded.
+pub fn to_rfc3339(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs_f64().ceil() as i64),
+    };
+    let days = seconds.div_euclid(86400);
+    let seconds_of_day = seconds.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+/// The proleptic Gregorian date of `days` since 1970-01-01.
+///
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(seconds: i64) -> SystemTime {
+        if seconds >= 0 {
+            UNIX_EPOCH + Duration::from_secs(seconds.unsigned_abs())
+        } else {
+            UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+        }
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(to_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+    }
+
+    #[test]
+    fn times_after_the_epoch() {
+        assert_eq!(to_rfc3339(at(1_700_000_000)), "2023-11-14T22:13:20Z");
+        // Leap day of a year divisible by 400
+        assert_eq!(to_rfc3339(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(to_rfc3339(at(951_868_799)), "2000-02-29T23:59:59Z");
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+        // Not a leap year, as it's divisible by 100 but not 400
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn times_before_the_epoch() {
+        assert_eq!(to_rfc3339(at(-1)), "1969-12-31T23:59:59Z");
+        assert_eq!(to_rfc3339(at(-86_400)), "1969-12-31T00:00:00Z");
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn sub_second_precision_is_dropped() {
+        assert_eq!(
+            to_rfc3339(at(1_700_000_000) + Duration::from_millis(999)),
+            "2023-11-14T22:13:20Z"
+        );
+        assert_eq!(
+            to_rfc3339(UNIX_EPOCH - Duration::from_millis(500)),
+            "1969-12-31T23:59:59Z"
+        );
+    }
+}
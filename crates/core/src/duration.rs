@@ -0,0 +1,323 @@
+//! Rendering durations, e.g. a battery's time remaining or a media player's position.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::{AsTimestamp, Error, Seconds};
+
+#[derive(Clone, Default, PartialEq, Eq)]
+pub enum DurationFormat {
+    /// `01:23:45`
+    #[default]
+    Clock,
+    /// `1h 23m`, with only the two largest units
+    Compact,
+    /// `1 hour 23 minutes`, with only the two largest units
+    Long,
+    /// `PT1H23M45S`
+    Iso8601,
+    /// A pattern like `%h:%M`, where:
+    ///
+    /// | Specifier    | Value                                              |
+    /// |--------------|----------------------------------------------------|
+    /// | `%d`         | Days                                               |
+    /// | `%H` / `%h`  | Hours, within the day if `%d` is used, else total  |
+    /// | `%M` / `%m`  | Minutes, within the hour                           |
+    /// | `%S` / `%s`  | Seconds, within the minute                         |
+    /// | `%%`         | A literal `%`                                      |
+    ///
+    /// Uppercase specifiers are zero-padded to two digits.
+    Custom(String),
+}
+
+/// A duration split into days, hours, minutes, and seconds.
+struct Components {
+    days: u64,
+    hours: u64,
+    minutes: u64,
+    seconds: u64,
+}
+
+impl Components {
+    fn new(total_seconds: Seconds) -> Self {
+        Self {
+            days: total_seconds / 86400,
+            hours: total_seconds / 3600 % 24,
+            minutes: total_seconds / 60 % 60,
+            seconds: total_seconds % 60,
+        }
+    }
+
+    /// The largest non-zero component and the one after it if it's non-zero, with their compact
+    /// and long unit names, e.g. 1h 23m but not 1h 5s for 1h 0m 5s.
+    fn largest_two(&self) -> Vec<(u64, &'static str, &'static str)> {
+        let all = [
+            (self.days, "d", "day"),
+            (self.hours, "h", "hour"),
+            (self.minutes, "m", "minute"),
+            (self.seconds, "s", "second"),
+        ];
+        let Some(largest) = all.iter().position(|(value, _, _)| *value != 0) else {
+            return Vec::new();
+        };
+        all.into_iter()
+            .skip(largest)
+            .take(2)
+            .filter(|(value, _, _)| *value != 0)
+            .collect()
+    }
+}
+
+impl DurationFormat {
+    pub fn format(&self, seconds: Seconds) -> String {
+        let components = Components::new(seconds);
+        match self {
+            DurationFormat::Clock => seconds.as_timestamp().to_string(),
+            DurationFormat::Compact => {
+                let parts = components
+                    .largest_two()
+                    .into_iter()
+                    .map(|(value, unit, _)| format!("{}{}", value, unit))
+                    .collect::<Vec<_>>();
+                if parts.is_empty() {
+                    "0s".to_string()
+                } else {
+                    parts.join(" ")
+                }
+            }
+            DurationFormat::Long => {
+                let parts = components
+                    .largest_two()
+                    .into_iter()
+                    .map(|(value, _, unit)| {
+                        format!("{} {}{}", value, unit, if value == 1 { "" } else { "s" })
+                    })
+                    .collect::<Vec<_>>();
+                if parts.is_empty() {
+                    "0 seconds".to_string()
+                } else {
+                    parts.join(" ")
+                }
+            }
+            DurationFormat::Iso8601 => {
+                let mut iso = String::from("P");
+                if components.days != 0 {
+                    iso.push_str(&format!("{}D", components.days));
+                }
+                iso.push('T');
+                for (value, designator) in [
+                    (components.hours, 'H'),
+                    (components.minutes, 'M'),
+                    (components.seconds, 'S'),
+                ] {
+                    if value != 0 {
+                        iso.push_str(&format!("{}{}", value, designator));
+                    }
+                }
+                match iso.as_str() {
+                    "PT" => "PT0S".to_string(),
+                    _ => iso.trim_end_matches('T').to_string(),
+                }
+            }
+            DurationFormat::Custom(pattern) => format_custom(pattern, seconds),
+        }
+    }
+}
+
+/// A part of a custom pattern.
+enum Token {
+    Literal(char),
+    /// The character after a `%`, or `None` if the pattern ends with it.
+    Directive(Option<char>),
+}
+
+fn tokens(pattern: &str) -> impl Iterator<Item = Token> + '_ {
+    let mut chars = pattern.chars();
+    std::iter::from_fn(move || {
+        Some(match chars.next()? {
+            '%' => Token::Directive(chars.next()),
+            c => Token::Literal(c),
+        })
+    })
+}
+
+fn format_custom(pattern: &str, seconds: Seconds) -> String {
+    let components = Components::new(seconds);
+    let has_days = tokens(pattern).any(|token| matches!(token, Token::Directive(Some('d'))));
+    let hours = if has_days {
+        components.hours
+    } else {
+        seconds / 3600
+    };
+
+    let mut output = String::new();
+    for token in tokens(pattern) {
+        match token {
+            Token::Literal(c) => output.push(c),
+            Token::Directive(Some('d')) => output.push_str(&components.days.to_string()),
+            Token::Directive(Some('H')) => output.push_str(&format!("{:0>2}", hours)),
+            Token::Directive(Some('h')) => output.push_str(&hours.to_string()),
+            Token::Directive(Some('M')) => output.push_str(&format!("{:0>2}", components.minutes)),
+            Token::Directive(Some('m')) => output.push_str(&components.minutes.to_string()),
+            Token::Directive(Some('S')) => output.push_str(&format!("{:0>2}", components.seconds)),
+            Token::Directive(Some('s')) => output.push_str(&components.seconds.to_string()),
+            Token::Directive(Some('%')) => output.push('%'),
+            // Validated when parsed
+            Token::Directive(_) => {}
+        }
+    }
+    output
+}
+
+impl FromStr for DurationFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clock" => return Ok(Self::Clock),
+            "compact" => return Ok(Self::Compact),
+            "long" => return Ok(Self::Long),
+            "iso8601" => return Ok(Self::Iso8601),
+            _ => {}
+        }
+
+        if !s.contains('%') {
+            return Err(Self::Err::InvalidArgument {
+                message: format!(
+                    "Invalid duration format \"{}\". Expected \"clock\", \"compact\", \"long\", \"iso8601\", or a pattern such as \"%h:%M\"",
+                    s
+                ),
+            });
+        }
+        for token in tokens(s) {
+            match token {
+                Token::Literal(_)
+                | Token::Directive(Some('d' | 'H' | 'h' | 'M' | 'm' | 'S' | 's' | '%')) => {}
+                Token::Directive(specifier) => {
+                    return Err(Self::Err::InvalidArgument {
+                        message: format!(
+                            "Invalid duration pattern \"{}\": unknown specifier \"%{}\". Expected one of %d, %H, %h, %M, %m, %S, %s, or %%",
+                            s,
+                            specifier.map(String::from).unwrap_or_default()
+                        ),
+                    });
+                }
+            }
+        }
+        Ok(Self::Custom(s.to_string()))
+    }
+}
+
+impl Display for DurationFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DurationFormat::Clock => write!(f, "clock"),
+            DurationFormat::Compact => write!(f, "compact"),
+            DurationFormat::Long => write!(f, "long"),
+            DurationFormat::Iso8601 => write!(f, "iso8601"),
+            DurationFormat::Custom(pattern) => write!(f, "{}", pattern),
+        }
+    }
+}
+
+impl Serialize for DurationFormat {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DurationFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        DurationFormat::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1h 23m 45s
+    const CLOCK: Seconds = 5025;
+    /// 1d 1h 1m 1s
+    const OVER_A_DAY: Seconds = 90061;
+
+    fn format(format: &str, seconds: Seconds) -> String {
+        DurationFormat::from_str(format).unwrap().format(seconds)
+    }
+
+    #[test]
+    fn clock() {
+        assert_eq!(format("clock", CLOCK), "01:23:45");
+        assert_eq!(format("clock", OVER_A_DAY), "25:01:01");
+        assert_eq!(format("clock", 0), "00:00:00");
+    }
+
+    #[test]
+    fn compact_and_long_keep_the_two_largest_units() {
+        assert_eq!(format("compact", CLOCK), "1h 23m");
+        assert_eq!(format("long", CLOCK), "1 hour 23 minutes");
+        assert_eq!(format("compact", OVER_A_DAY), "1d 1h");
+        assert_eq!(format("long", OVER_A_DAY), "1 day 1 hour");
+        // The second unit is left out rather than replaced by a smaller one
+        assert_eq!(format("compact", 3605), "1h");
+        assert_eq!(format("long", 61), "1 minute 1 second");
+        assert_eq!(format("compact", 0), "0s");
+        assert_eq!(format("long", 0), "0 seconds");
+    }
+
+    #[test]
+    fn iso8601() {
+        assert_eq!(format("iso8601", CLOCK), "PT1H23M45S");
+        assert_eq!(format("iso8601", OVER_A_DAY), "P1DT1H1M1S");
+        assert_eq!(format("iso8601", 86400), "P1D");
+        assert_eq!(format("iso8601", 60), "PT1M");
+        assert_eq!(format("iso8601", 0), "PT0S");
+    }
+
+    #[test]
+    fn custom_patterns() {
+        assert_eq!(format("%h:%M", CLOCK), "1:23");
+        assert_eq!(format("%H:%M:%S", 65), "00:01:05");
+        assert_eq!(format("%m min %s s", 65), "1 min 5 s");
+        // Hours are only within the day when days are rendered
+        assert_eq!(format("%h:%M", OVER_A_DAY), "25:01");
+        assert_eq!(format("%dd %H:%M:%S", OVER_A_DAY), "1d 01:01:01");
+        assert_eq!(format("100%% in %hh", 3600), "100% in 1h");
+        // `%%d` is a literal `%d`, so hours aren't within the day.
+        assert_eq!(format("%%d %hh", 90000), "%d 25h");
+        assert_eq!(format("%dd %hh", 90000), "1d 1h");
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        for s in ["clock", "compact", "long", "iso8601", "%h:%M", "%d %% %S"] {
+            let duration_format = DurationFormat::from_str(s).unwrap();
+            assert_eq!(duration_format.to_string(), s);
+            assert!(
+                DurationFormat::from_str(&duration_format.to_string()).unwrap() == duration_format
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_formats_are_rejected() {
+        for s in ["", "hours", "%h:%x", "%h:%", "%Y"] {
+            assert!(
+                matches!(
+                    DurationFormat::from_str(s),
+                    Err(Error::InvalidArgument { .. })
+                ),
+                "{:?} should be rejected",
+                s
+            );
+        }
+    }
+}
@@ -1,12 +1,46 @@
-use std::fmt::Display;
+//! # Exit codes
+//!
+//! Every [`Error`] maps to an exit code with [`Error::exit_code`], as do the errors of the
+//! `getinfo` binary itself:
+//!
+//! | Code | Meaning                                                                     |
+//! |------|-----------------------------------------------------------------------------|
+//! | 0    | Success                                                                     |
+//! | 1    | Any other error                                                             |
+//! | 2    | Invalid arguments or config file                                            |
+//! | 3    | Not found, e.g. there is no battery, or no battery with the given name      |
+//! | 4    | Permission denied, e.g. reading a file that the user has no permission to   |
+//! | 5    | Unsupported, e.g. the battery doesn't report the requested info             |
+
+use std::{fmt::Display, io, sync::Arc};
 
 use serde::Serialize;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+pub mod datetime;
+pub mod duration;
+pub mod scheduler;
+pub mod units;
+
+pub mod exit_code {
+    pub const FAILURE: u8 = 1;
+    pub const USAGE: u8 = 2;
+    pub const NOT_FOUND: u8 = 3;
+    pub const PERMISSION_DENIED: u8 = 4;
+    pub const UNSUPPORTED: u8 = 5;
+}
+
+/// Cloneable, so that one error can be shared by every subscriber of a watcher.
+#[derive(Error, Debug, Clone)]
 pub enum Error {
     #[error(transparent)]
-    Io(#[from] std::io::Error),
+    Io(Arc<io::Error>),
+
+    #[error("Failed to read {}: {}", .path, .source)]
+    ReadFile {
+        path: String,
+        source: Arc<io::Error>,
+    },
 
     #[error(transparent)]
     ParseInt(#[from] std::num::ParseIntError),
@@ -14,19 +48,79 @@ pub enum Error {
     #[error("No batteries found in {}", .path)]
     NoBatteriesFound { path: String },
 
+    #[error("Battery \"{}\" not found", .name)]
+    BatteryNotFound { name: String },
+
     #[error("Invalid info name \"{}\"", .name)]
     InvalidInfoName { name: String },
 
+    #[error("Battery \"{}\" does not provide {}", .name, .info)]
+    UnsupportedBatteryInfo { name: String, info: String },
+
     #[error("Invalid battery status \"{}\". Expected \"Charging\", \"Discharging\", \"Not Charging\", or \"Full\" ", .status)]
     InvalidBatteryStatus { status: String },
 
+    #[error("{}", .message)]
+    InvalidArgument { message: String },
+
     #[error("Invalid path: {}", .path)]
     InvalidPath { path: String },
+
+    #[error("No media players found")]
+    NoMediaPlayersFound,
+
+    #[error("No media player matching \"{}\" found", .pattern)]
+    MediaPlayerNotFound { pattern: String },
+
+    #[error("Media player \"{}\" does not support {}: {}", .player, .control, .reason)]
+    UnsupportedMediaPlayerControl {
+        player: String,
+        control: String,
+        reason: String,
+    },
+}
+
+impl Error {
+    /// See the [crate-level documentation](crate#exit-codes) for the list of exit codes.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Io(source) | Error::ReadFile { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => exit_code::NOT_FOUND,
+                io::ErrorKind::PermissionDenied => exit_code::PERMISSION_DENIED,
+                io::ErrorKind::Unsupported => exit_code::UNSUPPORTED,
+                _ => exit_code::FAILURE,
+            },
+            Error::NoBatteriesFound { .. }
+            | Error::BatteryNotFound { .. }
+            | Error::NoMediaPlayersFound
+            | Error::MediaPlayerNotFound { .. } => exit_code::NOT_FOUND,
+            Error::UnsupportedBatteryInfo { .. } | Error::UnsupportedMediaPlayerControl { .. } => {
+                exit_code::UNSUPPORTED
+            }
+            Error::InvalidInfoName { .. }
+            | Error::InvalidArgument { .. }
+            | Error::InvalidPath { .. } => exit_code::USAGE,
+            Error::ParseInt(_) | Error::InvalidBatteryStatus { .. } => exit_code::FAILURE,
+        }
+    }
+
+    pub fn read_file(path: impl AsRef<std::path::Path>, source: io::Error) -> Self {
+        Error::ReadFile {
+            path: path.as_ref().display().to_string(),
+            source: Arc::new(source),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Io(Arc::new(value))
+    }
 }
 
 pub type Seconds = u64;
 
-#[derive(Default, Serialize)]
+#[derive(Default, Serialize, Clone, PartialEq, Eq)]
 pub struct Timestamp {
     #[serde(rename = "h")]
     hours: u64,
@@ -0,0 +1,270 @@
+//! Drift-free scheduling for polling.
+//!
+//! Ticks are scheduled from when the scheduler started rather than from when the previous tick
+//! finished, so the time it takes to read and output info doesn't add up over time.
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{Instant, Interval, MissedTickBehavior, interval};
+
+use crate::Error;
+
+/// Wall-clock boundary that ticks are aligned to.
+///
+/// The polling interval should evenly divide the boundary (e.g. 1000ms for seconds, or 15000ms for
+/// minutes), so that a tick lands on every boundary.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Alignment {
+    #[default]
+    None,
+    Second,
+    Minute,
+    Hour,
+}
+
+/// What to do when ticks were missed, because reading or outputting info took longer than the
+/// interval (e.g. when stdout is blocked because nothing is reading from it).
+///
+/// See [`MissedTickBehavior`] for details.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedTick {
+    /// Tick as fast as possible until caught up
+    Burst,
+    /// Tick immediately, then every interval from then on
+    Delay,
+    /// Tick at the next multiple of the interval, skipping missed ticks
+    #[default]
+    Skip,
+}
+
+pub struct Scheduler {
+    interval: Interval,
+    period: Duration,
+    alignment: Alignment,
+    is_first: bool,
+}
+
+impl Scheduler {
+    /// The first tick completes immediately. If `alignment` is set, the following ticks are
+    /// aligned to it.
+    ///
+    /// Fails if `period` is zero.
+    pub fn new(
+        period: Duration,
+        alignment: Alignment,
+        missed_tick: MissedTick,
+    ) -> Result<Self, Error> {
+        if period.is_zero() {
+            return Err(Error::InvalidArgument {
+                message: "Polling interval must be greater than 0".to_string(),
+            });
+        }
+
+        let mut interval = interval(period);
+        interval.set_missed_tick_behavior(missed_tick.into());
+        Ok(Self {
+            interval,
+            period,
+            alignment,
+            is_first: true,
+        })
+    }
+
+    pub async fn tick(&mut self) {
+        self.interval.tick().await;
+
+        if let Some(boundary) = self.alignment.as_duration() {
+            // Intervals run on a monotonic clock, which doesn't follow changes to the wall clock
+            // and stops while the system is suspended, so every tick is re-aligned to the wall
+            // clock. After the first tick, look past the boundary that was just ticked on, as the
+            // wall clock may not have reached it yet.
+            let now = SystemTime::now();
+            let earliest = if self.is_first {
+                now
+            } else {
+                now + self.period / 2
+            };
+            let next_tick = next_aligned(earliest, boundary, self.period);
+            let delay = next_tick.duration_since(now).unwrap_or_default();
+            self.interval.reset_at(Instant::now() + delay);
+        }
+        self.is_first = false;
+    }
+}
+
+/// The first time at or after `earliest` that is a multiple of `period` since the start of the
+/// `boundary` that `earliest` is in.
+fn next_aligned(earliest: SystemTime, boundary: Duration, period: Duration) -> SystemTime {
+    let since_epoch = earliest
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is after the Unix epoch")
+        .as_nanos();
+    let boundary = boundary.as_nanos();
+    let period = period.as_nanos();
+
+    let boundary_start = since_epoch - since_epoch % boundary;
+    let periods = (since_epoch - boundary_start).div_ceil(period);
+    let next = boundary_start + periods * period;
+
+    UNIX_EPOCH + Duration::from_nanos(next.try_into().expect("fits until the year 2554"))
+}
+
+impl Alignment {
+    pub fn as_duration(&self) -> Option<Duration> {
+        match self {
+            Alignment::None => None,
+            Alignment::Second => Some(Duration::from_secs(1)),
+            Alignment::Minute => Some(Duration::from_secs(60)),
+            Alignment::Hour => Some(Duration::from_secs(3600)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Alignment::None => "none",
+            Alignment::Second => "second",
+            Alignment::Minute => "minute",
+            Alignment::Hour => "hour",
+        }
+    }
+}
+
+impl FromStr for Alignment {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "second" => Ok(Self::Second),
+            "minute" => Ok(Self::Minute),
+            "hour" => Ok(Self::Hour),
+            _ => Err(Self::Err::InvalidArgument {
+                message: format!(
+                    "Invalid alignment \"{}\". Expected \"none\", \"second\", \"minute\", or \"hour\"",
+                    s
+                ),
+            }),
+        }
+    }
+}
+
+impl Display for Alignment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl MissedTick {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MissedTick::Burst => "burst",
+            MissedTick::Delay => "delay",
+            MissedTick::Skip => "skip",
+        }
+    }
+}
+
+impl FromStr for MissedTick {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "burst" => Ok(Self::Burst),
+            "delay" => Ok(Self::Delay),
+            "skip" => Ok(Self::Skip),
+            _ => Err(Self::Err::InvalidArgument {
+                message: format!(
+                    "Invalid missed tick behavior \"{}\". Expected \"burst\", \"delay\", or \"skip\"",
+                    s
+                ),
+            }),
+        }
+    }
+}
+
+impl Display for MissedTick {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl From<MissedTick> for MissedTickBehavior {
+    fn from(value: MissedTick) -> Self {
+        match value {
+            MissedTick::Burst => MissedTickBehavior::Burst,
+            MissedTick::Delay => MissedTickBehavior::Delay,
+            MissedTick::Skip => MissedTickBehavior::Skip,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(milliseconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(milliseconds)
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn aligned_time_is_kept() {
+        assert_eq!(next_aligned(at(5_000), SECOND, SECOND), at(5_000));
+        assert_eq!(
+            next_aligned(at(120_000), MINUTE, Duration::from_secs(15)),
+            at(120_000)
+        );
+        assert_eq!(
+            next_aligned(at(5_250), SECOND, Duration::from_millis(250)),
+            at(5_250)
+        );
+    }
+
+    #[test]
+    fn unaligned_time_is_rounded_up_to_the_next_period() {
+        assert_eq!(next_aligned(at(5_001), SECOND, SECOND), at(6_000));
+        assert_eq!(
+            next_aligned(at(5_001), SECOND, Duration::from_millis(250)),
+            at(5_250)
+        );
+        assert_eq!(
+            next_aligned(at(121_000), MINUTE, Duration::from_secs(15)),
+            at(135_000)
+        );
+        assert_eq!(
+            next_aligned(at(179_999), MINUTE, Duration::from_secs(15)),
+            at(180_000)
+        );
+    }
+
+    #[test]
+    fn missed_ticks_are_skipped() {
+        // A tick that was meant for 5s but only ran at 7.3s goes to the next aligned time,
+        // rather than to any of the ticks that were missed in between.
+        assert_eq!(
+            next_aligned(at(7_300), SECOND, Duration::from_millis(500)),
+            at(7_500)
+        );
+        assert_eq!(
+            next_aligned(at(7_300 + 250), SECOND, Duration::from_millis(500)),
+            at(8_000)
+        );
+    }
+
+    #[test]
+    fn zero_period_is_rejected() {
+        assert!(matches!(
+            Scheduler::new(Duration::ZERO, Alignment::Second, MissedTick::Skip),
+            Err(Error::InvalidArgument { .. })
+        ));
+    }
+}
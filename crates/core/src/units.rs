@@ -0,0 +1,632 @@
+//! Units of info values, and converting between them.
+//!
+//! A [`Quantity`] keeps its value in the unit it was read in (e.g. µAh from sysfs), and is only
+//! converted when rendered, so that `--format-output raw` can output the value as is.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::Error;
+
+/// What a unit measures. Only units of the same dimension can be converted between each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dimension {
+    Charge,
+    Current,
+    Energy,
+    Power,
+    Voltage,
+    Temperature,
+    Information,
+    Ratio,
+    Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BaseUnit {
+    AmpHour,
+    Amp,
+    WattHour,
+    Watt,
+    Volt,
+    Celsius,
+    Byte,
+    /// A ratio where 1 is the whole, e.g. a capacity of 0.83
+    Fraction,
+    Percent,
+    Second,
+    Minute,
+    Hour,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Prefix {
+    Micro,
+    Milli,
+    #[default]
+    None,
+    Kilo,
+    Mega,
+    Giga,
+    Tera,
+    Kibi,
+    Mebi,
+    Gibi,
+    Tebi,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Unit {
+    pub prefix: Prefix,
+    pub base: BaseUnit,
+}
+
+/// How to pick a prefix for units that weren't explicitly selected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scale {
+    /// Powers of 1000 (e.g. mAh, kB)
+    Si,
+    /// Powers of 1000, except for bytes which use powers of 1024 (e.g. KiB)
+    Iec,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+/// The units to render quantities in, e.g. `si,mAh,W`, selected with `--units`.
+///
+/// Dimensions without an explicitly selected unit are scaled by [`Scale`] if set, and otherwise
+/// use [`Dimension::default_unit`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Units {
+    pub scale: Option<Scale>,
+    pub units: Vec<Unit>,
+}
+
+const SI_PREFIXES: [Prefix; 7] = [
+    Prefix::Micro,
+    Prefix::Milli,
+    Prefix::None,
+    Prefix::Kilo,
+    Prefix::Mega,
+    Prefix::Giga,
+    Prefix::Tera,
+];
+const SI_BYTE_PREFIXES: [Prefix; 5] = [
+    Prefix::None,
+    Prefix::Kilo,
+    Prefix::Mega,
+    Prefix::Giga,
+    Prefix::Tera,
+];
+const IEC_BYTE_PREFIXES: [Prefix; 5] = [
+    Prefix::None,
+    Prefix::Kibi,
+    Prefix::Mebi,
+    Prefix::Gibi,
+    Prefix::Tebi,
+];
+
+impl Dimension {
+    /// The unit used when neither `--units` nor a [`Scale`] selects one, or `None` for durations,
+    /// which are rendered as a clock (e.g. `01:23:45`) by default.
+    pub fn default_unit(&self) -> Option<Unit> {
+        match self {
+            Dimension::Charge => Some(Unit::new(Prefix::Milli, BaseUnit::AmpHour)),
+            Dimension::Current => Some(Unit::new(Prefix::Milli, BaseUnit::Amp)),
+            Dimension::Energy => Some(Unit::new(Prefix::None, BaseUnit::WattHour)),
+            Dimension::Power => Some(Unit::new(Prefix::None, BaseUnit::Watt)),
+            Dimension::Voltage => Some(Unit::new(Prefix::None, BaseUnit::Volt)),
+            Dimension::Temperature => Some(Unit::new(Prefix::None, BaseUnit::Celsius)),
+            Dimension::Information => Some(Unit::new(Prefix::None, BaseUnit::Byte)),
+            Dimension::Ratio => Some(Unit::new(Prefix::None, BaseUnit::Percent)),
+            Dimension::Duration => None,
+        }
+    }
+}
+
+impl Dimension {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dimension::Charge => "charge",
+            Dimension::Current => "current",
+            Dimension::Energy => "energy",
+            Dimension::Power => "power",
+            Dimension::Voltage => "voltage",
+            Dimension::Temperature => "temperature",
+            Dimension::Information => "information",
+            Dimension::Ratio => "ratio",
+            Dimension::Duration => "duration",
+        }
+    }
+}
+
+impl Display for Dimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl BaseUnit {
+    /// Longest symbols first, so that e.g. "mAh" is parsed as milli-"Ah" rather than as "h".
+    const ALL: [BaseUnit; 12] = [
+        BaseUnit::Minute,
+        BaseUnit::AmpHour,
+        BaseUnit::WattHour,
+        BaseUnit::Celsius,
+        BaseUnit::Amp,
+        BaseUnit::Watt,
+        BaseUnit::Volt,
+        BaseUnit::Byte,
+        BaseUnit::Percent,
+        BaseUnit::Second,
+        BaseUnit::Hour,
+        BaseUnit::Fraction,
+    ];
+
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            BaseUnit::AmpHour => Dimension::Charge,
+            BaseUnit::Amp => Dimension::Current,
+            BaseUnit::WattHour => Dimension::Energy,
+            BaseUnit::Watt => Dimension::Power,
+            BaseUnit::Volt => Dimension::Voltage,
+            BaseUnit::Celsius => Dimension::Temperature,
+            BaseUnit::Byte => Dimension::Information,
+            BaseUnit::Fraction | BaseUnit::Percent => Dimension::Ratio,
+            BaseUnit::Second | BaseUnit::Minute | BaseUnit::Hour => Dimension::Duration,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BaseUnit::AmpHour => "Ah",
+            BaseUnit::Amp => "A",
+            BaseUnit::WattHour => "Wh",
+            BaseUnit::Watt => "W",
+            BaseUnit::Volt => "V",
+            BaseUnit::Celsius => "°C",
+            BaseUnit::Byte => "B",
+            BaseUnit::Fraction => "",
+            BaseUnit::Percent => "%",
+            BaseUnit::Second => "s",
+            BaseUnit::Minute => "min",
+            BaseUnit::Hour => "h",
+        }
+    }
+
+    /// Multiplier to this unit's dimension's reference unit, as a numerator and denominator so
+    /// that conversions between integer values stay exact.
+    fn factor(&self) -> (f64, f64) {
+        match self {
+            BaseUnit::Percent => (1.0, 100.0),
+            BaseUnit::Minute => (60.0, 1.0),
+            BaseUnit::Hour => (3600.0, 1.0),
+            _ => (1.0, 1.0),
+        }
+    }
+
+    fn allows_prefix(&self, prefix: Prefix) -> bool {
+        match self {
+            BaseUnit::AmpHour
+            | BaseUnit::Amp
+            | BaseUnit::WattHour
+            | BaseUnit::Watt
+            | BaseUnit::Volt => SI_PREFIXES.contains(&prefix),
+            BaseUnit::Byte => {
+                SI_BYTE_PREFIXES.contains(&prefix) || IEC_BYTE_PREFIXES.contains(&prefix)
+            }
+            BaseUnit::Second => matches!(prefix, Prefix::Micro | Prefix::Milli | Prefix::None),
+            BaseUnit::Celsius
+            | BaseUnit::Fraction
+            | BaseUnit::Percent
+            | BaseUnit::Minute
+            | BaseUnit::Hour => prefix == Prefix::None,
+        }
+    }
+}
+
+impl Prefix {
+    const ALL: [Prefix; 11] = [
+        Prefix::Micro,
+        Prefix::Milli,
+        Prefix::None,
+        Prefix::Kilo,
+        Prefix::Mega,
+        Prefix::Giga,
+        Prefix::Tera,
+        Prefix::Kibi,
+        Prefix::Mebi,
+        Prefix::Gibi,
+        Prefix::Tebi,
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Prefix::Micro => "µ",
+            Prefix::Milli => "m",
+            Prefix::None => "",
+            Prefix::Kilo => "k",
+            Prefix::Mega => "M",
+            Prefix::Giga => "G",
+            Prefix::Tera => "T",
+            Prefix::Kibi => "Ki",
+            Prefix::Mebi => "Mi",
+            Prefix::Gibi => "Gi",
+            Prefix::Tebi => "Ti",
+        }
+    }
+
+    /// See [`BaseUnit::factor`].
+    fn factor(&self) -> (f64, f64) {
+        match self {
+            Prefix::Micro => (1.0, 1e6),
+            Prefix::Milli => (1.0, 1e3),
+            Prefix::None => (1.0, 1.0),
+            Prefix::Kilo => (1e3, 1.0),
+            Prefix::Mega => (1e6, 1.0),
+            Prefix::Giga => (1e9, 1.0),
+            Prefix::Tera => (1e12, 1.0),
+            Prefix::Kibi => (1024.0, 1.0),
+            Prefix::Mebi => (1024.0 * 1024.0, 1.0),
+            Prefix::Gibi => (1024.0 * 1024.0 * 1024.0, 1.0),
+            Prefix::Tebi => (1024.0 * 1024.0 * 1024.0 * 1024.0, 1.0),
+        }
+    }
+}
+
+impl Unit {
+    pub const MICRO_AMP_HOUR: Unit = Unit::new(Prefix::Micro, BaseUnit::AmpHour);
+    pub const MICRO_AMP: Unit = Unit::new(Prefix::Micro, BaseUnit::Amp);
+    pub const MICRO_WATT_HOUR: Unit = Unit::new(Prefix::Micro, BaseUnit::WattHour);
+    pub const MICRO_WATT: Unit = Unit::new(Prefix::Micro, BaseUnit::Watt);
+    pub const MICRO_VOLT: Unit = Unit::new(Prefix::Micro, BaseUnit::Volt);
+    pub const CELSIUS: Unit = Unit::new(Prefix::None, BaseUnit::Celsius);
+    pub const BYTE: Unit = Unit::new(Prefix::None, BaseUnit::Byte);
+    pub const FRACTION: Unit = Unit::new(Prefix::None, BaseUnit::Fraction);
+    pub const PERCENT: Unit = Unit::new(Prefix::None, BaseUnit::Percent);
+    pub const SECOND: Unit = Unit::new(Prefix::None, BaseUnit::Second);
+    pub const MICRO_SECOND: Unit = Unit::new(Prefix::Micro, BaseUnit::Second);
+
+    pub const fn new(prefix: Prefix, base: BaseUnit) -> Self {
+        Self { prefix, base }
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.base.dimension()
+    }
+
+    fn factor(&self) -> (f64, f64) {
+        let (prefix_numerator, prefix_denominator) = self.prefix.factor();
+        let (base_numerator, base_denominator) = self.base.factor();
+        (
+            prefix_numerator * base_numerator,
+            prefix_denominator * base_denominator,
+        )
+    }
+}
+
+impl Quantity {
+    pub fn new(value: impl Into<f64>, unit: Unit) -> Self {
+        Self {
+            value: value.into(),
+            unit,
+        }
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.unit.dimension()
+    }
+
+    /// The value in `unit`, which must be of the same dimension.
+    pub fn value_in(&self, unit: Unit) -> f64 {
+        debug_assert_eq!(self.dimension(), unit.dimension());
+        if unit == self.unit {
+            return self.value;
+        }
+        let (from_numerator, from_denominator) = self.unit.factor();
+        let (to_numerator, to_denominator) = unit.factor();
+        // Multiplying first keeps e.g. 3000000µAh -> 3000mAh exact
+        self.value * (from_numerator * to_denominator) / (from_denominator * to_numerator)
+    }
+
+    /// The unit with the largest prefix that keeps the value at or above 1 (e.g. 1.5Ah rather
+    /// than 1500mAh), or `None` if the dimension doesn't use prefixes.
+    pub fn scaled_unit(&self, scale: Scale) -> Option<Unit> {
+        let base = match self.dimension() {
+            Dimension::Charge => BaseUnit::AmpHour,
+            Dimension::Current => BaseUnit::Amp,
+            Dimension::Energy => BaseUnit::WattHour,
+            Dimension::Power => BaseUnit::Watt,
+            Dimension::Voltage => BaseUnit::Volt,
+            Dimension::Information => BaseUnit::Byte,
+            Dimension::Temperature | Dimension::Ratio | Dimension::Duration => return None,
+        };
+        let prefixes: &[Prefix] = match (base, scale) {
+            (BaseUnit::Byte, Scale::Si) => &SI_BYTE_PREFIXES,
+            (BaseUnit::Byte, Scale::Iec) => &IEC_BYTE_PREFIXES,
+            _ => &SI_PREFIXES,
+        };
+
+        let value = self.value_in(Unit::new(Prefix::None, base)).abs();
+        if value == 0.0 {
+            return Some(Unit::new(Prefix::None, base));
+        }
+        let prefix = prefixes
+            .iter()
+            .rev()
+            .find(|prefix| value >= prefix.factor().0 / prefix.factor().1)
+            .unwrap_or(&prefixes[0]);
+        Some(Unit::new(*prefix, base))
+    }
+}
+
+impl Units {
+    /// The unit to render `quantity` in, or `None` if it's a duration that should be rendered as
+    /// a clock.
+    pub fn unit_for(&self, quantity: &Quantity) -> Option<Unit> {
+        let dimension = quantity.dimension();
+        if let Some(unit) = self.units.iter().find(|unit| unit.dimension() == dimension) {
+            return Some(*unit);
+        }
+        self.scale
+            .and_then(|scale| quantity.scaled_unit(scale))
+            .or_else(|| dimension.default_unit())
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.prefix.symbol(), self.base.symbol())
+    }
+}
+
+impl FromStr for Unit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidArgument {
+            message: format!(
+                "Invalid unit \"{}\". Expected a unit such as \"mAh\", \"W\", \"°C\", \"KiB\", \"%\", or \"min\"",
+                s
+            ),
+        };
+        if s.is_empty() {
+            return Err(invalid());
+        }
+        let normalized = s.replace('u', "µ");
+
+        for base in BaseUnit::ALL {
+            // Also accept "C" for °C, which is easier to type
+            let prefix = normalized.strip_suffix(base.symbol()).or_else(|| {
+                (base == BaseUnit::Celsius)
+                    .then(|| normalized.strip_suffix('C'))
+                    .flatten()
+            });
+            let Some(prefix) = prefix else {
+                continue;
+            };
+            let prefix = Prefix::ALL
+                .into_iter()
+                .find(|candidate| candidate.symbol() == prefix)
+                .filter(|prefix| base.allows_prefix(*prefix))
+                .ok_or_else(invalid)?;
+            return Ok(Unit::new(prefix, base));
+        }
+        Err(invalid())
+    }
+}
+
+impl Display for Scale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scale::Si => write!(f, "si"),
+            Scale::Iec => write!(f, "iec"),
+        }
+    }
+}
+
+impl Display for Units {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let items = self
+            .scale
+            .iter()
+            .map(Scale::to_string)
+            .chain(self.units.iter().map(Unit::to_string))
+            .collect::<Vec<_>>();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+impl FromStr for Units {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut units = Units::default();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let scale = match item {
+                "si" => Some(Scale::Si),
+                "iec" => Some(Scale::Iec),
+                _ => None,
+            };
+            if let Some(scale) = scale {
+                if units.scale.replace(scale).is_some() {
+                    return Err(Error::InvalidArgument {
+                        message: "Only one of \"si\" or \"iec\" can be selected".to_string(),
+                    });
+                }
+                continue;
+            }
+
+            let unit = Unit::from_str(item)?;
+            if units
+                .units
+                .iter()
+                .any(|selected| selected.dimension() == unit.dimension())
+            {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "Unit \"{}\" measures the same thing as another selected unit",
+                        item
+                    ),
+                });
+            }
+            units.units.push(unit);
+        }
+        Ok(units)
+    }
+}
+
+impl Serialize for Units {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Units {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Units::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(s: &str) -> Unit {
+        Unit::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn units_are_parsed() {
+        assert_eq!(unit("mAh"), Unit::new(Prefix::Milli, BaseUnit::AmpHour));
+        assert_eq!(unit("Ah"), Unit::new(Prefix::None, BaseUnit::AmpHour));
+        assert_eq!(unit("uA"), Unit::MICRO_AMP);
+        assert_eq!(unit("µWh"), Unit::MICRO_WATT_HOUR);
+        assert_eq!(unit("°C"), Unit::CELSIUS);
+        assert_eq!(unit("C"), Unit::CELSIUS);
+        assert_eq!(unit("kB"), Unit::new(Prefix::Kilo, BaseUnit::Byte));
+        assert_eq!(unit("KiB"), Unit::new(Prefix::Kibi, BaseUnit::Byte));
+        assert_eq!(unit("%"), Unit::PERCENT);
+        assert_eq!(unit("min"), Unit::new(Prefix::None, BaseUnit::Minute));
+        assert_eq!(unit("ms"), Unit::new(Prefix::Milli, BaseUnit::Second));
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        for s in [
+            "mAh", "µA", "kW", "MWh", "V", "°C", "GiB", "TB", "%", "s", "min", "h",
+        ] {
+            assert_eq!(unit(s).to_string(), s);
+            assert_eq!(unit(&unit(s).to_string()), unit(s));
+        }
+    }
+
+    #[test]
+    fn invalid_units_are_rejected() {
+        for s in ["", "x", "mAhh", "KiAh", "kmin", "m%", "MiW", "ks"] {
+            assert!(
+                matches!(Unit::from_str(s), Err(Error::InvalidArgument { .. })),
+                "{:?} should be rejected",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let charge = Quantity::new(3_000_000, Unit::MICRO_AMP_HOUR);
+        assert_eq!(charge.value_in(unit("mAh")), 3000.0);
+        assert_eq!(charge.value_in(unit("Ah")), 3.0);
+        assert_eq!(charge.value_in(Unit::MICRO_AMP_HOUR), 3_000_000.0);
+
+        assert_eq!(
+            Quantity::new(0.83, Unit::FRACTION).value_in(Unit::PERCENT),
+            83.0
+        );
+        assert_eq!(
+            Quantity::new(50, Unit::PERCENT).value_in(Unit::FRACTION),
+            0.5
+        );
+        assert_eq!(Quantity::new(5400, Unit::SECOND).value_in(unit("h")), 1.5);
+        assert_eq!(Quantity::new(90, unit("min")).value_in(unit("h")), 1.5);
+        assert_eq!(Quantity::new(1536, Unit::BYTE).value_in(unit("KiB")), 1.5);
+        assert_eq!(Quantity::new(1536, Unit::BYTE).value_in(unit("kB")), 1.536);
+    }
+
+    #[test]
+    fn scaled_units_keep_the_value_at_or_above_one() {
+        let scaled = |value, unit, scale| Quantity::new(value, unit).scaled_unit(scale).unwrap();
+
+        assert_eq!(
+            scaled(1_500_000, Unit::MICRO_AMP_HOUR, Scale::Si),
+            unit("Ah")
+        );
+        assert_eq!(scaled(999, Unit::MICRO_AMP, Scale::Si), unit("µA"));
+        assert_eq!(scaled(-2_500_000, Unit::MICRO_WATT, Scale::Si), unit("W"));
+        assert_eq!(scaled(0, Unit::MICRO_VOLT, Scale::Si), unit("V"));
+        assert_eq!(scaled(2048, Unit::BYTE, Scale::Iec), unit("KiB"));
+        assert_eq!(scaled(2048, Unit::BYTE, Scale::Si), unit("kB"));
+        assert_eq!(scaled(512, Unit::BYTE, Scale::Iec), unit("B"));
+        assert_eq!(
+            Quantity::new(0.5, Unit::FRACTION).scaled_unit(Scale::Si),
+            None
+        );
+    }
+
+    #[test]
+    fn unit_selection() {
+        let charge = Quantity::new(1_500_000, Unit::MICRO_AMP_HOUR);
+        let duration = Quantity::new(60, Unit::SECOND);
+
+        let units = Units::from_str("si,mAh").unwrap();
+        assert_eq!(units.unit_for(&charge), Some(unit("mAh")));
+        assert_eq!(
+            units.unit_for(&Quantity::new(2_000_000, Unit::MICRO_WATT)),
+            Some(unit("W"))
+        );
+
+        let units = Units::default();
+        assert_eq!(units.unit_for(&charge), Some(unit("mAh")));
+        assert_eq!(
+            units.unit_for(&Quantity::new(0.5, Unit::FRACTION)),
+            Some(Unit::PERCENT)
+        );
+        assert_eq!(units.unit_for(&duration), None);
+        assert_eq!(
+            Units::from_str("min").unwrap().unit_for(&duration),
+            Some(unit("min"))
+        );
+    }
+
+    #[test]
+    fn units_parse_round_trips() {
+        let units = Units::from_str(" si, mAh ,W,").unwrap();
+        assert_eq!(units.scale, Some(Scale::Si));
+        assert_eq!(units.units, vec![unit("mAh"), unit("W")]);
+        assert_eq!(units.to_string(), "si,mAh,W");
+        assert_eq!(Units::from_str(&units.to_string()).unwrap(), units);
+    }
+
+    #[test]
+    fn invalid_unit_selections_are_rejected() {
+        for s in ["si,iec", "si,si", "mAh,Ah", "s,min", "mAh,x"] {
+            assert!(
+                matches!(Units::from_str(s), Err(Error::InvalidArgument { .. })),
+                "{:?} should be rejected",
+                s
+            );
+        }
+    }
+}
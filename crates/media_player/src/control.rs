@@ -0,0 +1,393 @@
+//! Controlling a player with the methods and writable properties of
+//! `org.mpris.MediaPlayer2.Player`.
+//!
+//! Players are expected to ignore controls they don't support, so the capabilities they report
+//! (`CanControl`, `CanSeek`, etc.) are checked first with [`Control::check`], to fail with an
+//! error instead. It returns the [`Request`] that sends the control.
+
+use gi_core::Error;
+use zbus::{
+    Connection,
+    zvariant::{ObjectPath, OwnedObjectPath, Value},
+};
+
+use crate::{
+    MPRIS_OBJECT_PATH, PLAYER_INTERFACE, PROPERTIES_INTERFACE, media::properties::LoopStatus,
+    registry::Player, selection::player_name,
+};
+
+/// The `mpris:trackid` of players that have no current track.
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Control {
+    Play,
+    Pause,
+    PlayPause,
+    Next,
+    Previous,
+    Stop,
+    /// Seeks forwards, or backwards if negative, by this many microseconds.
+    Seek(i64),
+    /// Seeks to this many microseconds into the current track.
+    SetPosition(i64),
+    /// Sets the volume, where 1.0 is the maximum sensible volume.
+    SetVolume(f64),
+    /// Changes the volume by this much, within 0.0 to 1.0.
+    AdjustVolume(f64),
+    SetShuffle(bool),
+    ToggleShuffle,
+    SetLoop(LoopStatus),
+}
+
+/// The call to the player that sends a [`Control`].
+#[derive(PartialEq, Debug)]
+pub enum Request {
+    /// A method of `org.mpris.MediaPlayer2.Player` without arguments, e.g. `Play`.
+    Method(&'static str),
+    Seek(i64),
+    SetPosition {
+        trackid: OwnedObjectPath,
+        position: i64,
+    },
+    /// Sets a property of `org.mpris.MediaPlayer2.Player`.
+    SetProperty(&'static str, Value<'static>),
+}
+
+impl Control {
+    /// The name of the control, as in `getinfo media <name>`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Control::Play => "play",
+            Control::Pause => "pause",
+            Control::PlayPause => "play-pause",
+            Control::Next => "next",
+            Control::Previous => "previous",
+            Control::Stop => "stop",
+            Control::Seek(_) => "seek",
+            Control::SetPosition(_) => "set-position",
+            Control::SetVolume(_) | Control::AdjustVolume(_) => "volume",
+            Control::SetShuffle(_) | Control::ToggleShuffle => "shuffle",
+            Control::SetLoop(_) => "loop",
+        }
+    }
+
+    /// The capability property that must be true for `player` to support the control, other than
+    /// `CanControl`, and whether it is.
+    fn capability(&self, player: &Player) -> Option<(&'static str, bool)> {
+        let properties = &player.properties;
+        match self {
+            Control::Play => Some(("CanPlay", properties.can_play)),
+            Control::Pause | Control::PlayPause => Some(("CanPause", properties.can_pause)),
+            Control::Next => Some(("CanGoNext", properties.can_go_next)),
+            Control::Previous => Some(("CanGoPrevious", properties.can_go_previous)),
+            Control::Seek(_) | Control::SetPosition(_) => Some(("CanSeek", properties.can_seek)),
+            _ => None,
+        }
+    }
+
+    /// Checks that `player` supports the control, and returns the request that sends it.
+    pub fn check(&self, player: &Player) -> Result<Request, Error> {
+        let properties = &player.properties;
+        if !properties.can_control {
+            return Err(self.unsupported(player, "CanControl is false"));
+        }
+        if let Some((capability, false)) = self.capability(player) {
+            return Err(self.unsupported(player, &format!("{capability} is false")));
+        }
+
+        Ok(match *self {
+            Control::Play => Request::Method("Play"),
+            Control::Pause => Request::Method("Pause"),
+            Control::PlayPause => Request::Method("PlayPause"),
+            Control::Next => Request::Method("Next"),
+            Control::Previous => Request::Method("Previous"),
+            Control::Stop => Request::Method("Stop"),
+            Control::Seek(offset) => Request::Seek(offset),
+            Control::SetPosition(position) => {
+                let metadata = &properties.metadata;
+                let trackid = match ObjectPath::try_from(metadata.trackid()) {
+                    Ok(trackid) if trackid.as_str() != NO_TRACK => trackid,
+                    _ => return Err(self.unsupported(player, "there is no current track")),
+                };
+                if let Some(length) = metadata.length()
+                    && !(0..=length).contains(&position)
+                {
+                    return Err(Error::InvalidArgument {
+                        message: format!(
+                            "Position must be within the track's length of {} seconds",
+                            length / 1_000_000
+                        ),
+                    });
+                }
+                Request::SetPosition {
+                    trackid: trackid.into(),
+                    position,
+                }
+            }
+            Control::SetVolume(volume) => {
+                self.require(player, "Volume", properties.volume)?;
+                Request::SetProperty("Volume", Value::from(volume.max(0.0)))
+            }
+            Control::AdjustVolume(change) => {
+                let volume = self.require(player, "Volume", properties.volume)?;
+                let volume = (volume + change).clamp(0.0, 1.0);
+                Request::SetProperty("Volume", Value::from(volume))
+            }
+            Control::SetShuffle(shuffle) => {
+                self.require(player, "Shuffle", properties.shuffle)?;
+                Request::SetProperty("Shuffle", Value::from(shuffle))
+            }
+            Control::ToggleShuffle => {
+                let shuffle = self.require(player, "Shuffle", properties.shuffle)?;
+                Request::SetProperty("Shuffle", Value::from(!shuffle))
+            }
+            Control::SetLoop(loop_status) => {
+                self.require(player, "LoopStatus", properties.loop_status)?;
+                Request::SetProperty("LoopStatus", Value::from(loop_status.as_str()))
+            }
+        })
+    }
+
+    /// The value of an optional property that the control needs.
+    fn require<T>(&self, player: &Player, property: &str, value: Option<T>) -> Result<T, Error> {
+        value.ok_or_else(|| self.unsupported(player, &format!("it has no {property} property")))
+    }
+
+    fn unsupported(&self, player: &Player, reason: &str) -> Error {
+        Error::UnsupportedMediaPlayerControl {
+            player: player_name(player).to_string(),
+            control: self.as_str().to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Request {
+    /// Sends the request to `player`.
+    pub async fn send(&self, connection: &Connection, player: &Player) -> zbus::Result<()> {
+        match self {
+            Request::Method(method) => call_method(connection, player, method, &()).await,
+            Request::Seek(offset) => call_method(connection, player, "Seek", offset).await,
+            Request::SetPosition { trackid, position } => {
+                call_method(connection, player, "SetPosition", &(trackid, position)).await
+            }
+            Request::SetProperty(property, value) => {
+                set_property(connection, player, property, value).await
+            }
+        }
+    }
+
+    /// Like [`send`](Self::send), but blocks until the player replies, so it shouldn't be called
+    /// from async code.
+    pub fn blocking_send(
+        &self,
+        connection: &zbus::blocking::Connection,
+        player: &Player,
+    ) -> zbus::Result<()> {
+        zbus::block_on(self.send(connection.inner(), player))
+    }
+}
+
+async fn call_method<B>(
+    connection: &Connection,
+    player: &Player,
+    method: &str,
+    body: &B,
+) -> zbus::Result<()>
+where
+    B: serde::Serialize + zbus::zvariant::DynamicType,
+{
+    connection
+        .call_method(
+            Some(player.bus_name.as_str()),
+            MPRIS_OBJECT_PATH,
+            Some(PLAYER_INTERFACE),
+            method,
+            body,
+        )
+        .await
+        .map(|_| ())
+}
+
+async fn set_property(
+    connection: &Connection,
+    player: &Player,
+    property: &str,
+    value: &Value<'_>,
+) -> zbus::Result<()> {
+    connection
+        .call_method(
+            Some(player.bus_name.as_str()),
+            MPRIS_OBJECT_PATH,
+            Some(PROPERTIES_INTERFACE),
+            "Set",
+            &(PLAYER_INTERFACE, property, value),
+        )
+        .await
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        media::properties::{PlaybackStatus, Properties},
+        registry::MPRIS_BUS_NAME_PREFIX,
+    };
+
+    fn player(properties: Properties) -> Player {
+        Player {
+            bus_name: format!("{}mpv", MPRIS_BUS_NAME_PREFIX),
+            unique_name: ":1.1".to_string(),
+            properties,
+        }
+    }
+
+    /// A player playing a track that's 200 seconds long, at half volume.
+    fn properties() -> Properties {
+        let mut properties =
+            Properties::test(PlaybackStatus::Playing, "/track/1", Some(200_000_000), 0);
+        properties.volume = Some(0.5);
+        properties
+    }
+
+    fn unsupported_reason(result: Result<Request, Error>) -> String {
+        match result {
+            Err(Error::UnsupportedMediaPlayerControl { player, reason, .. }) => {
+                assert_eq!(player, "mpv");
+                reason
+            }
+            result => panic!("expected an unsupported control, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn methods() {
+        let player = player(properties());
+        assert_eq!(
+            Control::Play.check(&player).unwrap(),
+            Request::Method("Play")
+        );
+        assert_eq!(
+            Control::PlayPause.check(&player).unwrap(),
+            Request::Method("PlayPause")
+        );
+        assert_eq!(
+            Control::Next.check(&player).unwrap(),
+            Request::Method("Next")
+        );
+        assert_eq!(
+            Control::Seek(-5_000_000).check(&player).unwrap(),
+            Request::Seek(-5_000_000)
+        );
+    }
+
+    #[test]
+    fn requires_can_control() {
+        let mut properties = properties();
+        properties.can_control = false;
+        let player = player(properties);
+        for control in [Control::Play, Control::Stop, Control::SetVolume(1.0)] {
+            assert_eq!(
+                unsupported_reason(control.check(&player)),
+                "CanControl is false"
+            );
+        }
+    }
+
+    #[test]
+    fn requires_capabilities() {
+        let mut properties = properties();
+        properties.can_seek = false;
+        properties.can_go_next = false;
+        let player = player(properties);
+        assert_eq!(
+            unsupported_reason(Control::Seek(1).check(&player)),
+            "CanSeek is false"
+        );
+        assert_eq!(
+            unsupported_reason(Control::SetPosition(1).check(&player)),
+            "CanSeek is false"
+        );
+        assert_eq!(
+            unsupported_reason(Control::Next.check(&player)),
+            "CanGoNext is false"
+        );
+        // Stop has no capability of its own.
+        assert!(Control::Stop.check(&player).is_ok());
+        assert!(Control::Previous.check(&player).is_ok());
+    }
+
+    #[test]
+    fn set_position() {
+        let player = player(properties());
+        assert_eq!(
+            Control::SetPosition(200_000_000).check(&player).unwrap(),
+            Request::SetPosition {
+                trackid: OwnedObjectPath::try_from("/track/1").unwrap(),
+                position: 200_000_000,
+            }
+        );
+        for position in [-1, 200_000_001] {
+            match Control::SetPosition(position).check(&player) {
+                Err(Error::InvalidArgument { message }) => assert_eq!(
+                    message,
+                    "Position must be within the track's length of 200 seconds"
+                ),
+                result => panic!("expected an invalid position, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn set_position_without_track() {
+        for trackid in [NO_TRACK, "", "not a path"] {
+            let player = player(Properties::test(PlaybackStatus::Stopped, trackid, None, 0));
+            assert_eq!(
+                unsupported_reason(Control::SetPosition(0).check(&player)),
+                "there is no current track"
+            );
+        }
+    }
+
+    #[test]
+    fn volume() {
+        let player = player(properties());
+        let volume = |control: Control| match control.check(&player).unwrap() {
+            Request::SetProperty("Volume", Value::F64(volume)) => volume,
+            request => panic!("expected a volume, got {:?}", request),
+        };
+        assert_eq!(volume(Control::SetVolume(1.5)), 1.5);
+        assert_eq!(volume(Control::SetVolume(-1.0)), 0.0);
+        assert_eq!(volume(Control::AdjustVolume(0.25)), 0.75);
+        assert_eq!(volume(Control::AdjustVolume(0.75)), 1.0);
+        assert_eq!(volume(Control::AdjustVolume(-0.75)), 0.0);
+
+        let player = self::player(Properties::test(
+            PlaybackStatus::Playing,
+            "/track/1",
+            None,
+            0,
+        ));
+        assert_eq!(
+            unsupported_reason(Control::AdjustVolume(0.1).check(&player)),
+            "it has no Volume property"
+        );
+    }
+
+    #[test]
+    fn shuffle_and_loop() {
+        let mut properties = properties();
+        properties.shuffle = Some(true);
+        let player = player(properties);
+        assert_eq!(
+            Control::ToggleShuffle.check(&player).unwrap(),
+            Request::SetProperty("Shuffle", Value::from(false))
+        );
+        assert_eq!(
+            unsupported_reason(Control::SetLoop(LoopStatus::Track).check(&player)),
+            "it has no LoopStatus property"
+        );
+    }
+}
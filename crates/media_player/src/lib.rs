@@ -1,46 +1,41 @@
 use std::{
-    collections::HashSet,
-    sync::mpsc::{self, Sender}, thread,
+    collections::{HashMap, HashSet},
+    pin::pin,
+    sync::{Arc, mpsc::Sender},
+    thread,
 };
 
+use futures_lite::{Stream, StreamExt, stream};
 use zbus::{
+    MatchRule, MessageStream,
     blocking::Connection,
+    message,
     zvariant::{OwnedValue, Value},
 };
 
-use crate::media::properties::{LoopStatus, Metadata, PlaybackStatus};
+use media::{
+    properties::{LoopStatus, MetadataValue, PlaybackStatus},
+    try_as_value::Coerce,
+};
+use registry::{PlayerEvent, PlayerRegistry};
+
+pub mod control;
+pub mod media;
+pub mod position;
+pub mod registry;
+pub mod selection;
 
-mod media;
-//
-// use std::{
-//     cell::RefCell,
-//     collections::HashMap,
-//     error::Error,
-//     future::pending,
-//     sync::{mpsc::Sender, Arc},
-//     time::{Duration, Instant, SystemTime},
-// };
-//
-// use dashmap::{DashMap, Entry};
-// use futures_lite::StreamExt;
-// use media::properties::{PlaybackStatus, Properties};
-// use tokio::{
-//     sync::Mutex,
-//     time::{MissedTickBehavior, interval},
-// };
-// use zbus::{
-//     Connection, Proxy,
-//     fdo::{DBusProxy, PropertiesProxy},
-//     zvariant::{Dict, OwnedValue, Value},
-// };
+pub const MPRIS_OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+pub const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+pub(crate) const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
 
+/// Watches every MPRIS player on the session bus.
 pub struct MediaPlayer {
-    connection: zbus::blocking::Connection,
+    connection: zbus::Connection,
     watched_properties: HashSet<PropertyName>,
-    sender: Sender<Property>,
 }
 
-#[derive(PartialEq, Eq, Hash, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum MetadataName {
     MprisTrackid,
     MprisLength,
@@ -66,12 +61,15 @@ pub enum MetadataName {
     XesamUserRating,
 }
 
-#[derive(PartialEq, Eq, Hash, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum PropertyName {
     PlaybackStatus,
     LoopStatus,
     Rate,
     Shuffle,
+    /// An entry of `Metadata` that's in the spec. Other entries, such as `kde:mediaSrc`, can only
+    /// be read from [`Metadata::extra`](media::properties::Metadata::extra), as changes of them
+    /// aren't sent.
     Metadata(MetadataName),
     Volume,
     Position,
@@ -85,10 +83,210 @@ pub enum PropertyName {
     CanControl,
 }
 
+impl MetadataName {
+    pub const ALL: [MetadataName; 22] = [
+        MetadataName::MprisTrackid,
+        MetadataName::MprisLength,
+        MetadataName::MprisArtUrl,
+        MetadataName::XesamAlbum,
+        MetadataName::XesamAlbumArtist,
+        MetadataName::XesamArtist,
+        MetadataName::XesamAsText,
+        MetadataName::XesamAudioBpm,
+        MetadataName::XesamAutoRating,
+        MetadataName::XesamComment,
+        MetadataName::XesamComposer,
+        MetadataName::XesamContentCreated,
+        MetadataName::XesamDiscNumber,
+        MetadataName::XesamFirstUsed,
+        MetadataName::XesamGenre,
+        MetadataName::XesamLastUsed,
+        MetadataName::XesamLyricist,
+        MetadataName::XesamTitle,
+        MetadataName::XesamTrackNumber,
+        MetadataName::XesamUrl,
+        MetadataName::XesamUseCount,
+        MetadataName::XesamUserRating,
+    ];
+
+    /// The key in the `Metadata` map, e.g. `xesam:title`.
+    pub fn key(&self) -> &'static str {
+        match self {
+            MetadataName::MprisTrackid => "mpris:trackid",
+            MetadataName::MprisLength => "mpris:length",
+            MetadataName::MprisArtUrl => "mpris:artUrl",
+            MetadataName::XesamAlbum => "xesam:album",
+            MetadataName::XesamAlbumArtist => "xesam:albumArtist",
+            MetadataName::XesamArtist => "xesam:artist",
+            MetadataName::XesamAsText => "xesam:asText",
+            MetadataName::XesamAudioBpm => "xesam:audioBPM",
+            MetadataName::XesamAutoRating => "xesam:autoRating",
+            MetadataName::XesamComment => "xesam:comment",
+            MetadataName::XesamComposer => "xesam:composer",
+            MetadataName::XesamContentCreated => "xesam:contentCreated",
+            MetadataName::XesamDiscNumber => "xesam:discNumber",
+            MetadataName::XesamFirstUsed => "xesam:firstUsed",
+            MetadataName::XesamGenre => "xesam:genre",
+            MetadataName::XesamLastUsed => "xesam:lastUsed",
+            MetadataName::XesamLyricist => "xesam:lyricist",
+            MetadataName::XesamTitle => "xesam:title",
+            MetadataName::XesamTrackNumber => "xesam:trackNumber",
+            MetadataName::XesamUrl => "xesam:url",
+            MetadataName::XesamUseCount => "xesam:useCount",
+            MetadataName::XesamUserRating => "xesam:userRating",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|name| name.key() == key)
+    }
+
+    /// The name of the entry without its namespace, in snake case, e.g. `art_url` for
+    /// `mpris:artUrl`. It's the name it's serialized with.
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            MetadataName::MprisTrackid => "trackid",
+            MetadataName::MprisLength => "length",
+            MetadataName::MprisArtUrl => "art_url",
+            MetadataName::XesamAlbum => "album",
+            MetadataName::XesamAlbumArtist => "album_artist",
+            MetadataName::XesamArtist => "artist",
+            MetadataName::XesamAsText => "as_text",
+            MetadataName::XesamAudioBpm => "audio_bpm",
+            MetadataName::XesamAutoRating => "auto_rating",
+            MetadataName::XesamComment => "comment",
+            MetadataName::XesamComposer => "composer",
+            MetadataName::XesamContentCreated => "content_created",
+            MetadataName::XesamDiscNumber => "disc_number",
+            MetadataName::XesamFirstUsed => "first_used",
+            MetadataName::XesamGenre => "genre",
+            MetadataName::XesamLastUsed => "last_used",
+            MetadataName::XesamLyricist => "lyricist",
+            MetadataName::XesamTitle => "title",
+            MetadataName::XesamTrackNumber => "track_number",
+            MetadataName::XesamUrl => "url",
+            MetadataName::XesamUseCount => "use_count",
+            MetadataName::XesamUserRating => "user_rating",
+        }
+    }
+}
+
+impl PropertyName {
+    /// Every property except `Metadata`, whose entries are separate properties.
+    pub const PLAYER_PROPERTIES: [PropertyName; 14] = [
+        PropertyName::PlaybackStatus,
+        PropertyName::LoopStatus,
+        PropertyName::Rate,
+        PropertyName::Shuffle,
+        PropertyName::Volume,
+        PropertyName::Position,
+        PropertyName::MinimumRate,
+        PropertyName::MaximumRate,
+        PropertyName::CanGoNext,
+        PropertyName::CanGoPrevious,
+        PropertyName::CanPlay,
+        PropertyName::CanPause,
+        PropertyName::CanSeek,
+        PropertyName::CanControl,
+    ];
+
+    /// The name of the property in `org.mpris.MediaPlayer2.Player`, or the key in the
+    /// `Metadata` map for its entries.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PropertyName::PlaybackStatus => "PlaybackStatus",
+            PropertyName::LoopStatus => "LoopStatus",
+            PropertyName::Rate => "Rate",
+            PropertyName::Shuffle => "Shuffle",
+            PropertyName::Metadata(metadata_name) => metadata_name.key(),
+            PropertyName::Volume => "Volume",
+            PropertyName::Position => "Position",
+            PropertyName::MinimumRate => "MinimumRate",
+            PropertyName::MaximumRate => "MaximumRate",
+            PropertyName::CanGoNext => "CanGoNext",
+            PropertyName::CanGoPrevious => "CanGoPrevious",
+            PropertyName::CanPlay => "CanPlay",
+            PropertyName::CanPause => "CanPause",
+            PropertyName::CanSeek => "CanSeek",
+            PropertyName::CanControl => "CanControl",
+        }
+    }
+
+    /// The property named `name` in `org.mpris.MediaPlayer2.Player`. `Metadata` isn't one, as
+    /// its entries are separate properties.
+    pub fn from_dbus_name(name: &str) -> Option<Self> {
+        Self::PLAYER_PROPERTIES
+            .into_iter()
+            .find(|property| property.as_str() == name)
+    }
+}
+
+impl std::fmt::Display for PropertyName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug)]
 pub struct Property {
-    name: PropertyName,
-    value: OwnedValue,
+    /// The unique bus name of the player that changed it, e.g. `:1.42`.
+    pub player: String,
+    pub name: PropertyName,
+    pub value: PropertyValue,
+}
+
+/// The value of a [`Property`], with a variant for each [`PropertyName`].
+#[derive(Clone, PartialEq, Debug)]
+pub enum PropertyValue {
+    PlaybackStatus(PlaybackStatus),
+    LoopStatus(LoopStatus),
+    Rate(f64),
+    Shuffle(bool),
+    Metadata(MetadataValue),
+    Volume(f64),
+    /// In microseconds.
+    Position(i64),
+    MinimumRate(f64),
+    MaximumRate(f64),
+    CanGoNext(bool),
+    CanGoPrevious(bool),
+    CanPlay(bool),
+    CanPause(bool),
+    CanSeek(bool),
+    CanControl(bool),
+}
+
+impl PropertyValue {
+    /// Decodes the value of the property `name` as sent over D-Bus, coercing it with [`Coerce`] if
+    /// it has a similar type. `None` if it can't be, whereas an optional property of
+    /// [`Properties`](media::properties::Properties) that can't be coerced is `None` and a
+    /// required one fails the whole reply.
+    pub fn from_dbus(name: PropertyName, value: &Value<'_>) -> Option<Self> {
+        let status = || String::coerce(value);
+        Some(match name {
+            PropertyName::PlaybackStatus => {
+                PropertyValue::PlaybackStatus(PlaybackStatus::try_from(status()?).ok()?)
+            }
+            PropertyName::LoopStatus => {
+                PropertyValue::LoopStatus(LoopStatus::try_from(status()?).ok()?)
+            }
+            PropertyName::Rate => PropertyValue::Rate(f64::coerce(value)?),
+            PropertyName::Shuffle => PropertyValue::Shuffle(bool::coerce(value)?),
+            PropertyName::Metadata(name) => {
+                PropertyValue::Metadata(MetadataValue::coerce_entry(name, value)?)
+            }
+            PropertyName::Volume => PropertyValue::Volume(f64::coerce(value)?),
+            PropertyName::Position => PropertyValue::Position(i64::coerce(value)?),
+            PropertyName::MinimumRate => PropertyValue::MinimumRate(f64::coerce(value)?),
+            PropertyName::MaximumRate => PropertyValue::MaximumRate(f64::coerce(value)?),
+            PropertyName::CanGoNext => PropertyValue::CanGoNext(bool::coerce(value)?),
+            PropertyName::CanGoPrevious => PropertyValue::CanGoPrevious(bool::coerce(value)?),
+            PropertyName::CanPlay => PropertyValue::CanPlay(bool::coerce(value)?),
+            PropertyName::CanPause => PropertyValue::CanPause(bool::coerce(value)?),
+            PropertyName::CanSeek => PropertyValue::CanSeek(bool::coerce(value)?),
+            PropertyName::CanControl => PropertyValue::CanControl(bool::coerce(value)?),
+        })
+    }
 }
 
 #[derive(Default)]
@@ -97,17 +295,53 @@ pub struct MediaPlayerBuilder {
 }
 
 impl MediaPlayerBuilder {
-    pub fn build_and_start(self, sender: Sender<Property>) -> MediaPlayer {
-        assert!(!self.watched_properties.is_empty());
+    /// Connects to the session bus.
+    pub async fn build(self) -> zbus::Result<MediaPlayer> {
+        Ok(self.build_with_connection(zbus::Connection::session().await?))
+    }
 
-        let connection = Connection::session().unwrap();
-        let media_player = MediaPlayer {
+    /// Uses `connection`, e.g. one that's shared with other modules.
+    pub fn build_with_connection(self, connection: zbus::Connection) -> MediaPlayer {
+        MediaPlayer {
             connection,
-            sender,
             watched_properties: self.watched_properties,
-        };
-        media_player.start();
-        media_player
+        }
+    }
+
+    /// Connects to the session bus and starts sending changes of the watched properties to
+    /// `sender` from a dedicated thread, until the receiver is dropped.
+    ///
+    /// This and receiving from the channel block the calling thread, so async code should use
+    /// [`build`](Self::build) and [`MediaPlayer::watch_properties`] instead.
+    ///
+    /// Fails if no property is watched, as nothing would ever be sent.
+    pub fn build_and_start(self, sender: Sender<Property>) -> zbus::Result<MediaPlayer> {
+        if self.watched_properties.is_empty() {
+            return Err(zbus::Error::Failure(
+                "no properties are watched".to_string(),
+            ));
+        }
+
+        let connection = Connection::session()?;
+        let media_player = self.build_with_connection(connection.into_inner());
+        // Subscribe before spawning the thread, so that no change is missed and errors are
+        // returned to the caller.
+        let properties = zbus::block_on(media_player.watch_properties())?;
+        thread::Builder::new()
+            .name("media player watch loop".to_string())
+            .spawn(move || {
+                zbus::block_on(async {
+                    let mut properties = pin!(properties);
+                    while let Some(property) = properties.next().await {
+                        // The receiver was dropped, so nothing is watching anymore.
+                        if sender.send(property).is_err() {
+                            return;
+                        }
+                    }
+                })
+            })
+            .map_err(|err| zbus::Error::InputOutput(Arc::new(err)))?;
+        Ok(media_player)
     }
 
     pub fn watch(mut self, property: PropertyName) -> Self {
@@ -126,325 +360,101 @@ impl MediaPlayer {
         MediaPlayerBuilder::default()
     }
 
-    pub(crate) fn start(&self) {
-        let tx = self.sender.clone();
-        let _ = thread::Builder::new()
-            .name("media player watch loop".to_string())
-            .spawn(move || {
-                loop {
-                    tx.send(Property { name: PropertyName::LoopStatus, value: OwnedValue::from(123) }).unwrap();
-                }
-            });
+    /// The session bus connection, which can be shared with a
+    /// [`PlayerRegistry`](registry::PlayerRegistry).
+    pub fn connection(&self) -> &zbus::Connection {
+        &self.connection
     }
-}
 
-pub fn foo() {
-    let (tx, rx) = mpsc::channel::<Property>();
-    let _media_player = MediaPlayerBuilder::default()
-        .watch(PropertyName::LoopStatus)
-        .watch(PropertyName::Volume)
-        .build_and_start(tx);
+    /// Every player as it's added, changed, or removed, starting with the players that are
+    /// already on the bus. See [`PlayerRegistry::watch`](registry::PlayerRegistry::watch).
+    pub async fn watch(
+        &self,
+    ) -> zbus::Result<impl Stream<Item = PlayerEvent> + Send + 'static + use<>> {
+        let (_, events) = PlayerRegistry::watch(&self.connection).await?;
+        Ok(events)
+    }
 
-    for prop in rx {
-        dbg!(prop);
+    /// The changes of the watched properties of every player, as they signal
+    /// `PropertiesChanged`.
+    pub async fn watch_properties(
+        &self,
+    ) -> zbus::Result<impl Stream<Item = Property> + Send + 'static + use<>> {
+        let rule = properties_changed_rule()?;
+        let messages = MessageStream::for_match_rule(rule, &self.connection, None).await?;
+        let watched_properties = self.watched_properties.clone();
+        Ok(messages
+            .filter_map(|message| {
+                let message = message.ok()?;
+                let player = message.header().sender()?.to_string();
+                // Signals that don't match `PropertiesChanged`'s signature are ignored.
+                let (_, changed, _) = message
+                    .body()
+                    .deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>()
+                    .ok()?;
+                Some((player, changed))
+            })
+            .flat_map(move |(player, changed)| {
+                stream::iter(watched_properties_changed(
+                    &player,
+                    changed,
+                    &watched_properties,
+                ))
+            }))
     }
 }
 
-// match property {
-//     PropertyName::PlaybackStatus => todo!(),
-//     PropertyName::LoopStatus => todo!(),
-//     PropertyName::Rate => todo!(),
-//     PropertyName::Shuffle => todo!(),
-//     PropertyName::Metadata(metadata_name) => {
-//         match metadata_name {
-//             MetadataName::MprisTrackid => todo!(),
-//             MetadataName::MprisLength => todo!(),
-//             MetadataName::MprisArtUrl => todo!(),
-//             MetadataName::XesamAlbum => todo!(),
-//             MetadataName::XesamAlbumArtist => todo!(),
-//             MetadataName::XesamArtist => todo!(),
-//             MetadataName::XesamAsText => todo!(),
-//             MetadataName::XesamAudioBpm => todo!(),
-//             MetadataName::XesamAutoRating => todo!(),
-//             MetadataName::XesamComment => todo!(),
-//             MetadataName::XesamComposer => todo!(),
-//             MetadataName::XesamContentCreated => todo!(),
-//             MetadataName::XesamDiscNumber => todo!(),
-//             MetadataName::XesamFirstUsed => todo!(),
-//             MetadataName::XesamGenre => todo!(),
-//             MetadataName::XesamLastUsed => todo!(),
-//             MetadataName::XesamLyricist => todo!(),
-//             MetadataName::XesamTitle => todo!(),
-//             MetadataName::XesamTrackNumber => todo!(),
-//             MetadataName::XesamUrl => todo!(),
-//             MetadataName::XesamUseCount => todo!(),
-//             MetadataName::XesamUserRating => todo!(),
-//         }
-//     },
-//     PropertyName::Volume => todo!(),
-//     PropertyName::Position => todo!(),
-//     PropertyName::MinimumRate => todo!(),
-//     PropertyName::MaximumRate => todo!(),
-//     PropertyName::CanGoNext => todo!(),
-//     PropertyName::CanGoPrevious => todo!(),
-//     PropertyName::CanPlay => todo!(),
-//     PropertyName::CanPause => todo!(),
-//     PropertyName::CanSeek => todo!(),
-//     PropertyName::CanControl => todo!(),
-// }
-
-// #[derive(Deserialize, Type, Debug)]
-// #[zvariant(signature = "dict")]
-// #[serde(rename_all(deserialize = "PascalCase"))]
-// pub struct Properties {
-//     #[serde(with = "as_value")]
-//     pub playback_status: PlaybackStatus,
-//
-//     #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
-//     pub loop_status: Option<LoopStatus>,
-//
-//     #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
-//     pub rate: Option<f64>,
-//
-//     #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
-//     pub shuffle: Option<bool>,
-//
-//     #[serde(with = "as_value")]
-//     pub metadata: Metadata,
-//
-//     #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
-//     pub volume: Option<f64>,
-//
-//     #[serde(with = "as_value")]
-//     pub position: i64,
-//
-//     #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
-//     pub minimum_rate: Option<f64>,
-//
-//     #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
-//     pub maximum_rate: Option<f64>,
-//
-//     #[serde(with = "as_value")]
-//     pub can_go_next: bool,
-//
-//     #[serde(with = "as_value")]
-//     pub can_go_previous: bool,
-//
-//     #[serde(with = "as_value")]
-//     pub can_play: bool,
-//
-//     #[serde(with = "as_value")]
-//     pub can_pause: bool,
-//
-//     #[serde(with = "as_value")]
-//     pub can_seek: bool,
-//
-//     #[serde(with = "as_value")]
-//     pub can_control: bool,
-// }
+/// Matches `PropertiesChanged` of `org.mpris.MediaPlayer2.Player` from any player.
+pub(crate) fn properties_changed_rule() -> zbus::Result<MatchRule<'static>> {
+    Ok(MatchRule::builder()
+        .msg_type(message::Type::Signal)
+        .interface(PROPERTIES_INTERFACE)?
+        .member("PropertiesChanged")?
+        .path(MPRIS_OBJECT_PATH)?
+        .arg(0, PLAYER_INTERFACE)?
+        .build())
+}
 
-// pub async fn get_mpris_bus_names(connection: &Connection) -> Result<Vec<String>, Box<dyn Error>> {
-//     let reply = connection
-//         .call_method(
-//             Some("org.freedesktop.DBus"),
-//             "/org/freedesktop/DBus",
-//             Some("org.freedesktop.DBus"),
-//             "ListNames",
-//             &(),
-//         )
-//         .await?;
-//     let all_bus_names: Vec<String> = reply.body().deserialize()?;
-//     Ok(all_bus_names
-//         .into_iter()
-//         .filter(|bus_name| bus_name.starts_with("org.mpris.MediaPlayer2"))
-//         .collect::<Vec<_>>())
-// }
-//
-// pub async fn get_mpris_all_properties(
-//     connection: &Connection,
-//     bus_name: &str,
-// ) -> Result<Properties, Box<dyn Error>> {
-//     let reply = connection
-//         .call_method(
-//             Some(bus_name),
-//             "/org/mpris/MediaPlayer2",
-//             Some("org.freedesktop.DBus.Properties"),
-//             "GetAll",
-//             &("org.mpris.MediaPlayer2.Player"),
-//         )
-//         .await?;
-//
-//     let reply_body = reply.body();
-//     let properties: Properties = reply_body.deserialize()?;
-//     Ok(properties)
-// }
-//
-// pub async fn get_mpris_property(
-//     connection: &Connection,
-//     bus_name: &str,
-//     property_name: &str,
-// ) -> Result<OwnedValue, Box<dyn Error>> {
-//     let reply = connection
-//         .call_method(
-//             Some(bus_name),
-//             "/org/mpris/MediaPlayer2",
-//             Some("org.freedesktop.DBus.Properties"),
-//             "Get",
-//             &("org.mpris.MediaPlayer2.Player", property_name),
-//         )
-//         .await?;
-//
-//     let reply_body = reply.body();
-//     let value: Value = reply_body.deserialize()?;
-//     Ok(value.try_to_owned()?)
-// }
-//
-// pub async fn get_mpris_position(
-//     connection: &Connection,
-//     bus_name: &str,
-// ) -> Result<i64, Box<dyn Error>> {
-//     Ok(i64::try_from(
-//         get_mpris_property(connection, bus_name, "Position").await?,
-//     )?)
-// }
-//
-// pub async fn get_mpris_playback_status(
-//     connection: &Connection,
-//     bus_name: &str,
-// ) -> Result<PlaybackStatus, Box<dyn Error>> {
-//     Ok(PlaybackStatus::try_from(
-//         get_mpris_property(connection, bus_name, "PlaybackStatus").await?,
-//     )?)
-// }
-//
-// async fn main() -> Result<(), Box<dyn Error>> {
-//     let connection = Connection::session().await?;
-//
-//     let proxy_dbus = DBusProxy::new(&connection).await?;
-//     let name_owner_changed = Arc::new(Mutex::new(proxy_dbus.receive_name_owner_changed().await?));
-//
-//     let interval = Arc::new(Mutex::new(interval(Duration::from_secs(1))));
-//     interval
-//         .clone()
-//         .lock()
-//         .await
-//         .set_missed_tick_behavior(MissedTickBehavior::Skip);
-//
-//     let bus_names = get_mpris_bus_names(&connection).await?;
-//     let bus_properties = Arc::new(DashMap::new());
-//     let property_streams = Arc::new(DashMap::new());
-//
-//     for bus_name in bus_names {
-//         bus_properties.insert(
-//             bus_name.clone(),
-//             (
-//                 get_mpris_all_properties(&connection, &bus_name).await?,
-//                 None::<SystemTime>,
-//             ),
-//         );
-//         let proxy =
-//             PropertiesProxy::new(&connection, bus_name.clone(), "/org/mpris/MediaPlayer2").await?;
-//         let stream = proxy.receive_properties_changed().await?;
-//         property_streams.clone().insert(bus_name.clone(), stream);
-//     }
-//
-//     // let properties_changed_signal = Arc::new(Mutex::new(
-//     //     proxy_properties.receive_properties_changed().await?,
-//     // ));
-//
-//     // FIXME: firefox `Position` property incrementing while player is paused:
-//     // https://bugzilla.mozilla.org/show_bug.cgi?id=1950461
-//     //
-//     // https://phabricator.services.mozilla.com/D242633
-//     let name_owner_changed_handle = tokio::spawn({
-//         let name_owner_changed_cloned = name_owner_changed.clone();
-//         let property_streams_cloned = property_streams.clone();
-//         let bus_properties_cloned = bus_properties.clone();
-//         async move {
-//             loop {
-//                 if let Some(name) = name_owner_changed_cloned.lock().await.next().await {
-//                     let body = name.message().body();
-//                     let message: (String, String, String) = body.deserialize().unwrap();
-//                     let bus_name = message.0;
-//                     if bus_name.starts_with("org.mpris.MediaPlayer2") {
-//                         match bus_properties_cloned.entry(bus_name.clone()) {
-//                             Entry::Occupied(occupied_entry) => {
-//                                 property_streams_cloned.remove(&bus_name);
-//                                 occupied_entry.remove();
-//                             }
-//                             Entry::Vacant(vacant_entry) => {
-//                                 let property =
-//                                     get_mpris_all_properties(&connection.clone(), &bus_name)
-//                                         .await
-//                                         .unwrap();
-//                                 let proxy = PropertiesProxy::new(
-//                                     &connection.clone(),
-//                                     bus_name.clone(),
-//                                     "/org/mpris/MediaPlayer2",
-//                                 )
-//                                 .await
-//                                 .unwrap();
-//                                 let stream = proxy.receive_properties_changed().await.unwrap();
-//                                 property_streams_cloned.insert(bus_name.clone(), stream);
-//                                 vacant_entry.insert((property, None));
-//                             }
-//                         }
-//                     }
-//                 }
-//             }
-//         }
-//     });
-//
-//     //
-//     // let playback_status_changed_handle = tokio::spawn({
-//     //     let proxy_properties_changed_signals_cloned = proxy_properties_changed_signals.clone();
-//     //     let bus_properties_cloned = bus_properties.clone();
-//     //     let interval_cloned = interval.clone();
-//     //     async move {
-//     //         loop {
-//     //             for mut pair in proxy_properties_changed_signals_cloned.iter_mut() {
-//     //                 let (bus_name, bus_proxy_properties_changed) = pair.pair_mut();
-//     //                 if let Some(properties_changed_message) =
-//     //                     bus_proxy_properties_changed.next().await
-//     //                 {
-//     //                     let body = properties_changed_message.message().body();
-//     //                     let mut message: (String, HashMap<String, Value>, Vec<String>) =
-//     //                         body.deserialize().unwrap();
-//     //                     dbg!(&message);
-//     //                     let playback_status = PlaybackStatus::try_from(
-//     //                         String::try_from(message.1.remove("PlaybackStatus").unwrap()).unwrap(),
-//     //                     )
-//     //                     .unwrap();
-//     //                     let mut bus_entry = bus_properties_cloned.get_mut(bus_name).unwrap();
-//     //                     let bus_property = bus_entry.value_mut();
-//     //                     bus_property.playback_status = playback_status;
-//     //                     dbg!(bus_property);
-//     //                     interval_cloned.lock().await.reset_immediately();
-//     //                 }
-//     //             }
-//     //         }
-//     //     }
-//     // });
-//     //
-//     // let update_position_handle = tokio::spawn({
-//     //     let bus_properties_cloned = bus_properties.clone();
-//     //     let conn_test = Connection::session().await.unwrap();
-//     //     let interval_cloned = interval.clone();
-//     //     async move {
-//     //         loop {
-//     //             interval_cloned.lock().await.tick().await;
-//     //             for mut pair in bus_properties_cloned.iter_mut() {
-//     //                 let (bus_name, bus_property) = pair.pair_mut();
-//     //                 if bus_property.playback_status == PlaybackStatus::Playing {
-//     //                     bus_property.position =
-//     //                         get_mpris_position(&connection, bus_name).await.unwrap();
-//     //                     dbg!(&bus_name, &bus_property);
-//     //                 }
-//     //             }
-//     //         }
-//     //     }
-//     // });
-//
-//     pending::<()>().await;
-//     Ok(())
-// }
+/// The changed properties of `player` that are watched, with the entries of `Metadata` as
+/// separate properties. Values that can't be decoded are skipped, as are entries of `Metadata` that
+/// aren't in the spec, as there's no [`PropertyName`] to send them as.
+fn watched_properties_changed(
+    player: &str,
+    changed: HashMap<String, OwnedValue>,
+    watched_properties: &HashSet<PropertyName>,
+) -> Vec<Property> {
+    let mut properties = Vec::new();
+    for (name, value) in changed {
+        if name == "Metadata" {
+            // Entries that are missing from a new `Metadata` were removed, but as there's no value
+            // to send for them, only the entries that are present are sent.
+            let Ok(metadata) = HashMap::<String, OwnedValue>::try_from(value) else {
+                continue;
+            };
+            for (key, value) in metadata {
+                let Some(name) = MetadataName::from_key(&key).map(PropertyName::Metadata) else {
+                    continue;
+                };
+                if watched_properties.contains(&name)
+                    && let Some(value) = PropertyValue::from_dbus(name, &value)
+                {
+                    properties.push(Property {
+                        player: player.to_string(),
+                        name,
+                        value,
+                    });
+                }
+            }
+        } else if let Some(name) = PropertyName::from_dbus_name(&name)
+            && watched_properties.contains(&name)
+            && let Some(value) = PropertyValue::from_dbus(name, &value)
+        {
+            properties.push(Property {
+                player: player.to_string(),
+                name,
+                value,
+            });
+        }
+    }
+    properties
+}
@@ -1,233 +1,350 @@
-use super::try_as_value::{self, try_as_optional};
+use std::collections::HashMap;
 
-use serde::Deserialize;
+use super::try_as_value::{self, Coerce, try_as_optional};
+use crate::MetadataName;
+
+use serde::{Deserialize, Deserializer, Serialize};
 use zbus::zvariant::{
-    OwnedValue, Type,
+    OwnedValue, Type, Value,
     as_value::{self, optional},
 };
 
+/// Serialized with the names of [`MetadataName::field_name`], e.g. `art_url` for `mpris:artUrl`,
+/// followed by the [`extra`](Self::extra) entries with their keys.
+///
 /// https://www.freedesktop.org/wiki/Specifications/mpris-spec/metadata/
 /// https://specifications.freedesktop.org/mpris-spec/latest/Track_List_Interface.html#Mapping:Metadata_Map
-#[derive(Default, Deserialize, Type, Debug)]
+#[derive(Default, Clone, Deserialize, Serialize, Type, Debug)]
 #[zvariant(signature = "dict")]
 #[serde(default)]
 pub struct Metadata {
-    #[serde(with = "try_as_value", rename(deserialize = "mpris:trackid"))]
+    #[serde(
+        deserialize_with = "try_as_value::deserialize",
+        rename(deserialize = "mpris:trackid", serialize = "trackid")
+    )]
     mpris_trackid: String,
 
     #[serde(
-        with = "try_as_optional",
-        rename(deserialize = "mpris:length"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "mpris:length", serialize = "length"),
         skip_serializing_if = "Option::is_none"
     )]
     mpris_length: Option<i64>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "mpris:artUrl"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "mpris:artUrl", serialize = "art_url"),
         skip_serializing_if = "Option::is_none"
     )]
     mpris_art_url: Option<String>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:album"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:album", serialize = "album"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_album: Option<String>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:albumArtist"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:albumArtist", serialize = "album_artist"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_album_artist: Option<Vec<String>>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:artist"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:artist", serialize = "artist"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_artist: Option<Vec<String>>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:asText"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:asText", serialize = "as_text"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_as_text: Option<String>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:audioBPM"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:audioBPM", serialize = "audio_bpm"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_audio_bpm: Option<i32>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:autoRating"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:autoRating", serialize = "auto_rating"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_auto_rating: Option<f64>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:comment"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:comment", serialize = "comment"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_comment: Option<Vec<String>>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:composer"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:composer", serialize = "composer"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_composer: Option<Vec<String>>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:contentCreated"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:contentCreated", serialize = "content_created"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_content_created: Option<String>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:discNumber"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:discNumber", serialize = "disc_number"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_disc_number: Option<i32>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:firstUsed"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:firstUsed", serialize = "first_used"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_first_used: Option<String>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:genre"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:genre", serialize = "genre"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_genre: Option<Vec<String>>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:lastUsed"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:lastUsed", serialize = "last_used"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_last_used: Option<String>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:lyricist"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:lyricist", serialize = "lyricist"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_lyricist: Option<Vec<String>>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:title"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:title", serialize = "title"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_title: Option<String>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:trackNumber"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:trackNumber", serialize = "track_number"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_track_number: Option<i32>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:url"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:url", serialize = "url"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_url: Option<String>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:useCount"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:useCount", serialize = "use_count"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_use_count: Option<i32>,
 
     #[serde(
-        with = "optional",
-        rename(deserialize = "xesam:userRating"),
+        deserialize_with = "try_as_optional::deserialize",
+        rename(deserialize = "xesam:userRating", serialize = "user_rating"),
         skip_serializing_if = "Option::is_none"
     )]
     xesam_user_rating: Option<f64>,
+
+    /// Entries that aren't in the spec, or are vendor-specific, e.g. `mpv:*` or `kde:mediaSrc`.
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    extra: HashMap<String, MetadataValue>,
+}
+
+/// Entries whose values can't be coerced into a [`MetadataValue`], e.g. dictionaries, are skipped.
+fn deserialize_extra<'de, D>(deserializer: D) -> Result<HashMap<String, MetadataValue>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = HashMap::<String, OwnedValue>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, MetadataValue::coerce(&value)?)))
+        .collect())
+}
+
+/// The value of any entry of [`Metadata`].
+#[derive(Clone, PartialEq, Serialize, Debug)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    String(String),
+    Strings(Vec<String>),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl MetadataValue {
+    /// Coerces the value of the entry `name` into the same type as [`Metadata::get`] has for it.
+    pub fn coerce_entry(name: MetadataName, value: &Value<'_>) -> Option<Self> {
+        match name {
+            MetadataName::MprisTrackid
+            | MetadataName::MprisArtUrl
+            | MetadataName::XesamAlbum
+            | MetadataName::XesamAsText
+            | MetadataName::XesamContentCreated
+            | MetadataName::XesamFirstUsed
+            | MetadataName::XesamLastUsed
+            | MetadataName::XesamTitle
+            | MetadataName::XesamUrl => String::coerce(value).map(MetadataValue::String),
+            MetadataName::XesamAlbumArtist
+            | MetadataName::XesamArtist
+            | MetadataName::XesamComment
+            | MetadataName::XesamComposer
+            | MetadataName::XesamGenre
+            | MetadataName::XesamLyricist => {
+                Vec::<String>::coerce(value).map(MetadataValue::Strings)
+            }
+            MetadataName::MprisLength => i64::coerce(value).map(MetadataValue::Integer),
+            MetadataName::XesamAudioBpm
+            | MetadataName::XesamDiscNumber
+            | MetadataName::XesamTrackNumber
+            | MetadataName::XesamUseCount => {
+                i32::coerce(value).map(|value| MetadataValue::Integer(value.into()))
+            }
+            MetadataName::XesamAutoRating | MetadataName::XesamUserRating => {
+                f64::coerce(value).map(MetadataValue::Float)
+            }
+        }
+    }
 }
 
 impl Metadata {
+    /// The value of the entry `key`, e.g. `xesam:title`, whether it's in the spec or
+    /// [`extra`](Self::extra).
+    pub fn get_key(&self, key: &str) -> Option<MetadataValue> {
+        match MetadataName::from_key(key) {
+            Some(name) => self.get(name),
+            None => self.extra.get(key).cloned(),
+        }
+    }
+
+    /// The entries that aren't in the spec, by key.
+    pub fn extra(&self) -> &HashMap<String, MetadataValue> {
+        &self.extra
+    }
+
+    /// The value of the entry `name`, if present.
+    pub fn get(&self, name: MetadataName) -> Option<MetadataValue> {
+        let string = |value: Option<&str>| value.map(|value| MetadataValue::String(value.into()));
+        let strings =
+            |value: Option<&[String]>| value.map(|value| MetadataValue::Strings(value.into()));
+        let integer = |value: Option<i32>| value.map(|value| MetadataValue::Integer(value.into()));
+        match name {
+            MetadataName::MprisTrackid => string(Some(self.trackid())),
+            MetadataName::MprisLength => self.length().map(MetadataValue::Integer),
+            MetadataName::MprisArtUrl => string(self.art_url()),
+            MetadataName::XesamAlbum => string(self.album()),
+            MetadataName::XesamAlbumArtist => strings(self.album_artist()),
+            MetadataName::XesamArtist => strings(self.artist()),
+            MetadataName::XesamAsText => string(self.as_text()),
+            MetadataName::XesamAudioBpm => integer(self.audio_bpm()),
+            MetadataName::XesamAutoRating => self.auto_rating().map(MetadataValue::Float),
+            MetadataName::XesamComment => strings(self.comment()),
+            MetadataName::XesamComposer => strings(self.composer()),
+            MetadataName::XesamContentCreated => string(self.content_created()),
+            MetadataName::XesamDiscNumber => integer(self.disc_number()),
+            MetadataName::XesamFirstUsed => string(self.first_used()),
+            MetadataName::XesamGenre => strings(self.genre()),
+            MetadataName::XesamLastUsed => string(self.last_used()),
+            MetadataName::XesamLyricist => strings(self.lyricist()),
+            MetadataName::XesamTitle => string(self.title()),
+            MetadataName::XesamTrackNumber => integer(self.track_number()),
+            MetadataName::XesamUrl => string(self.url()),
+            MetadataName::XesamUseCount => integer(self.use_count()),
+            MetadataName::XesamUserRating => self.user_rating().map(MetadataValue::Float),
+        }
+    }
+
     /// A unique identity for this track within the context of an MPRIS object (eg: tracklist).
     ///
     /// Must always be present.
-    pub fn trackid(&mut self) -> &mut String {
-        &mut self.mpris_trackid
+    pub fn trackid(&self) -> &str {
+        &self.mpris_trackid
     }
 
     /// The duration of the track in microseconds.
     ///
     /// Present only if length is known.
-    pub fn length(&mut self) -> Option<&mut i64> {
-        self.mpris_length.as_mut()
+    pub fn length(&self) -> Option<i64> {
+        self.mpris_length
     }
 
     /// The location of an image representing the track or album. Clients should not assume this
     /// will continue to exist when the media player stops giving out the URL.
-    pub fn art_url(&mut self) -> Option<&mut String> {
-        self.mpris_art_url.as_mut()
+    pub fn art_url(&self) -> Option<&str> {
+        self.mpris_art_url.as_deref()
     }
 
     /// Album name
-    pub fn album(&mut self) -> Option<&mut String> {
-        self.xesam_album.as_mut()
+    pub fn album(&self) -> Option<&str> {
+        self.xesam_album.as_deref()
     }
 
     /// The album artist(s).
-    pub fn album_artist(&mut self) -> Option<&mut Vec<String>> {
-        self.xesam_album_artist.as_mut()
+    pub fn album_artist(&self) -> Option<&[String]> {
+        self.xesam_album_artist.as_deref()
     }
 
     /// The track artist(s).
-    pub fn artist(&mut self) -> Option<&mut Vec<String>> {
-        self.xesam_artist.as_mut()
+    pub fn artist(&self) -> Option<&[String]> {
+        self.xesam_artist.as_deref()
     }
 
     /// The track lyrics.
-    pub fn as_text(&mut self) -> Option<&mut String> {
-        self.xesam_as_text.as_mut()
+    pub fn as_text(&self) -> Option<&str> {
+        self.xesam_as_text.as_deref()
     }
 
     /// The speed of the music, in beats per minute.
-    pub fn audio_bpm(&mut self) -> Option<&mut i32> {
-        self.xesam_audio_bpm.as_mut()
+    pub fn audio_bpm(&self) -> Option<i32> {
+        self.xesam_audio_bpm
     }
 
     /// An automatically-generated rating, based on things such as how often it has been played.
     /// This should be in the range 0.0 to 1.0.
-    pub fn auto_rating(&mut self) -> Option<&mut f64> {
-        self.xesam_auto_rating.as_mut()
+    pub fn auto_rating(&self) -> Option<f64> {
+        self.xesam_auto_rating
     }
 
     /// A (list of) freeform comment(s).
-    pub fn comment(&mut self) -> Option<&mut Vec<String>> {
-        self.xesam_comment.as_mut()
+    pub fn comment(&self) -> Option<&[String]> {
+        self.xesam_comment.as_deref()
     }
 
     /// The composer(s) of the track.
-    pub fn composer(&mut self) -> Option<&mut Vec<String>> {
-        self.xesam_composer.as_mut()
+    pub fn composer(&self) -> Option<&[String]> {
+        self.xesam_composer.as_deref()
     }
 
     /// When the track was created. Usually only the year component will be useful.
-    pub fn content_created(&mut self) -> Option<&mut String> {
-        self.xesam_content_created.as_mut()
+    pub fn content_created(&self) -> Option<&str> {
+        self.xesam_content_created.as_deref()
     }
 
     /// The disc number on the album that this track is from.
@@ -236,52 +353,52 @@ impl Metadata {
     }
 
     /// When the track was first played.
-    pub fn first_used(&mut self) -> Option<&mut String> {
-        self.xesam_first_used.as_mut()
+    pub fn first_used(&self) -> Option<&str> {
+        self.xesam_first_used.as_deref()
     }
 
     /// The genre(s) of the track.
-    pub fn genre(&mut self) -> Option<&mut Vec<String>> {
-        self.xesam_genre.as_mut()
+    pub fn genre(&self) -> Option<&[String]> {
+        self.xesam_genre.as_deref()
     }
 
     /// When the track was last played.
-    pub fn last_used(&mut self) -> Option<&mut String> {
-        self.xesam_last_used.as_mut()
+    pub fn last_used(&self) -> Option<&str> {
+        self.xesam_last_used.as_deref()
     }
 
     /// The lyricist(s) of the track.
-    pub fn lyricist(&mut self) -> Option<&mut Vec<String>> {
-        self.xesam_lyricist.as_mut()
+    pub fn lyricist(&self) -> Option<&[String]> {
+        self.xesam_lyricist.as_deref()
     }
 
     /// The track title.
-    pub fn title(&mut self) -> Option<&mut String> {
-        self.xesam_title.as_mut()
+    pub fn title(&self) -> Option<&str> {
+        self.xesam_title.as_deref()
     }
 
     /// The track number on the album disc.
-    pub fn track_number(&mut self) -> Option<&mut i32> {
-        self.xesam_track_number.as_mut()
+    pub fn track_number(&self) -> Option<i32> {
+        self.xesam_track_number
     }
 
     /// The location of the media file.
-    pub fn url(&mut self) -> Option<&mut String> {
-        self.xesam_url.as_mut()
+    pub fn url(&self) -> Option<&str> {
+        self.xesam_url.as_deref()
     }
 
     /// The number of times the track has been played.
-    pub fn use_count(&mut self) -> Option<&mut i32> {
-        self.xesam_use_count.as_mut()
+    pub fn use_count(&self) -> Option<i32> {
+        self.xesam_use_count
     }
 
     /// A user-specified rating. This should be in the range 0.0 to 1.0.
-    pub fn user_rating(&mut self) -> Option<&mut f64> {
-        self.xesam_user_rating.as_mut()
+    pub fn user_rating(&self) -> Option<f64> {
+        self.xesam_user_rating
     }
 }
 
-#[derive(Type, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Type, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
 #[zvariant(signature = "s")]
 pub enum PlaybackStatus {
     Playing,
@@ -315,7 +432,7 @@ impl TryFrom<String> for PlaybackStatus {
     }
 }
 
-#[derive(Type, Deserialize, Debug)]
+#[derive(Type, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
 #[zvariant(signature = "s")]
 pub enum LoopStatus {
     None,
@@ -323,52 +440,154 @@ pub enum LoopStatus {
     Playlist,
 }
 
-#[derive(Deserialize, Type, Debug)]
+impl TryFrom<String> for LoopStatus {
+    type Error = &'static str;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "None" => Ok(Self::None),
+            "Track" => Ok(Self::Track),
+            "Playlist" => Ok(Self::Playlist),
+            _ => Err("Invalid loop status"),
+        }
+    }
+}
+
+impl PlaybackStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlaybackStatus::Playing => "Playing",
+            PlaybackStatus::Paused => "Paused",
+            PlaybackStatus::Stopped => "Stopped",
+        }
+    }
+}
+
+impl std::fmt::Display for PlaybackStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl LoopStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoopStatus::None => "None",
+            LoopStatus::Track => "Track",
+            LoopStatus::Playlist => "Playlist",
+        }
+    }
+}
+
+impl std::fmt::Display for LoopStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Deserialize, Serialize, Type, Clone, Debug)]
 #[zvariant(signature = "dict")]
 #[serde(rename_all(deserialize = "PascalCase"))]
 pub struct Properties {
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "as_value::deserialize")]
     pub playback_status: PlaybackStatus,
 
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        deserialize_with = "optional::deserialize",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     pub loop_status: Option<LoopStatus>,
 
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        deserialize_with = "try_as_optional::deserialize",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     pub rate: Option<f64>,
 
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        deserialize_with = "optional::deserialize",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     pub shuffle: Option<bool>,
 
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "as_value::deserialize")]
     pub metadata: Metadata,
 
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        deserialize_with = "try_as_optional::deserialize",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     pub volume: Option<f64>,
 
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "try_as_value::deserialize")]
     pub position: i64,
 
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        deserialize_with = "try_as_optional::deserialize",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     pub minimum_rate: Option<f64>,
 
-    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    #[serde(
+        deserialize_with = "try_as_optional::deserialize",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
     pub maximum_rate: Option<f64>,
 
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "as_value::deserialize")]
     pub can_go_next: bool,
 
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "as_value::deserialize")]
     pub can_go_previous: bool,
 
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "as_value::deserialize")]
     pub can_play: bool,
 
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "as_value::deserialize")]
     pub can_pause: bool,
 
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "as_value::deserialize")]
     pub can_seek: bool,
 
-    #[serde(with = "as_value")]
+    #[serde(deserialize_with = "as_value::deserialize")]
     pub can_control: bool,
 }
+
+#[cfg(test)]
+impl Properties {
+    /// A player that can do anything, at `position` µs into the track `trackid`.
+    pub(crate) fn test(
+        playback_status: PlaybackStatus,
+        trackid: &str,
+        length: Option<i64>,
+        position: i64,
+    ) -> Self {
+        Self {
+            playback_status,
+            loop_status: None,
+            rate: None,
+            shuffle: None,
+            metadata: Metadata {
+                mpris_trackid: trackid.to_string(),
+                mpris_length: length,
+                ..Metadata::default()
+            },
+            volume: None,
+            position,
+            minimum_rate: None,
+            maximum_rate: None,
+            can_go_next: true,
+            can_go_previous: true,
+            can_play: true,
+            can_pause: true,
+            can_seek: true,
+            can_control: true,
+        }
+    }
+}
@@ -5,10 +5,13 @@
 //!
 //! This is useful for when you are deserializing something is similar in type, but changes to some
 //! other similar type somehow. For example, dict entry "mpris:length" has value type `int64` for
-//! Firefox, but `uint64` for Spotify, so we try parsing the `uint64` into `int64` instead.
+//! Firefox, but `uint64` for Spotify, so we try parsing the `uint64` into `int64` instead. See
+//! [`Coerce`] for every conversion.
 
+mod coerce;
 mod deserialize;
-pub use deserialize::{Deserialize, deserialize};
+pub use coerce::Coerce;
+pub use deserialize::deserialize;
 
 pub mod try_as_optional {
     use super::*;
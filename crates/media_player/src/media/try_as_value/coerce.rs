@@ -0,0 +1,100 @@
+use zbus::zvariant::Value;
+
+use crate::media::properties::MetadataValue;
+
+/// Converts a value of whichever type a player sent into `Self`, if it's similar enough.
+///
+/// - Strings can also be object paths, e.g. `mpris:trackid` is a string for some players.
+/// - Lists of strings can also be a single string, e.g. `xesam:artist` for some players.
+/// - Integers can be any integer type in range, e.g. `xesam:trackNumber` as `uint32` or `int64`,
+///   a float without a fractional part, or a string of digits.
+/// - Floats can also be integers, e.g. ratings.
+///
+/// Values wrapped in more variants are unwrapped first.
+pub trait Coerce: Sized {
+    fn coerce(value: &Value<'_>) -> Option<Self>;
+}
+
+impl Coerce for String {
+    fn coerce(value: &Value<'_>) -> Option<Self> {
+        match value {
+            Value::Value(value) => Self::coerce(value),
+            Value::Str(value) => Some(value.to_string()),
+            Value::ObjectPath(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl Coerce for Vec<String> {
+    fn coerce(value: &Value<'_>) -> Option<Self> {
+        match value {
+            Value::Value(value) => Self::coerce(value),
+            // e.g. `as`, or `av` of strings
+            Value::Array(array) => array.iter().map(String::coerce).collect(),
+            value => String::coerce(value).map(|value| vec![value]),
+        }
+    }
+}
+
+impl Coerce for i64 {
+    fn coerce(value: &Value<'_>) -> Option<Self> {
+        match value {
+            Value::Value(value) => Self::coerce(value),
+            Value::U8(value) => Some((*value).into()),
+            Value::I16(value) => Some((*value).into()),
+            Value::U16(value) => Some((*value).into()),
+            Value::I32(value) => Some((*value).into()),
+            Value::U32(value) => Some((*value).into()),
+            Value::I64(value) => Some(*value),
+            Value::U64(value) => (*value).try_into().ok(),
+            Value::F64(value) if value.fract() == 0.0 && value.abs() < i64::MAX as f64 => {
+                Some(*value as i64)
+            }
+            Value::Str(value) => value.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl Coerce for i32 {
+    fn coerce(value: &Value<'_>) -> Option<Self> {
+        i64::coerce(value)?.try_into().ok()
+    }
+}
+
+impl Coerce for f64 {
+    fn coerce(value: &Value<'_>) -> Option<Self> {
+        match value {
+            Value::Value(value) => Self::coerce(value),
+            Value::F64(value) => Some(*value),
+            Value::Str(value) => value.trim().parse().ok(),
+            value => i64::coerce(value).map(|value| value as f64),
+        }
+    }
+}
+
+impl Coerce for bool {
+    fn coerce(value: &Value<'_>) -> Option<Self> {
+        match value {
+            Value::Value(value) => Self::coerce(value),
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl Coerce for MetadataValue {
+    fn coerce(value: &Value<'_>) -> Option<Self> {
+        match value {
+            Value::Value(value) => Self::coerce(value),
+            Value::Bool(value) => Some(MetadataValue::Bool(*value)),
+            Value::F64(value) => Some(MetadataValue::Float(*value)),
+            Value::Str(_) | Value::ObjectPath(_) => {
+                String::coerce(value).map(MetadataValue::String)
+            }
+            Value::Array(_) => Vec::<String>::coerce(value).map(MetadataValue::Strings),
+            value => i64::coerce(value).map(MetadataValue::Integer),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Tracks a player's playback position without asking the player for it all the time.
+//!
+//! MPRIS doesn't signal changes of `Position`, as it changes continuously while playing. Instead,
+//! the position is interpolated from the last known position, `Rate` and `PlaybackStatus`, and
+//! synced again when the player signals `Seeked`, changes track, or changes status, as well as
+//! every [`VERIFY_INTERVAL`] while playing, to correct drift.
+//!
+//! The position reported by a paused player isn't trusted, as e.g. Firefox keeps incrementing it
+//! while paused (<https://bugzilla.mozilla.org/show_bug.cgi?id=1950461>). It's only synced when
+//! the player pauses, and stays where it was until the player plays or seeks again.
+
+use std::time::{Duration, Instant};
+
+use crate::media::properties::{PlaybackStatus, Properties};
+
+/// How often the position of a playing player should be gotten from the player again.
+pub const VERIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+
+#[derive(Clone, Debug)]
+pub struct PositionTracker {
+    /// In microseconds, as of `synced_at`.
+    position: i64,
+    synced_at: Instant,
+    /// When the position was last gotten from the player.
+    verified_at: Instant,
+    status: PlaybackStatus,
+    rate: f64,
+    trackid: String,
+    /// In microseconds, if known.
+    length: Option<i64>,
+}
+
+impl PositionTracker {
+    /// Starts tracking from `properties`, which were just gotten from the player.
+    pub fn new(properties: &Properties, now: Instant) -> Self {
+        let metadata = &properties.metadata;
+        Self {
+            position: properties.position,
+            synced_at: now,
+            verified_at: now,
+            status: properties.playback_status,
+            rate: properties.rate.unwrap_or(1.0),
+            trackid: metadata.trackid().to_string(),
+            length: metadata.length(),
+        }
+    }
+
+    /// The interpolated position at `now`, in microseconds, clamped to the track's length if
+    /// it's known.
+    pub fn position(&self, now: Instant) -> i64 {
+        let mut position = self.position;
+        if self.status == PlaybackStatus::Playing {
+            let elapsed = now.saturating_duration_since(self.synced_at).as_micros() as f64;
+            position += (elapsed * self.rate) as i64;
+        }
+        match self.length {
+            Some(length) if length > 0 => position.clamp(0, length),
+            _ => position.max(0),
+        }
+    }
+
+    /// Applies `properties`, which were just gotten from the player after it signalled that they
+    /// changed.
+    ///
+    /// The reported position is used if the player is playing, or if its track, status or rate
+    /// changed, and ignored otherwise.
+    pub fn update(&mut self, properties: &Properties, now: Instant) {
+        let metadata = &properties.metadata;
+        let rate = properties.rate.unwrap_or(1.0);
+        let resync = properties.playback_status == PlaybackStatus::Playing
+            || properties.playback_status != self.status
+            || rate != self.rate
+            || metadata.trackid() != self.trackid;
+        if resync {
+            self.sync(properties.position, now);
+        } else {
+            // Keep the interpolated position, as of now.
+            self.sync(self.position(now), now);
+        }
+        self.status = properties.playback_status;
+        self.rate = rate;
+        self.trackid = metadata.trackid().to_string();
+        self.length = metadata.length();
+    }
+
+    /// Applies a `Seeked` signal, whose position is always trusted.
+    pub fn seeked(&mut self, position: i64, now: Instant) {
+        self.sync(position, now);
+    }
+
+    /// Whether the position should be gotten from the player again with [`Self::verify`], i.e.
+    /// the player is playing and it's been at least [`VERIFY_INTERVAL`] since it was last gotten.
+    pub fn needs_verification(&self, now: Instant) -> bool {
+        self.status == PlaybackStatus::Playing
+            && now.saturating_duration_since(self.verified_at) >= VERIFY_INTERVAL
+    }
+
+    /// Applies the position that was just gotten from the player. It's ignored unless the player
+    /// is playing.
+    pub fn verify(&mut self, position: i64, now: Instant) {
+        if self.status == PlaybackStatus::Playing {
+            self.sync(position, now);
+        }
+    }
+
+    /// How long until the interpolated position reaches its next whole second, or `None` if it
+    /// isn't moving. Useful to update a displayed position right as it changes.
+    pub fn until_next_second(&self, now: Instant) -> Option<Duration> {
+        if self.status != PlaybackStatus::Playing || self.rate <= 0.0 {
+            return None;
+        }
+        let position = self.position(now);
+        if self
+            .length
+            .is_some_and(|length| length > 0 && position >= length)
+        {
+            return None;
+        }
+        let remaining = MICROS_PER_SECOND - position.rem_euclid(MICROS_PER_SECOND);
+        Some(Duration::from_micros(
+            (remaining as f64 / self.rate).ceil() as u64
+        ))
+    }
+
+    fn sync(&mut self, position: i64, now: Instant) {
+        self.position = position;
+        self.synced_at = now;
+        self.verified_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i64 = MICROS_PER_SECOND;
+
+    fn properties(status: PlaybackStatus, position: i64) -> Properties {
+        Properties::test(status, "/track/1", Some(200 * SECOND), position)
+    }
+
+    fn after(start: Instant, micros: i64) -> Instant {
+        start + Duration::from_micros(micros as u64)
+    }
+
+    #[test]
+    fn interpolates_while_playing() {
+        let start = Instant::now();
+        let tracker =
+            PositionTracker::new(&properties(PlaybackStatus::Playing, 10 * SECOND), start);
+        assert_eq!(tracker.position(start), 10 * SECOND);
+        assert_eq!(tracker.position(after(start, 2 * SECOND)), 12 * SECOND);
+
+        let tracker = PositionTracker::new(&properties(PlaybackStatus::Paused, 10 * SECOND), start);
+        assert_eq!(tracker.position(after(start, 2 * SECOND)), 10 * SECOND);
+    }
+
+    #[test]
+    fn interpolates_with_rate() {
+        let start = Instant::now();
+        let mut properties = properties(PlaybackStatus::Playing, 10 * SECOND);
+        properties.rate = Some(1.5);
+        let mut tracker = PositionTracker::new(&properties, start);
+        assert_eq!(tracker.position(after(start, 2 * SECOND)), 13 * SECOND);
+
+        // A rate change resyncs from the reported position.
+        properties.rate = Some(0.5);
+        properties.position = 20 * SECOND;
+        tracker.update(&properties, after(start, 2 * SECOND));
+        assert_eq!(tracker.position(after(start, 4 * SECOND)), 21 * SECOND);
+    }
+
+    #[test]
+    fn clamps_to_length() {
+        let start = Instant::now();
+        let tracker =
+            PositionTracker::new(&properties(PlaybackStatus::Playing, 199 * SECOND), start);
+        assert_eq!(tracker.position(after(start, 5 * SECOND)), 200 * SECOND);
+
+        let tracker = PositionTracker::new(&properties(PlaybackStatus::Paused, -SECOND), start);
+        assert_eq!(tracker.position(start), 0);
+
+        let unknown_length = Properties::test(PlaybackStatus::Playing, "/track/1", None, 0);
+        let tracker = PositionTracker::new(&unknown_length, start);
+        assert_eq!(tracker.position(after(start, 500 * SECOND)), 500 * SECOND);
+    }
+
+    #[test]
+    fn ignores_position_of_paused_player() {
+        let start = Instant::now();
+        let mut tracker =
+            PositionTracker::new(&properties(PlaybackStatus::Playing, 10 * SECOND), start);
+        // Pausing syncs to the reported position.
+        tracker.update(
+            &properties(PlaybackStatus::Paused, 12 * SECOND),
+            after(start, 2 * SECOND),
+        );
+        assert_eq!(tracker.position(after(start, 3 * SECOND)), 12 * SECOND);
+
+        // Later changes of a paused player, e.g. of its volume, report a drifting position.
+        tracker.update(
+            &properties(PlaybackStatus::Paused, 15 * SECOND),
+            after(start, 5 * SECOND),
+        );
+        assert_eq!(tracker.position(after(start, 6 * SECOND)), 12 * SECOND);
+        tracker.verify(16 * SECOND, after(start, 6 * SECOND));
+        assert_eq!(tracker.position(after(start, 6 * SECOND)), 12 * SECOND);
+
+        tracker.update(
+            &properties(PlaybackStatus::Playing, 12 * SECOND),
+            after(start, 7 * SECOND),
+        );
+        assert_eq!(tracker.position(after(start, 8 * SECOND)), 13 * SECOND);
+    }
+
+    #[test]
+    fn resyncs_on_seeked() {
+        let start = Instant::now();
+        let mut tracker =
+            PositionTracker::new(&properties(PlaybackStatus::Paused, 10 * SECOND), start);
+        tracker.seeked(50 * SECOND, after(start, SECOND));
+        assert_eq!(tracker.position(after(start, 2 * SECOND)), 50 * SECOND);
+
+        let mut tracker =
+            PositionTracker::new(&properties(PlaybackStatus::Playing, 10 * SECOND), start);
+        tracker.seeked(50 * SECOND, after(start, SECOND));
+        assert_eq!(tracker.position(after(start, 2 * SECOND)), 51 * SECOND);
+    }
+
+    #[test]
+    fn resyncs_on_track_change() {
+        let start = Instant::now();
+        let mut tracker =
+            PositionTracker::new(&properties(PlaybackStatus::Paused, 100 * SECOND), start);
+        let next_track = Properties::test(PlaybackStatus::Paused, "/track/2", Some(SECOND * 60), 0);
+        tracker.update(&next_track, after(start, SECOND));
+        assert_eq!(tracker.position(after(start, 2 * SECOND)), 0);
+
+        // The new track's length is used for clamping.
+        tracker.seeked(100 * SECOND, after(start, 2 * SECOND));
+        assert_eq!(tracker.position(after(start, 2 * SECOND)), 60 * SECOND);
+    }
+
+    #[test]
+    fn needs_verification_after_interval() {
+        let start = Instant::now();
+        let interval = VERIFY_INTERVAL.as_micros() as i64;
+        let mut tracker = PositionTracker::new(&properties(PlaybackStatus::Playing, 0), start);
+        assert!(!tracker.needs_verification(after(start, interval - 1)));
+        assert!(tracker.needs_verification(after(start, interval)));
+
+        // Verifying corrects drift and restarts the interval.
+        tracker.verify(4 * SECOND, after(start, interval));
+        assert_eq!(tracker.position(after(start, interval)), 4 * SECOND);
+        assert!(!tracker.needs_verification(after(start, interval + 1)));
+        assert!(tracker.needs_verification(after(start, 2 * interval)));
+
+        // Any sync restarts it.
+        tracker.seeked(0, after(start, 2 * interval));
+        assert!(!tracker.needs_verification(after(start, 2 * interval + 1)));
+
+        let tracker = PositionTracker::new(&properties(PlaybackStatus::Paused, 0), start);
+        assert!(!tracker.needs_verification(after(start, 2 * interval)));
+    }
+
+    #[test]
+    fn until_next_second() {
+        let start = Instant::now();
+        let tracker = PositionTracker::new(
+            &properties(PlaybackStatus::Playing, 10 * SECOND + 250_000),
+            start,
+        );
+        assert_eq!(
+            tracker.until_next_second(start),
+            Some(Duration::from_micros(750_000))
+        );
+        assert_eq!(
+            tracker.until_next_second(after(start, 750_000)),
+            Some(Duration::from_secs(1))
+        );
+
+        let mut properties = properties(PlaybackStatus::Playing, 10 * SECOND + 500_000);
+        properties.rate = Some(2.0);
+        let tracker = PositionTracker::new(&properties, start);
+        assert_eq!(
+            tracker.until_next_second(start),
+            Some(Duration::from_micros(250_000))
+        );
+    }
+
+    #[test]
+    fn no_next_second_when_not_moving() {
+        let start = Instant::now();
+        let paused = PositionTracker::new(&properties(PlaybackStatus::Paused, 0), start);
+        assert_eq!(paused.until_next_second(start), None);
+
+        let mut stopped_rate = properties(PlaybackStatus::Playing, 0);
+        stopped_rate.rate = Some(0.0);
+        let tracker = PositionTracker::new(&stopped_rate, start);
+        assert_eq!(tracker.until_next_second(start), None);
+
+        let ended = PositionTracker::new(&properties(PlaybackStatus::Playing, 200 * SECOND), start);
+        assert_eq!(ended.until_next_second(start), None);
+    }
+}
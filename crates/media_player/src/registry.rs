@@ -0,0 +1,651 @@
+//! Tracks which MPRIS players are on the session bus.
+//!
+//! Players are listed with `ListNames` on startup, and then followed with `NameOwnerChanged` as
+//! they appear and vanish. Every player is added with its initial state from `GetAll`, which is
+//! gotten again whenever the player signals `PropertiesChanged`, so that e.g. entries removed from
+//! `Metadata` on a track change don't linger. `Seeked` is followed as well, as `Position` changes
+//! aren't signalled otherwise.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, mpsc::Sender},
+    thread,
+};
+
+use futures_lite::{Stream, StreamExt, future, stream};
+use zbus::{
+    MatchRule, Message, MessageStream,
+    blocking::Connection,
+    fdo::DBusProxy,
+    message,
+    zvariant::{self, OwnedValue},
+};
+
+use crate::{
+    MPRIS_OBJECT_PATH, PLAYER_INTERFACE, PROPERTIES_INTERFACE,
+    media::{properties::Properties, try_as_value::Coerce},
+    properties_changed_rule,
+};
+
+/// Every MPRIS player's bus name starts with this, e.g. `org.mpris.MediaPlayer2.spotify`.
+pub const MPRIS_BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+#[derive(Clone, Debug)]
+pub struct Player {
+    /// e.g. `org.mpris.MediaPlayer2.spotify`
+    pub bus_name: String,
+    /// The unique name of the connection that owns `bus_name`, e.g. `:1.42`, which is the
+    /// sender of the player's signals.
+    pub unique_name: String,
+    /// The state of the player as of its last `PropertiesChanged` or `Seeked`. `Position` isn't
+    /// signalled otherwise, so it's only as of then; see [`PositionTracker`] to interpolate it.
+    ///
+    /// [`PositionTracker`]: crate::position::PositionTracker
+    pub properties: Properties,
+}
+
+#[derive(Clone, Debug)]
+pub enum PlayerEvent {
+    Added(Box<Player>),
+    /// The player signalled that its properties changed.
+    Changed(Box<Player>),
+    /// The player signalled that its position jumped, e.g. because the user seeked.
+    Seeked {
+        bus_name: String,
+        /// In microseconds.
+        position: i64,
+    },
+    Removed {
+        bus_name: String,
+        unique_name: String,
+    },
+}
+
+/// The players that are currently on the bus, kept up to date by the stream of
+/// [`watch`](Self::watch), or the thread of [`start`](Self::start).
+#[derive(Clone)]
+pub struct PlayerRegistry {
+    players: Arc<Mutex<HashMap<String, Player>>>,
+}
+
+/// A signal that the registry follows.
+enum Signal {
+    NameOwnerChanged(Message),
+    PropertiesChanged(Message),
+    Seeked(Message),
+}
+
+impl PlayerRegistry {
+    /// Adds the players that are already on the bus, and returns a stream of an event for
+    /// whenever a player is added, changed, or removed, starting with the players that are
+    /// already on the bus. The registry is only kept up to date while the stream is polled.
+    ///
+    /// Players that fail to return their properties aren't added.
+    pub async fn watch(
+        connection: &zbus::Connection,
+    ) -> zbus::Result<(
+        Self,
+        impl Stream<Item = PlayerEvent> + Send + 'static + use<>,
+    )> {
+        let (registry, added, signals) = Self::subscribe(connection).await?;
+        let follower = registry.clone();
+        let connection = connection.clone();
+        let events = signals
+            .then(move |signal| {
+                let (follower, connection) = (follower.clone(), connection.clone());
+                async move { follower.follow(&connection, signal).await }
+            })
+            .flat_map(stream::iter);
+        Ok((registry, stream::iter(added).chain(events)))
+    }
+
+    /// Like [`watch`](Self::watch), but sends the events to `sender` from a dedicated thread,
+    /// until the receiver is dropped. The players that are already on the bus are sent before
+    /// this returns.
+    ///
+    /// This and receiving from the channel block the calling thread, so async code should use
+    /// [`watch`](Self::watch) instead.
+    pub fn start(connection: &Connection, sender: Sender<PlayerEvent>) -> zbus::Result<Self> {
+        let (registry, events) = zbus::block_on(Self::watch(connection.inner()))?;
+        let mut events = Box::pin(events);
+        // The added players are ready without waiting for any signal.
+        while let Some(Some(event)) = zbus::block_on(future::poll_once(events.next())) {
+            if sender.send(event).is_err() {
+                return Ok(registry);
+            }
+        }
+        thread::Builder::new()
+            .name("media player registry".to_string())
+            .spawn(move || {
+                zbus::block_on(async {
+                    while let Some(event) = events.next().await {
+                        if sender.send(event).is_err() {
+                            return;
+                        }
+                    }
+                })
+            })
+            .map_err(|err| zbus::Error::InputOutput(Arc::new(err)))?;
+        Ok(registry)
+    }
+
+    /// Subscribes to the signals that are followed, and adds the players that are already on
+    /// the bus, returning the events for them.
+    async fn subscribe(
+        connection: &zbus::Connection,
+    ) -> zbus::Result<(Self, Vec<PlayerEvent>, impl Stream<Item = Signal> + use<>)> {
+        let name_owner_changed_rule = MatchRule::builder()
+            .msg_type(message::Type::Signal)
+            .sender("org.freedesktop.DBus")?
+            .interface("org.freedesktop.DBus")?
+            .member("NameOwnerChanged")?
+            .arg0ns("org.mpris.MediaPlayer2")?
+            .build();
+        // Subscribe before listing the names, so that a player appearing in between isn't missed.
+        let name_owner_changed =
+            MessageStream::for_match_rule(name_owner_changed_rule, connection, None).await?;
+
+        let properties_changed =
+            MessageStream::for_match_rule(properties_changed_rule()?, connection, None).await?;
+
+        let seeked_rule = MatchRule::builder()
+            .msg_type(message::Type::Signal)
+            .interface(PLAYER_INTERFACE)?
+            .member("Seeked")?
+            .path(MPRIS_OBJECT_PATH)?
+            .build();
+        let seeked = MessageStream::for_match_rule(seeked_rule, connection, None).await?;
+
+        // Name owner changes come first, so that a player is added before its other signals.
+        let signals = name_owner_changed
+            .filter_map(|message| message.ok().map(Signal::NameOwnerChanged))
+            .or(properties_changed
+                .filter_map(|message| message.ok().map(Signal::PropertiesChanged)))
+            .or(seeked.filter_map(|message| message.ok().map(Signal::Seeked)));
+
+        let registry = Self {
+            players: Arc::default(),
+        };
+        let mut added = Vec::new();
+        let dbus = DBusProxy::new(connection).await?;
+        for bus_name in dbus.list_names().await? {
+            if !bus_name.starts_with(MPRIS_BUS_NAME_PREFIX) {
+                continue;
+            }
+            // The player may have vanished since it was listed.
+            let Ok(unique_name) = dbus.get_name_owner(bus_name.as_ref()).await else {
+                continue;
+            };
+            added.extend(
+                registry
+                    .add(connection, bus_name.to_string(), unique_name.to_string())
+                    .await,
+            );
+        }
+        Ok((registry, added, signals))
+    }
+
+    /// Updates the players from `signal`, returning the events for the players it changed.
+    async fn follow(&self, connection: &zbus::Connection, signal: Signal) -> Vec<PlayerEvent> {
+        match signal {
+            Signal::NameOwnerChanged(message) => {
+                self.follow_name_owner_change(connection, &message).await
+            }
+            Signal::PropertiesChanged(message) => {
+                let Some(unique_name) = message.header().sender().map(|name| name.to_string())
+                else {
+                    return Vec::new();
+                };
+                self.update(connection, &unique_name).await
+            }
+            Signal::Seeked(message) => self.follow_seek(&message),
+        }
+    }
+
+    /// Adds and removes a player as its name changes owners.
+    async fn follow_name_owner_change(
+        &self,
+        connection: &zbus::Connection,
+        message: &Message,
+    ) -> Vec<PlayerEvent> {
+        let Ok((bus_name, old_owner, new_owner)) =
+            message.body().deserialize::<(String, String, String)>()
+        else {
+            return Vec::new();
+        };
+        if !bus_name.starts_with(MPRIS_BUS_NAME_PREFIX) {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        // An empty owner means that the name had no owner, so a player that changes owners is
+        // removed and then added again.
+        if !old_owner.is_empty() {
+            events.extend(self.remove(&bus_name));
+        }
+        if !new_owner.is_empty() {
+            events.extend(self.add(connection, bus_name, new_owner).await);
+        }
+        events
+    }
+
+    /// Updates the position of the players that signalled a seek.
+    fn follow_seek(&self, message: &Message) -> Vec<PlayerEvent> {
+        let Some(unique_name) = message.header().sender().map(|name| name.to_string()) else {
+            return Vec::new();
+        };
+        let Ok(position) = message.body().deserialize::<i64>() else {
+            return Vec::new();
+        };
+        let mut players = self.lock();
+        players
+            .values_mut()
+            .filter(|player| player.unique_name == unique_name)
+            .map(|player| {
+                player.properties.position = position;
+                PlayerEvent::Seeked {
+                    bus_name: player.bus_name.clone(),
+                    position,
+                }
+            })
+            .collect()
+    }
+
+    /// The players that are currently on the bus, in no particular order.
+    pub fn players(&self) -> Vec<Player> {
+        self.lock().values().cloned().collect()
+    }
+
+    pub fn player(&self, bus_name: &str) -> Option<Player> {
+        self.lock().get(bus_name).cloned()
+    }
+
+    /// The player owned by the connection named `unique_name`, i.e. the sender of a signal.
+    pub fn player_by_unique_name(&self, unique_name: &str) -> Option<Player> {
+        self.lock()
+            .values()
+            .find(|player| player.unique_name == unique_name)
+            .cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Player>> {
+        // The map is always in a valid state, even if a thread panicked while holding the lock.
+        self.players
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Gets the player's properties and adds it, unless it's already added.
+    async fn add(
+        &self,
+        connection: &zbus::Connection,
+        bus_name: String,
+        unique_name: String,
+    ) -> Option<PlayerEvent> {
+        if self.lock().contains_key(&bus_name) {
+            return None;
+        }
+        let properties = get_all_properties(connection, &bus_name).await.ok()?;
+        let player = Player {
+            bus_name: bus_name.clone(),
+            unique_name,
+            properties,
+        };
+        self.lock().insert(bus_name, player.clone());
+        Some(PlayerEvent::Added(Box::new(player)))
+    }
+
+    /// Gets the properties of the players owned by `unique_name` again.
+    async fn update(&self, connection: &zbus::Connection, unique_name: &str) -> Vec<PlayerEvent> {
+        let bus_names = self
+            .lock()
+            .values()
+            .filter(|player| player.unique_name == unique_name)
+            .map(|player| player.bus_name.clone())
+            .collect::<Vec<_>>();
+        let mut events = Vec::new();
+        for bus_name in bus_names {
+            let Ok(properties) = get_all_properties(connection, &bus_name).await else {
+                continue;
+            };
+            let mut players = self.lock();
+            // The player may have been removed in the meantime.
+            let Some(player) = players.get_mut(&bus_name) else {
+                continue;
+            };
+            player.properties = properties;
+            events.push(PlayerEvent::Changed(Box::new(player.clone())));
+        }
+        events
+    }
+
+    fn remove(&self, bus_name: &str) -> Option<PlayerEvent> {
+        let player = self.lock().remove(bus_name)?;
+        Some(PlayerEvent::Removed {
+            bus_name: player.bus_name,
+            unique_name: player.unique_name,
+        })
+    }
+}
+
+/// Every property of `org.mpris.MediaPlayer2.Player` of the player at `bus_name`.
+pub async fn get_all_properties(
+    connection: &zbus::Connection,
+    bus_name: &str,
+) -> zbus::Result<Properties> {
+    let reply = connection
+        .call_method(
+            Some(bus_name),
+            MPRIS_OBJECT_PATH,
+            Some(PROPERTIES_INTERFACE),
+            "GetAll",
+            &(PLAYER_INTERFACE),
+        )
+        .await?;
+    reply.body().deserialize()
+}
+
+/// The current `Position` of the player at `bus_name`, in microseconds.
+pub async fn get_position(connection: &zbus::Connection, bus_name: &str) -> zbus::Result<i64> {
+    let reply = connection
+        .call_method(
+            Some(bus_name),
+            MPRIS_OBJECT_PATH,
+            Some(PROPERTIES_INTERFACE),
+            "Get",
+            &(PLAYER_INTERFACE, "Position"),
+        )
+        .await?;
+    let value = reply.body().deserialize::<OwnedValue>()?;
+    // Like the `Position` of `GetAll`, which some players send as another integer type
+    i64::coerce(&value).ok_or(zbus::Error::Variant(zvariant::Error::IncorrectType))
+}
+
+/// Blocking versions of the functions of [`registry`](super), for code that doesn't run on an
+/// async runtime.
+pub mod blocking {
+    use zbus::blocking::Connection;
+
+    use crate::media::properties::Properties;
+
+    /// Every property of `org.mpris.MediaPlayer2.Player` of the player at `bus_name`.
+    pub fn get_all_properties(connection: &Connection, bus_name: &str) -> zbus::Result<Properties> {
+        zbus::block_on(super::get_all_properties(connection.inner(), bus_name))
+    }
+
+    /// The current `Position` of the player at `bus_name`, in microseconds.
+    pub fn get_position(connection: &Connection, bus_name: &str) -> zbus::Result<i64> {
+        zbus::block_on(super::get_position(connection.inner(), bus_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        time::Duration,
+    };
+
+    use zbus::{
+        connection, fdo::RequestNameFlags, interface, object_server::SignalEmitter,
+        zvariant::ObjectPath,
+    };
+
+    use super::*;
+
+    const FAKE_BUS_NAME: &str = "org.mpris.MediaPlayer2.fake";
+
+    /// A private bus from `dbus-daemon`, stopped when dropped.
+    struct Bus {
+        process: Child,
+        address: String,
+    }
+
+    impl Bus {
+        fn start() -> Self {
+            let mut process = Command::new("dbus-daemon")
+                .args(["--session", "--print-address", "--nofork"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("dbus-daemon should be installed");
+            let mut address = String::new();
+            BufReader::new(process.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            Self {
+                process,
+                address: address.trim().to_string(),
+            }
+        }
+
+        async fn connect(&self) -> zbus::Connection {
+            connection::Builder::address(self.address.as_str())
+                .unwrap()
+                .build()
+                .await
+                .unwrap()
+        }
+
+        /// Connects a [`FakePlayer`] playing `title`, which owns `bus_name` if set.
+        async fn player(&self, bus_name: Option<&str>, title: &str) -> zbus::Connection {
+            let mut builder = connection::Builder::address(self.address.as_str())
+                .unwrap()
+                .serve_at(
+                    MPRIS_OBJECT_PATH,
+                    FakePlayer {
+                        title: title.to_string(),
+                    },
+                )
+                .unwrap();
+            if let Some(bus_name) = bus_name {
+                builder = builder.name(bus_name).unwrap();
+            }
+            builder.build().await.unwrap()
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    /// The properties of `org.mpris.MediaPlayer2.Player` that a player must have.
+    struct FakePlayer {
+        title: String,
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl FakePlayer {
+        #[zbus(property)]
+        fn playback_status(&self) -> &str {
+            "Playing"
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<&str, OwnedValue> {
+            HashMap::from([
+                (
+                    "mpris:trackid",
+                    OwnedValue::from(ObjectPath::from_static_str_unchecked("/track/1")),
+                ),
+                (
+                    "xesam:title",
+                    zvariant::Value::from(self.title.as_str())
+                        .try_into()
+                        .unwrap(),
+                ),
+            ])
+        }
+
+        /// Unsigned, like some players send it, instead of the spec's `x`.
+        #[zbus(property(emits_changed_signal = "false"))]
+        fn position(&self) -> u64 {
+            10_000_000
+        }
+
+        #[zbus(property)]
+        fn can_go_next(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_go_previous(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_play(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_pause(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_seek(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_control(&self) -> bool {
+            true
+        }
+
+        #[zbus(signal)]
+        async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+    }
+
+    async fn next_event(events: &mut (impl Stream<Item = PlayerEvent> + Unpin)) -> PlayerEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .expect("an event should be sent")
+            .unwrap()
+    }
+
+    fn title(player: &Player) -> Option<&str> {
+        player.properties.metadata.title()
+    }
+
+    #[tokio::test]
+    async fn players_are_added_changed_and_removed() {
+        let bus = Bus::start();
+        let first = bus.player(Some(FAKE_BUS_NAME), "First").await;
+        // Not a player, despite serving the interface
+        let _other = bus.player(Some("org.example.NotAPlayer"), "Other").await;
+
+        let connection = bus.connect().await;
+        let (registry, events) = PlayerRegistry::watch(&connection).await.unwrap();
+        let mut events = Box::pin(events);
+        let PlayerEvent::Added(player) = next_event(&mut events).await else {
+            panic!("the player on the bus should be added first");
+        };
+        assert_eq!(player.bus_name, FAKE_BUS_NAME);
+        assert_eq!(player.unique_name, first.unique_name().unwrap().as_str());
+        assert_eq!(title(&player), Some("First"));
+        assert_eq!(player.properties.position, 10_000_000);
+
+        let second_name = format!("{}second", MPRIS_BUS_NAME_PREFIX);
+        let second = bus.player(Some(&second_name), "Second").await;
+        let PlayerEvent::Added(player) = next_event(&mut events).await else {
+            panic!("a new player should be added");
+        };
+        assert_eq!(player.bus_name, second_name);
+        assert_eq!(registry.players().len(), 2);
+
+        let fake = first
+            .object_server()
+            .interface::<_, FakePlayer>(MPRIS_OBJECT_PATH)
+            .await
+            .unwrap();
+        fake.get_mut().await.title = "Changed".to_string();
+        fake.get()
+            .await
+            .metadata_changed(fake.signal_emitter())
+            .await
+            .unwrap();
+        let PlayerEvent::Changed(player) = next_event(&mut events).await else {
+            panic!("the player should be changed");
+        };
+        assert_eq!(player.bus_name, FAKE_BUS_NAME);
+        assert_eq!(title(&player), Some("Changed"));
+
+        FakePlayer::seeked(fake.signal_emitter(), 42_000_000)
+            .await
+            .unwrap();
+        let PlayerEvent::Seeked { bus_name, position } = next_event(&mut events).await else {
+            panic!("the player should have seeked");
+        };
+        assert_eq!(bus_name, FAKE_BUS_NAME);
+        assert_eq!(position, 42_000_000);
+        assert_eq!(
+            registry.player(FAKE_BUS_NAME).unwrap().properties.position,
+            42_000_000
+        );
+
+        let unique_name = second.unique_name().unwrap().to_string();
+        drop(second);
+        let PlayerEvent::Removed {
+            bus_name,
+            unique_name: removed,
+        } = next_event(&mut events).await
+        else {
+            panic!("the player should be removed");
+        };
+        assert_eq!(bus_name, second_name);
+        assert_eq!(removed, unique_name);
+        assert!(registry.player(&second_name).is_none());
+        assert_eq!(registry.players().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn players_changing_owners_are_added_again() {
+        let bus = Bus::start();
+        let first = bus.player(None, "First").await;
+        first
+            .request_name_with_flags(FAKE_BUS_NAME, RequestNameFlags::AllowReplacement.into())
+            .await
+            .unwrap();
+
+        let connection = bus.connect().await;
+        let (registry, events) = PlayerRegistry::watch(&connection).await.unwrap();
+        let mut events = Box::pin(events);
+        assert!(matches!(
+            next_event(&mut events).await,
+            PlayerEvent::Added(_)
+        ));
+
+        let second = bus.player(Some(FAKE_BUS_NAME), "Second").await;
+        let PlayerEvent::Removed { unique_name, .. } = next_event(&mut events).await else {
+            panic!("the old owner should be removed");
+        };
+        assert_eq!(unique_name, first.unique_name().unwrap().as_str());
+        let PlayerEvent::Added(player) = next_event(&mut events).await else {
+            panic!("the new owner should be added");
+        };
+        assert_eq!(player.unique_name, second.unique_name().unwrap().as_str());
+        assert_eq!(title(&player), Some("Second"));
+        let unique_name = second.unique_name().unwrap().to_string();
+        assert_eq!(
+            registry
+                .player_by_unique_name(&unique_name)
+                .map(|player| player.bus_name),
+            Some(FAKE_BUS_NAME.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn position_is_coerced() {
+        let bus = Bus::start();
+        let _player = bus.player(Some(FAKE_BUS_NAME), "First").await;
+        let connection = bus.connect().await;
+        assert_eq!(
+            get_position(&connection, FAKE_BUS_NAME).await.unwrap(),
+            10_000_000
+        );
+    }
+}
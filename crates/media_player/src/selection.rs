@@ -0,0 +1,470 @@
+//! Picks one player out of every player in a [`PlayerRegistry`](crate::registry::PlayerRegistry),
+//! for when only one can be shown, e.g. in a bar.
+//!
+//! Players are referred to by their name without the `org.mpris.MediaPlayer2.` prefix (e.g.
+//! `spotify`, or `firefox.instance_1_84`), and matched with patterns where `*` matches any
+//! characters and `?` matches a single character.
+
+use std::{cmp::Ordering, collections::HashMap, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    media::properties::PlaybackStatus,
+    position::PositionTracker,
+    registry::{MPRIS_BUS_NAME_PREFIX, Player, PlayerEvent},
+};
+
+/// How a player is picked, from the players that match `player` and none of `ignore`:
+///
+/// - By default, the first player matching a `priority` pattern wins, followed by players that
+///   match none of them. Between those, playing players win over paused ones, which win over
+///   stopped ones, and then the one that most recently started playing wins.
+/// - With `latest`, the player that most recently started playing wins, regardless of whether
+///   it's still playing, followed by `priority`.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct SelectionPolicy {
+    /// Only players matching this pattern are picked.
+    pub player: Option<String>,
+    pub priority: Vec<String>,
+    pub ignore: Vec<String>,
+    pub latest: bool,
+}
+
+/// The players of a registry, kept up to date with its events, and the player picked from them.
+pub struct Selection {
+    policy: SelectionPolicy,
+    players: HashMap<String, SelectedPlayer>,
+    /// Incremented whenever a player starts playing, to order players by when they did.
+    playing_sequence: u64,
+}
+
+struct SelectedPlayer {
+    player: Player,
+    /// When the player last started playing, in [`Selection::playing_sequence`].
+    started_playing: Option<u64>,
+    position: PositionTracker,
+}
+
+impl SelectionPolicy {
+    pub fn is_candidate(&self, name: &str) -> bool {
+        self.player
+            .as_deref()
+            .is_none_or(|pattern| glob_match(pattern, name))
+            && !self.ignore.iter().any(|pattern| glob_match(pattern, name))
+    }
+
+    /// The index of the first `priority` pattern that `name` matches, or the number of patterns
+    /// if none do.
+    fn priority(&self, name: &str) -> usize {
+        self.priority
+            .iter()
+            .position(|pattern| glob_match(pattern, name))
+            .unwrap_or(self.priority.len())
+    }
+}
+
+impl Selection {
+    pub fn new(policy: SelectionPolicy) -> Self {
+        Self {
+            policy,
+            players: HashMap::new(),
+            playing_sequence: 0,
+        }
+    }
+
+    /// Applies `event`, and returns whether the selected player or its properties changed.
+    pub fn update(&mut self, event: PlayerEvent) -> bool {
+        let now = Instant::now();
+        let previous = self.selected().map(|player| player.bus_name.clone());
+        let changed_bus_name = match event {
+            PlayerEvent::Added(player) | PlayerEvent::Changed(player) => {
+                let bus_name = player.bus_name.clone();
+                let is_playing = player.properties.playback_status == PlaybackStatus::Playing;
+                let was_playing = self.players.get(&bus_name).is_some_and(|selected| {
+                    selected.player.properties.playback_status == PlaybackStatus::Playing
+                });
+                let started_playing = if is_playing && !was_playing {
+                    self.playing_sequence += 1;
+                    Some(self.playing_sequence)
+                } else {
+                    self.players
+                        .get(&bus_name)
+                        .and_then(|selected| selected.started_playing)
+                };
+                let position = match self.players.remove(&bus_name) {
+                    Some(SelectedPlayer { mut position, .. }) => {
+                        position.update(&player.properties, now);
+                        position
+                    }
+                    None => PositionTracker::new(&player.properties, now),
+                };
+                self.players.insert(
+                    bus_name.clone(),
+                    SelectedPlayer {
+                        player: *player,
+                        started_playing,
+                        position,
+                    },
+                );
+                bus_name
+            }
+            PlayerEvent::Seeked { bus_name, position } => {
+                if let Some(selected) = self.players.get_mut(&bus_name) {
+                    selected.player.properties.position = position;
+                    selected.position.seeked(position, now);
+                }
+                bus_name
+            }
+            PlayerEvent::Removed { bus_name, .. } => {
+                self.players.remove(&bus_name);
+                bus_name
+            }
+        };
+        let current = self.selected().map(|player| player.bus_name.clone());
+        previous != current || current.as_ref() == Some(&changed_bus_name)
+    }
+
+    /// The player picked by the policy, or `None` if no player is a candidate.
+    pub fn selected(&self) -> Option<&Player> {
+        self.selected_player().map(|selected| &selected.player)
+    }
+
+    /// The position of the selected player, or `None` if no player is selected.
+    pub fn selected_position(&self) -> Option<&PositionTracker> {
+        self.selected_player().map(|selected| &selected.position)
+    }
+
+    /// Applies the position that was just gotten from the player at `bus_name`, e.g. when
+    /// [`PositionTracker::needs_verification`].
+    pub fn verify_position(&mut self, bus_name: &str, position: i64, now: Instant) {
+        if let Some(selected) = self.players.get_mut(bus_name) {
+            selected.position.verify(position, now);
+        }
+    }
+
+    fn selected_player(&self) -> Option<&SelectedPlayer> {
+        self.players
+            .values()
+            .filter(|selected| self.policy.is_candidate(player_name(&selected.player)))
+            .min_by(|a, b| self.compare(a, b))
+    }
+
+    /// `Less` if `a` should be picked over `b`.
+    fn compare(&self, a: &SelectedPlayer, b: &SelectedPlayer) -> Ordering {
+        let (a_name, b_name) = (player_name(&a.player), player_name(&b.player));
+        let by_priority = self
+            .policy
+            .priority(a_name)
+            .cmp(&self.policy.priority(b_name));
+        let by_status = status_rank(&a.player).cmp(&status_rank(&b.player));
+        // Players that never started playing are last, as `None` is less than any `Some`.
+        let by_recency = b.started_playing.cmp(&a.started_playing);
+        let ordering = if self.policy.latest {
+            by_recency.then(by_priority).then(by_status)
+        } else {
+            by_priority.then(by_status).then(by_recency)
+        };
+        ordering.then_with(|| a_name.cmp(b_name))
+    }
+}
+
+fn status_rank(player: &Player) -> u8 {
+    match player.properties.playback_status {
+        PlaybackStatus::Playing => 0,
+        PlaybackStatus::Paused => 1,
+        PlaybackStatus::Stopped => 2,
+    }
+}
+
+/// The player's name without the `org.mpris.MediaPlayer2.` prefix, e.g. `spotify`.
+pub fn player_name(player: &Player) -> &str {
+    player
+        .bus_name
+        .strip_prefix(MPRIS_BUS_NAME_PREFIX)
+        .unwrap_or(&player.bus_name)
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any characters and `?` matches a single
+/// character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // The position after the last `*` in the pattern, and where it started matching in the name,
+    // to backtrack to when the rest fails to match.
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::properties::Properties;
+
+    fn player(name: &str, status: PlaybackStatus) -> Box<Player> {
+        Box::new(Player {
+            bus_name: format!("{}{}", MPRIS_BUS_NAME_PREFIX, name),
+            unique_name: format!(":1.{}", name.len()),
+            properties: Properties::test(status, "/track/1", Some(200_000_000), 0),
+        })
+    }
+
+    fn added(name: &str, status: PlaybackStatus) -> PlayerEvent {
+        PlayerEvent::Added(player(name, status))
+    }
+
+    fn changed(name: &str, status: PlaybackStatus) -> PlayerEvent {
+        PlayerEvent::Changed(player(name, status))
+    }
+
+    fn removed(name: &str) -> PlayerEvent {
+        PlayerEvent::Removed {
+            bus_name: format!("{}{}", MPRIS_BUS_NAME_PREFIX, name),
+            unique_name: format!(":1.{}", name.len()),
+        }
+    }
+
+    fn selection(policy: SelectionPolicy, events: Vec<PlayerEvent>) -> Selection {
+        let mut selection = Selection::new(policy);
+        for event in events {
+            selection.update(event);
+        }
+        selection
+    }
+
+    /// A selection of a paused and a stopped player, neither of which ever played.
+    fn idle_selection(policy: SelectionPolicy) -> Selection {
+        selection(
+            policy,
+            vec![
+                added("firefox", PlaybackStatus::Paused),
+                added("spotify", PlaybackStatus::Stopped),
+            ],
+        )
+    }
+
+    fn selected(selection: &Selection) -> Option<&str> {
+        selection.selected().map(player_name)
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn glob_match_literals_and_wildcards() {
+        assert!(glob_match("spotify", "spotify"));
+        assert!(!glob_match("spotify", "spotifyd"));
+        assert!(glob_match("spot*", "spotify"));
+        assert!(glob_match("spot*", "spot"));
+        assert!(glob_match("*fy", "spotify"));
+        assert!(glob_match("firefox.*", "firefox.instance_1_84"));
+        assert!(glob_match("spot?fy", "spotify"));
+        assert!(!glob_match("spot?fy", "spotfy"));
+        assert!(glob_match("??", "mé"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn glob_match_backtracks() {
+        assert!(glob_match("*a*b", "xaxaxb"));
+        assert!(glob_match("a*b*c", "abxbc"));
+        assert!(glob_match("*ab", "aab"));
+        assert!(glob_match("*?b", "ab"));
+        assert!(!glob_match("a*b", "abc"));
+        assert!(!glob_match("*a*b", "xaxax"));
+        assert!(glob_match("**a", "a"));
+    }
+
+    #[test]
+    fn glob_match_empty() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "spotify"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "spotify"));
+        assert!(!glob_match("a", ""));
+    }
+
+    #[test]
+    fn playing_wins_then_most_recent() {
+        let mut selection = selection(
+            SelectionPolicy::default(),
+            vec![
+                added("stopped", PlaybackStatus::Stopped),
+                added("paused", PlaybackStatus::Paused),
+            ],
+        );
+        assert_eq!(selected(&selection), Some("paused"));
+
+        selection.update(added("first", PlaybackStatus::Playing));
+        selection.update(added("second", PlaybackStatus::Playing));
+        assert_eq!(selected(&selection), Some("second"));
+
+        selection.update(changed("second", PlaybackStatus::Paused));
+        assert_eq!(selected(&selection), Some("first"));
+    }
+
+    #[test]
+    fn priority_wins_over_status() {
+        let policy = SelectionPolicy {
+            priority: patterns(&["mpd", "spot*"]),
+            ..SelectionPolicy::default()
+        };
+        let mut selection = selection(
+            policy,
+            vec![
+                added("firefox", PlaybackStatus::Playing),
+                added("spotify", PlaybackStatus::Paused),
+            ],
+        );
+        assert_eq!(selected(&selection), Some("spotify"));
+
+        selection.update(added("mpd", PlaybackStatus::Stopped));
+        assert_eq!(selected(&selection), Some("mpd"));
+
+        selection.update(removed("mpd"));
+        selection.update(removed("spotify"));
+        assert_eq!(selected(&selection), Some("firefox"));
+    }
+
+    #[test]
+    fn latest_wins_over_priority() {
+        let policy = SelectionPolicy {
+            priority: patterns(&["spotify"]),
+            latest: true,
+            ..SelectionPolicy::default()
+        };
+        let mut selection = selection(
+            policy,
+            vec![
+                added("spotify", PlaybackStatus::Playing),
+                added("firefox", PlaybackStatus::Playing),
+            ],
+        );
+        assert_eq!(selected(&selection), Some("firefox"));
+
+        // The latest player to start playing still wins once it's paused.
+        selection.update(changed("firefox", PlaybackStatus::Paused));
+        assert_eq!(selected(&selection), Some("firefox"));
+
+        selection.update(changed("spotify", PlaybackStatus::Paused));
+        selection.update(changed("spotify", PlaybackStatus::Playing));
+        assert_eq!(selected(&selection), Some("spotify"));
+
+        // Players that never played are ordered by priority.
+        let policy = SelectionPolicy {
+            priority: patterns(&["spotify"]),
+            latest: true,
+            ..SelectionPolicy::default()
+        };
+        let selection = idle_selection(policy);
+        assert_eq!(selected(&selection), Some("spotify"));
+    }
+
+    #[test]
+    fn ignored_players_are_never_picked() {
+        let policy = SelectionPolicy {
+            ignore: patterns(&["firefox.*", "kdeconnect*"]),
+            ..SelectionPolicy::default()
+        };
+        let mut selection = selection(
+            policy,
+            vec![
+                added("firefox.instance_1_84", PlaybackStatus::Playing),
+                added("kdeconnect.mpris_000001", PlaybackStatus::Playing),
+            ],
+        );
+        assert_eq!(selected(&selection), None);
+
+        selection.update(added("mpv", PlaybackStatus::Paused));
+        assert_eq!(selected(&selection), Some("mpv"));
+    }
+
+    #[test]
+    fn only_the_pinned_player_is_picked() {
+        let policy = SelectionPolicy {
+            player: Some("spot*".to_string()),
+            priority: patterns(&["mpv"]),
+            ..SelectionPolicy::default()
+        };
+        let mut selection = selection(
+            policy,
+            vec![
+                added("mpv", PlaybackStatus::Playing),
+                added("firefox", PlaybackStatus::Playing),
+            ],
+        );
+        assert_eq!(selected(&selection), None);
+
+        selection.update(added("spotify", PlaybackStatus::Stopped));
+        assert_eq!(selected(&selection), Some("spotify"));
+
+        let policy = SelectionPolicy {
+            player: Some("spot*".to_string()),
+            ignore: patterns(&["spotify"]),
+            ..SelectionPolicy::default()
+        };
+        let selection = idle_selection(policy);
+        assert_eq!(selected(&selection), None);
+    }
+
+    #[test]
+    fn update_returns_whether_the_selection_changed() {
+        let mut selection = Selection::new(SelectionPolicy::default());
+        assert!(selection.update(added("spotify", PlaybackStatus::Playing)));
+        // Added, but not picked over the playing player.
+        assert!(!selection.update(added("mpv", PlaybackStatus::Paused)));
+        assert!(!selection.update(changed("mpv", PlaybackStatus::Stopped)));
+        assert!(!selection.update(PlayerEvent::Seeked {
+            bus_name: format!("{}mpv", MPRIS_BUS_NAME_PREFIX),
+            position: 1_000_000,
+        }));
+
+        // The selected player's properties changed, even if to the same values.
+        assert!(selection.update(changed("spotify", PlaybackStatus::Playing)));
+        assert!(selection.update(PlayerEvent::Seeked {
+            bus_name: format!("{}spotify", MPRIS_BUS_NAME_PREFIX),
+            position: 1_000_000,
+        }));
+        assert_eq!(selection.selected().unwrap().properties.position, 1_000_000);
+
+        assert!(!selection.update(removed("mpv")));
+        assert!(selection.update(removed("spotify")));
+        assert_eq!(selected(&selection), None);
+        assert!(!selection.update(removed("spotify")));
+    }
+
+    #[test]
+    fn removing_the_selected_player_picks_another() {
+        let mut selection = selection(
+            SelectionPolicy::default(),
+            vec![
+                added("mpv", PlaybackStatus::Paused),
+                added("spotify", PlaybackStatus::Playing),
+            ],
+        );
+        assert!(selection.update(removed("spotify")));
+        assert_eq!(selected(&selection), Some("mpv"));
+    }
+}
@@ -0,0 +1,12 @@
+//! Building a [`MediaPlayer`] without a bus.
+
+use std::sync::mpsc;
+
+use gi_media_player::MediaPlayer;
+
+#[test]
+fn starting_without_watched_properties_fails() {
+    let (sender, _receiver) = mpsc::channel();
+    let result = MediaPlayer::builder().build_and_start(sender);
+    assert!(matches!(result, Err(zbus::Error::Failure(_))));
+}
@@ -0,0 +1,263 @@
+//! `Metadata` maps as sent by real players, which disagree on the types of many entries.
+
+use std::collections::HashMap;
+
+use gi_media_player::media::properties::{Metadata, MetadataValue};
+use zbus::zvariant::{LE, ObjectPath, Value, serialized::Context, to_bytes};
+
+/// Encodes `entries` as the `a{sv}` a player sends, and deserializes it.
+fn metadata(entries: Vec<(&str, Value<'_>)>) -> Metadata {
+    let map = entries.into_iter().collect::<HashMap<_, _>>();
+    let encoded = to_bytes(Context::new_dbus(LE, 0), &map).expect("encodes");
+    encoded.deserialize::<Metadata>().expect("deserializes").0
+}
+
+fn path(path: &str) -> Value<'_> {
+    Value::from(ObjectPath::try_from(path).expect("valid object path"))
+}
+
+fn strings(strings: &[&str]) -> Value<'static> {
+    Value::from(strings.iter().map(ToString::to_string).collect::<Vec<_>>())
+}
+
+#[test]
+fn spotify() {
+    let metadata = metadata(vec![
+        (
+            "mpris:trackid",
+            path("/com/spotify/track/4uLU6hMCjMI75M1A2tKUQC"),
+        ),
+        ("mpris:length", Value::U64(213_573_000)),
+        (
+            "mpris:artUrl",
+            Value::from("https://i.scdn.co/image/ab67616d0000b273e319baafd16e84f0408af2a0"),
+        ),
+        ("xesam:album", Value::from("Whenever You Need Somebody")),
+        ("xesam:albumArtist", strings(&["Rick Astley"])),
+        ("xesam:artist", strings(&["Rick Astley"])),
+        ("xesam:autoRating", Value::F64(0.78)),
+        ("xesam:discNumber", Value::I32(1)),
+        ("xesam:title", Value::from("Never Gonna Give You Up")),
+        ("xesam:trackNumber", Value::I32(1)),
+        (
+            "xesam:url",
+            Value::from("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC"),
+        ),
+    ]);
+    assert_eq!(
+        metadata.trackid(),
+        "/com/spotify/track/4uLU6hMCjMI75M1A2tKUQC"
+    );
+    assert_eq!(metadata.length(), Some(213_573_000));
+    assert_eq!(
+        metadata.album_artist(),
+        Some(&["Rick Astley".to_string()][..])
+    );
+    assert_eq!(metadata.auto_rating(), Some(0.78));
+    assert_eq!(metadata.disc_number(), Some(1));
+    assert_eq!(metadata.track_number(), Some(1));
+}
+
+#[test]
+fn spotify_legacy_string_trackid() {
+    let metadata = metadata(vec![
+        (
+            "mpris:trackid",
+            Value::from("spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+        ),
+        ("mpris:length", Value::U64(213_573_000)),
+        ("xesam:title", Value::from("Never Gonna Give You Up")),
+        ("xesam:userRating", Value::I32(1)),
+    ]);
+    assert_eq!(metadata.trackid(), "spotify:track:4uLU6hMCjMI75M1A2tKUQC");
+    assert_eq!(metadata.length(), Some(213_573_000));
+    assert_eq!(metadata.user_rating(), Some(1.0));
+}
+
+#[test]
+fn firefox() {
+    let metadata = metadata(vec![
+        ("mpris:trackid", path("/org/mpris/MediaPlayer2/firefox")),
+        ("mpris:length", Value::I64(212_000_000)),
+        (
+            "mpris:artUrl",
+            Value::from("file:///home/user/.mozilla/firefox/firefox-mpris/7123_0.png"),
+        ),
+        ("xesam:album", Value::from("")),
+        ("xesam:artist", strings(&["RickAstleyVEVO"])),
+        (
+            "xesam:title",
+            Value::from("Rick Astley - Never Gonna Give You Up (Official Music Video)"),
+        ),
+    ]);
+    assert_eq!(metadata.trackid(), "/org/mpris/MediaPlayer2/firefox");
+    assert_eq!(metadata.length(), Some(212_000_000));
+    assert_eq!(metadata.album(), Some(""));
+    assert_eq!(metadata.artist(), Some(&["RickAstleyVEVO".to_string()][..]));
+}
+
+#[test]
+fn chromium() {
+    let metadata = metadata(vec![
+        (
+            "mpris:trackid",
+            path("/org/chromium/MediaPlayer2/TrackList/TrackFd6eHyJ8UqeHaeGb"),
+        ),
+        ("mpris:length", Value::I64(212_091_000)),
+        (
+            "mpris:artUrl",
+            Value::from("file:///tmp/.org.chromium.Chromium.ZQBr7T"),
+        ),
+        ("xesam:album", Value::from("")),
+        // Some versions send the artist as a plain string.
+        ("xesam:artist", Value::from("RickAstleyVEVO")),
+        (
+            "xesam:title",
+            Value::from("Rick Astley - Never Gonna Give You Up (Official Music Video)"),
+        ),
+    ]);
+    assert_eq!(
+        metadata.trackid(),
+        "/org/chromium/MediaPlayer2/TrackList/TrackFd6eHyJ8UqeHaeGb"
+    );
+    assert_eq!(metadata.artist(), Some(&["RickAstleyVEVO".to_string()][..]));
+}
+
+#[test]
+fn mpv() {
+    let metadata = metadata(vec![
+        ("mpris:trackid", path("/0")),
+        ("mpris:length", Value::I64(213_112_000)),
+        ("xesam:album", Value::from("Whenever You Need Somebody")),
+        ("xesam:albumArtist", strings(&["Rick Astley"])),
+        ("xesam:artist", strings(&["Rick Astley"])),
+        ("xesam:comment", strings(&["Remastered 2022"])),
+        ("xesam:contentCreated", Value::from("1987")),
+        ("xesam:discNumber", Value::I64(1)),
+        ("xesam:genre", strings(&["Pop", "Dance-pop"])),
+        ("xesam:title", Value::from("Never Gonna Give You Up")),
+        ("xesam:trackNumber", Value::I64(1)),
+        (
+            "xesam:url",
+            Value::from(
+                "file:///home/user/Music/Rick%20Astley/01%20Never%20Gonna%20Give%20You%20Up.flac",
+            ),
+        ),
+        // Parsed from a tag, so it's a string.
+        ("xesam:audioBPM", Value::from("113")),
+    ]);
+    assert_eq!(metadata.trackid(), "/0");
+    assert_eq!(metadata.disc_number(), Some(1));
+    assert_eq!(metadata.track_number(), Some(1));
+    assert_eq!(metadata.audio_bpm(), Some(113));
+    assert_eq!(metadata.content_created(), Some("1987"));
+    assert_eq!(
+        metadata.genre(),
+        Some(&["Pop".to_string(), "Dance-pop".to_string()][..])
+    );
+}
+
+#[test]
+fn vlc() {
+    let metadata = metadata(vec![
+        ("mpris:trackid", path("/org/videolan/vlc/playlist/4")),
+        ("mpris:length", Value::I64(213_112_000)),
+        (
+            "mpris:artUrl",
+            Value::from("file:///home/user/.cache/vlc/art/artistalbum/Rick%20Astley/art.jpg"),
+        ),
+        ("xesam:album", Value::from("Whenever You Need Somebody")),
+        ("xesam:artist", strings(&["Rick Astley"])),
+        ("xesam:genre", strings(&["Pop"])),
+        ("xesam:title", Value::from("Never Gonna Give You Up")),
+        ("xesam:trackNumber", Value::U32(1)),
+        ("xesam:userRating", Value::I32(0)),
+        (
+            "xesam:url",
+            Value::from(
+                "file:///home/user/Music/Rick%20Astley/01%20Never%20Gonna%20Give%20You%20Up.flac",
+            ),
+        ),
+        // Nested in another variant.
+        ("xesam:discNumber", Value::Value(Box::new(Value::U32(1)))),
+    ]);
+    assert_eq!(metadata.trackid(), "/org/videolan/vlc/playlist/4");
+    assert_eq!(metadata.track_number(), Some(1));
+    assert_eq!(metadata.disc_number(), Some(1));
+    assert_eq!(metadata.user_rating(), Some(0.0));
+    assert_eq!(metadata.genre(), Some(&["Pop".to_string()][..]));
+}
+
+#[test]
+fn uncoercible_entries_are_skipped() {
+    let metadata = metadata(vec![
+        (
+            "mpris:trackid",
+            path("/org/mpris/MediaPlayer2/TrackList/NoTrack"),
+        ),
+        ("mpris:length", Value::U64(u64::MAX)),
+        ("xesam:title", Value::Bool(true)),
+        ("xesam:trackNumber", Value::from("first")),
+        ("xesam:artist", strings(&["Rick Astley"])),
+    ]);
+    assert_eq!(metadata.length(), None);
+    assert_eq!(metadata.title(), None);
+    assert_eq!(metadata.track_number(), None);
+    assert_eq!(metadata.artist(), Some(&["Rick Astley".to_string()][..]));
+}
+
+#[test]
+fn missing_entries_are_none() {
+    let metadata = metadata(vec![]);
+    assert_eq!(metadata.trackid(), "");
+    assert_eq!(metadata.length(), None);
+    assert_eq!(metadata.title(), None);
+}
+
+#[test]
+fn unknown_entries_are_kept() {
+    let metadata = metadata(vec![
+        ("mpris:trackid", path("/0")),
+        ("xesam:title", Value::from("Never Gonna Give You Up")),
+        ("mpv:playlistPosition", Value::U32(3)),
+        (
+            "kde:mediaSrc",
+            Value::from("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+        ),
+        ("kde:isLive", Value::Bool(false)),
+        ("kde:tags", strings(&["music", "80s"])),
+        // Nothing else can be done with a map, so it's dropped.
+        (
+            "kde:chapters",
+            Value::from(HashMap::from([("intro", 0_i64)])),
+        ),
+    ]);
+    assert_eq!(
+        metadata.get_key("mpv:playlistPosition"),
+        Some(MetadataValue::Integer(3))
+    );
+    assert_eq!(
+        metadata.get_key("kde:mediaSrc"),
+        Some(MetadataValue::String(
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string()
+        ))
+    );
+    assert_eq!(
+        metadata.get_key("kde:isLive"),
+        Some(MetadataValue::Bool(false))
+    );
+    assert_eq!(
+        metadata.get_key("kde:tags"),
+        Some(MetadataValue::Strings(vec![
+            "music".to_string(),
+            "80s".to_string()
+        ]))
+    );
+    assert_eq!(metadata.get_key("kde:chapters"), None);
+    // Known entries aren't duplicated.
+    assert_eq!(metadata.extra().len(), 4);
+    assert_eq!(
+        metadata.get_key("xesam:title"),
+        Some(MetadataValue::String("Never Gonna Give You Up".to_string()))
+    );
+}
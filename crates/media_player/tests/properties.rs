@@ -0,0 +1,84 @@
+//! `org.freedesktop.DBus.Properties.GetAll` replies as sent by real players, which disagree on the
+//! types of the numeric properties.
+
+use std::collections::HashMap;
+
+use gi_media_player::media::properties::{PlaybackStatus, Properties};
+use zbus::zvariant::{LE, ObjectPath, Value, serialized::Context, to_bytes};
+
+/// Encodes `entries` as the `a{sv}` a player replies with, along with the properties every player
+/// sends the same way, and deserializes it.
+fn properties(entries: Vec<(&str, Value<'_>)>) -> Properties {
+    let metadata = HashMap::from([(
+        "mpris:trackid",
+        Value::from(ObjectPath::try_from("/org/mpris/MediaPlayer2/Track/1").unwrap()),
+    )]);
+    let mut map = HashMap::from([
+        ("PlaybackStatus", Value::from("Playing")),
+        ("Metadata", Value::from(metadata)),
+        ("CanGoNext", Value::Bool(true)),
+        ("CanGoPrevious", Value::Bool(true)),
+        ("CanPlay", Value::Bool(true)),
+        ("CanPause", Value::Bool(true)),
+        ("CanSeek", Value::Bool(true)),
+        ("CanControl", Value::Bool(true)),
+    ]);
+    map.extend(entries);
+    let encoded = to_bytes(Context::new_dbus(LE, 0), &map).expect("encodes");
+    encoded.deserialize::<Properties>().expect("deserializes").0
+}
+
+#[test]
+fn spec_types() {
+    let properties = properties(vec![
+        ("Rate", Value::F64(1.0)),
+        ("Volume", Value::F64(0.5)),
+        ("Position", Value::I64(42_000_000)),
+        ("MinimumRate", Value::F64(0.25)),
+        ("MaximumRate", Value::F64(4.0)),
+    ]);
+    assert!(matches!(
+        properties.playback_status,
+        PlaybackStatus::Playing
+    ));
+    assert_eq!(properties.rate, Some(1.0));
+    assert_eq!(properties.volume, Some(0.5));
+    assert_eq!(properties.position, 42_000_000);
+    assert_eq!(properties.minimum_rate, Some(0.25));
+    assert_eq!(properties.maximum_rate, Some(4.0));
+    assert_eq!(
+        properties.metadata.trackid(),
+        "/org/mpris/MediaPlayer2/Track/1"
+    );
+}
+
+#[test]
+fn integer_rates_and_volume() {
+    let properties = properties(vec![
+        ("Rate", Value::I32(1)),
+        ("Volume", Value::U32(1)),
+        ("Position", Value::I64(0)),
+        ("MinimumRate", Value::I32(1)),
+        ("MaximumRate", Value::I32(1)),
+    ]);
+    assert_eq!(properties.rate, Some(1.0));
+    assert_eq!(properties.volume, Some(1.0));
+    assert_eq!(properties.minimum_rate, Some(1.0));
+    assert_eq!(properties.maximum_rate, Some(1.0));
+}
+
+#[test]
+fn unsigned_position() {
+    let properties = properties(vec![("Position", Value::U64(42_000_000))]);
+    assert_eq!(properties.position, 42_000_000);
+    assert_eq!(properties.rate, None);
+}
+
+#[test]
+fn odd_optional_property_is_none() {
+    let properties = properties(vec![
+        ("Position", Value::I32(0)),
+        ("Volume", Value::from("loud")),
+    ]);
+    assert_eq!(properties.volume, None);
+}
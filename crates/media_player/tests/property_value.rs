@@ -0,0 +1,76 @@
+//! Values of `PropertiesChanged` entries, as sent by players.
+
+use gi_media_player::{
+    MetadataName, PropertyName, PropertyValue,
+    media::properties::{LoopStatus, MetadataValue, PlaybackStatus},
+};
+use zbus::zvariant::Value;
+
+#[test]
+fn player_properties() {
+    assert_eq!(
+        PropertyValue::from_dbus(PropertyName::PlaybackStatus, &Value::from("Paused")),
+        Some(PropertyValue::PlaybackStatus(PlaybackStatus::Paused))
+    );
+    assert_eq!(
+        PropertyValue::from_dbus(PropertyName::LoopStatus, &Value::from("Playlist")),
+        Some(PropertyValue::LoopStatus(LoopStatus::Playlist))
+    );
+    assert_eq!(
+        PropertyValue::from_dbus(PropertyName::Volume, &Value::F64(0.5)),
+        Some(PropertyValue::Volume(0.5))
+    );
+    // Some players send rates as integers.
+    assert_eq!(
+        PropertyValue::from_dbus(PropertyName::Rate, &Value::I32(1)),
+        Some(PropertyValue::Rate(1.0))
+    );
+    assert_eq!(
+        PropertyValue::from_dbus(PropertyName::Position, &Value::U64(42_000_000)),
+        Some(PropertyValue::Position(42_000_000))
+    );
+    assert_eq!(
+        PropertyValue::from_dbus(PropertyName::CanSeek, &Value::Bool(false)),
+        Some(PropertyValue::CanSeek(false))
+    );
+}
+
+#[test]
+fn metadata_entries_have_their_types() {
+    // A single artist is still a list of artists.
+    assert_eq!(
+        PropertyValue::from_dbus(
+            PropertyName::Metadata(MetadataName::XesamArtist),
+            &Value::from("Rick Astley")
+        ),
+        Some(PropertyValue::Metadata(MetadataValue::Strings(vec![
+            "Rick Astley".to_string()
+        ])))
+    );
+    assert_eq!(
+        PropertyValue::from_dbus(
+            PropertyName::Metadata(MetadataName::XesamTrackNumber),
+            &Value::from("3")
+        ),
+        Some(PropertyValue::Metadata(MetadataValue::Integer(3)))
+    );
+}
+
+#[test]
+fn invalid_values_are_none() {
+    assert_eq!(
+        PropertyValue::from_dbus(PropertyName::PlaybackStatus, &Value::from("Buffering")),
+        None
+    );
+    assert_eq!(
+        PropertyValue::from_dbus(PropertyName::Shuffle, &Value::from("true")),
+        None
+    );
+    assert_eq!(
+        PropertyValue::from_dbus(
+            PropertyName::Metadata(MetadataName::XesamTitle),
+            &Value::Bool(true)
+        ),
+        None
+    );
+}
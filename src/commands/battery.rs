@@ -1,19 +1,29 @@
 use std::collections::HashSet;
-use std::{sync::mpsc, time::Duration};
+use std::str::FromStr;
+use std::{io, sync::Arc, time::Duration};
 
 use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
-use gi_battery::{Batteries, BatteryInfoName, BatteryStatus, get_main_battery_name};
-use gi_core::AsTimestamp;
+use gi_battery::{Batteries, Battery, BatteryInfoName, BatterySnapshot};
+use gi_core::{Error, units::Quantity};
 use notify::{Config, Event, PollWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
 
-use crate::commands::{Field, FieldValue, FormatOutputType, Output, SubCommandExt};
+use crate::commands::{
+    Field, FieldNames, FieldValue, FormatOptions, Output, OutputDiffer, PollOptions, RunMode,
+    SubCommandExt, diff_from_args,
+    hook::{OnChange, OnChangeNotifier},
+    schema::{FieldSchema, ModuleSchema},
+    write_line,
+};
+use crate::config::{ArgMatchesExt, BatteryConfig};
 
 pub trait BatteryInfoNameExt {
-    fn files_to_watch(&self) -> Vec<&str>;
+    fn files_to_watch(&self) -> Vec<&'static str>;
 }
 
 impl BatteryInfoNameExt for BatteryInfoName {
-    fn files_to_watch(&self) -> Vec<&str> {
+    fn files_to_watch(&self) -> Vec<&'static str> {
         match self {
             // No need to watch charge_full
             BatteryInfoName::ChargeFull => Vec::new(),
@@ -30,6 +40,8 @@ pub fn cli() -> Command {
     Command::new("battery")
         .about("Scripts for battery info")
         .common_args()
+        .format_args()
+        .mut_arg("template", |arg| arg.conflicts_with("info_names"))
         .arg(
             Arg::new("info_names")
                 .value_name("INFO_NAME")
@@ -46,220 +58,332 @@ pub fn cli() -> Command {
                 .long("name")
                 .help("Specify battery name in the case of multiple batteries (e.g. 'BAT1'). Defaults to lowest-numbered battery"),
         )
-        .arg(
-            Arg::new("format_output")
-                .short('f')
-                .long("format-output")
-                .value_parser(value_parser!(FormatOutputType))
-                .value_name("FORMAT_TYPE")
-                .default_value("no_symbols")
-                .help("Specify how the output fields should be formatted"),
-        )
 }
 
-struct BatteryContext<'a> {
-    battery_name: &'a str,
-    format_output: &'a FormatOutputType,
-    separator: &'a str,
-    output_as_json: bool,
+/// Options for getting battery info, resolved from the command line and the config.
+///
+/// Also sent to the daemon by `--connect` clients, so that the daemon can render output the same
+/// way as when running locally.
+#[derive(Serialize, Deserialize)]
+pub struct BatteryContext {
+    /// Defaults to the lowest-numbered battery if `None`.
+    pub battery_name: Option<String>,
+    pub info_names: Vec<BatteryInfoName>,
+    #[serde(flatten)]
+    pub format: FormatOptions,
+    pub diff: bool,
 }
 
-struct BatterySubcommand<'a> {
-    batteries: Batteries,
-    info_names: &'a Vec<&'a BatteryInfoName>,
-    context: BatteryContext<'a>,
-}
+impl BatteryContext {
+    pub fn from_args(args: &ArgMatches, config: &BatteryConfig) -> Result<Self, Error> {
+        let format = FormatOptions::from_args(args, &config.common.format(), FIELD_NAMES)?;
+        let info_names = match (&format.template, &config.fields) {
+            (Some(template), _) => {
+                let mut info_names = Vec::new();
+                for name in template.field_names() {
+                    let info_name = BatteryInfoName::from_str(name)?;
+                    if !info_names.contains(&info_name) {
+                        info_names.push(info_name);
+                    }
+                }
+                info_names
+            }
+            (None, Some(fields)) if !args.is_from_command_line("info_names") => fields.clone(),
+            _ => args
+                .get_many::<BatteryInfoName>("info_names")
+                .expect("has a default value")
+                .cloned()
+                .collect(),
+        };
+        let battery_name = args.get_or_config::<String>("name", config.name.as_ref());
+        let diff = diff_from_args(args, config.common.diff.as_ref(), format.output)?;
 
-impl<'a> BatterySubcommand<'a> {
-    fn new(
-        batteries: Batteries,
-        info_names: &'a Vec<&'a BatteryInfoName>,
-        context: BatteryContext<'a>,
-    ) -> Self {
-        Self {
-            batteries,
+        Ok(Self {
+            battery_name,
             info_names,
-            context,
-        }
+            format,
+            diff,
+        })
     }
 
-    fn watch(&mut self) {
-        let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
-        let config = Config::default()
-            .with_compare_contents(true)
-            .with_poll_interval(Duration::from_secs_f64(0.2));
+    pub fn find_battery<'a>(&self, batteries: &'a Batteries) -> Result<&'a Battery, Error> {
+        let battery_name = self
+            .battery_name
+            .as_deref()
+            .unwrap_or(&batteries.main_battery_name);
+        batteries
+            .get_battery(battery_name)
+            .ok_or_else(|| Error::BatteryNotFound {
+                name: battery_name.to_string(),
+            })
+    }
 
-        let mut watcher = PollWatcher::new(tx, config).unwrap();
+    pub fn get_output_string(&self, battery: &BatterySnapshot) -> String {
+        self.get_output(battery).render()
+    }
 
-        let battery_path = &self
-            .batteries
-            .get_battery(self.context.battery_name)
-            .unwrap()
-            .path;
+    pub fn get_output(&self, battery: &BatterySnapshot) -> Output<'_> {
+        let mut battery_output = Output::new(Vec::with_capacity(1), &self.format);
 
-        // Although `notify` already handles duplicate watched files properly, we filter out duplicate
-        // files just to avoid the extra calls to `watcher.watch(...)`. Have not tested if this is
-        // faster/more efficient.
-        let mut files_to_watch = HashSet::new();
         for info_name in self.info_names.iter() {
-            for filename in info_name.files_to_watch() {
-                files_to_watch.insert(filename);
-            }
+            let field_value = field_value(info_name, battery);
+            let field = Field::new(info_name.as_str(), field_value);
+            battery_output.fields.push(field);
         }
 
-        for filename in &files_to_watch {
+        battery_output
+    }
+}
+
+/// The value of `info_name` in `snapshot`, in the unit of [`BatteryInfoName::unit`].
+pub fn field_value(info_name: &BatteryInfoName, snapshot: &BatterySnapshot) -> FieldValue {
+    let quantity = |value: f64| {
+        let unit = info_name.unit().expect("numeric info has a unit");
+        FieldValue::Quantity(Quantity::new(value, unit))
+    };
+    match info_name {
+        BatteryInfoName::ChargeNow => quantity(snapshot.charge_now as f64),
+        BatteryInfoName::Capacity => quantity(snapshot.capacity()),
+        BatteryInfoName::ChargeFull => quantity(snapshot.charge_full as f64),
+        BatteryInfoName::CurrentNow => quantity(snapshot.current_now as f64),
+        BatteryInfoName::TimeRemaining => quantity(snapshot.time_remaining() as f64),
+        BatteryInfoName::Status => FieldValue::String(snapshot.status.to_string()),
+    }
+}
+
+/// The latest snapshot of a battery, or the error from failing to read it.
+pub type SnapshotResult = Result<BatterySnapshot, Error>;
+
+/// Watches the sysfs files of a battery and keeps its latest snapshot, which can be shared by any
+/// number of subscribers.
+pub struct BatteryWatcher {
+    _watcher: PollWatcher,
+    sender: Arc<watch::Sender<SnapshotResult>>,
+}
+
+impl BatteryWatcher {
+    pub fn new(battery: Battery, files_to_watch: &HashSet<&str>) -> Result<Self, Error> {
+        let sender = Arc::new(watch::Sender::new(battery.snapshot()));
+        let config = Config::default()
+            .with_compare_contents(true)
+            .with_poll_interval(Duration::from_secs_f64(0.2));
+
+        let battery_path = battery.path.clone();
+        let watcher_sender = sender.clone();
+        let mut watcher = PollWatcher::new(
+            move |_res: notify::Result<Event>| {
+                // Kept even while there are no subscribers, for the next one.
+                let _ = watcher_sender.send_replace(battery.snapshot());
+            },
+            config,
+        )
+        .map_err(io::Error::other)?;
+
+        for filename in files_to_watch {
             watcher
                 .watch(&battery_path.join(filename), RecursiveMode::NonRecursive)
-                .unwrap();
+                .map_err(io::Error::other)?;
         }
 
-        let mut previous_output = self.get_output_string();
-        println!("{}", previous_output);
+        Ok(Self {
+            _watcher: watcher,
+            sender,
+        })
+    }
 
-        // TODO: find a better way to prevent outputting redundant values other than checking it
-        // with the previous output
-        for _res in rx {
-            let output = self.get_output_string();
-            if previous_output != output {
-                println!("{}", output);
-                previous_output = output;
-            }
+    pub fn subscribe(&self) -> watch::Receiver<SnapshotResult> {
+        self.sender.subscribe()
+    }
+
+    /// Whether any receiver from [`subscribe`](Self::subscribe) is still alive.
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+}
+
+/// Every file that has to be watched to get notified of changes to `info_names`.
+pub fn files_to_watch<'a>(
+    info_names: impl IntoIterator<Item = &'a BatteryInfoName>,
+) -> HashSet<&'static str> {
+    // Although `notify` already handles duplicate watched files properly, we filter out duplicate
+    // files just to avoid the extra calls to `watcher.watch(...)`. Have not tested if this is
+    // faster/more efficient.
+    let mut files_to_watch = HashSet::new();
+    for info_name in info_names {
+        for filename in info_name.files_to_watch() {
+            files_to_watch.insert(filename);
+        }
+    }
+    files_to_watch
+}
+
+/// Output of a [`BatteryContext`] for every change of a [`BatteryWatcher`]'s snapshot.
+pub struct BatteryOutputs<'a> {
+    context: &'a BatteryContext,
+    receiver: watch::Receiver<SnapshotResult>,
+    differ: OutputDiffer<'a>,
+    is_first: bool,
+}
+
+impl<'a> BatteryOutputs<'a> {
+    pub fn new(context: &'a BatteryContext, receiver: watch::Receiver<SnapshotResult>) -> Self {
+        Self {
+            context,
+            receiver,
+            differ: OutputDiffer::new(RunMode::Watch, context.diff),
+            is_first: true,
         }
     }
 
-    fn poll(&self, milliseconds: u64) {
-        let duration = Duration::from_millis(milliseconds);
+    /// Waits for the next change to the output. Returns `None` once the watcher is dropped.
+    pub async fn next(&mut self) -> Option<Result<String, Error>> {
         loop {
-            println!("{}", self.get_output_string());
-            std::thread::sleep(duration);
+            if !self.is_first && self.receiver.changed().await.is_err() {
+                return None;
+            }
+            self.is_first = false;
+
+            let output = match &*self.receiver.borrow_and_update() {
+                Ok(snapshot) => self.context.get_output(snapshot),
+                Err(err) => return Some(Err(err.clone())),
+            };
+
+            if let Some(output) = self.differ.next(output) {
+                return Some(Ok(output.render()));
+            }
         }
     }
+}
 
-    fn get_output_string(&self) -> String {
-        let battery = self
-            .batteries
-            .get_battery(self.context.battery_name)
-            .unwrap();
+struct BatterySubcommand {
+    battery: Battery,
+    context: BatteryContext,
+    on_change: Option<OnChange>,
+}
 
-        let mut battery_output =
-            Output::new(Vec::with_capacity(1), Some(self.context.separator));
+impl BatterySubcommand {
+    fn new(battery: Battery, context: BatteryContext, on_change: Option<OnChange>) -> Self {
+        Self {
+            battery,
+            context,
+            on_change,
+        }
+    }
 
-        for info_name in self.info_names.iter() {
-            let field_value = match info_name {
-                BatteryInfoName::ChargeNow => {
-                    let value = battery.get_charge_now().unwrap();
-                    match self.context.format_output {
-                        FormatOutputType::Raw => FieldValue::I32(value),
-                        FormatOutputType::NoSymbols => FieldValue::I32(value),
-                        FormatOutputType::Formatted => {
-                            FieldValue::String(format!("{}mAh", as_amps(value)))
-                        }
-                    }
-                }
-                BatteryInfoName::Capacity => {
-                    let value = battery.get_capacity().unwrap();
-                    match self.context.format_output {
-                        FormatOutputType::Raw => FieldValue::F32(value),
-                        FormatOutputType::NoSymbols => FieldValue::F32(value * 100.0),
-                        FormatOutputType::Formatted => {
-                            FieldValue::String(format!("{}%", value * 100.0))
-                        }
-                    }
-                }
-                BatteryInfoName::ChargeFull => {
-                    let value = battery.get_charge_full();
-                    match self.context.format_output {
-                        FormatOutputType::Raw => FieldValue::I32(value),
-                        FormatOutputType::NoSymbols => FieldValue::I32(as_amps(value)),
-                        FormatOutputType::Formatted => {
-                            FieldValue::String(format!("{}mAh", as_amps(value)))
-                        }
-                    }
-                }
-                BatteryInfoName::CurrentNow => {
-                    let value = battery.get_current_now().unwrap();
-                    match self.context.format_output {
-                        FormatOutputType::Raw => FieldValue::I32(value),
-                        FormatOutputType::NoSymbols => FieldValue::I32(as_amps(value)),
-                        FormatOutputType::Formatted => {
-                            FieldValue::String(format!("{}mA", as_amps(value)))
-                        }
-                    }
-                }
-                BatteryInfoName::TimeRemaining => {
-                    let value = match battery.get_status().unwrap() {
-                        BatteryStatus::Unknown
-                        | BatteryStatus::NotCharging
-                        | BatteryStatus::Full => 0,
-                        BatteryStatus::Charging | BatteryStatus::Discharging => {
-                            battery.get_time_remaining().unwrap()
-                        }
-                    };
-                    match self.context.format_output {
-                        FormatOutputType::Raw => FieldValue::U64(value),
-                        FormatOutputType::NoSymbols => FieldValue::Timestamp(value.as_timestamp()),
-                        FormatOutputType::Formatted => {
-                            FieldValue::String(value.as_timestamp().to_string())
-                        }
-                    }
-                }
-                BatteryInfoName::Status => {
-                    FieldValue::String(battery.get_status().unwrap().to_string())
+    async fn watch(&self) -> Result<(), Error> {
+        let watcher = BatteryWatcher::new(
+            self.battery.clone(),
+            &files_to_watch(&self.context.info_names),
+        )?;
+
+        let mut outputs = BatteryOutputs::new(&self.context, watcher.subscribe());
+        let print = async {
+            let mut stdout = tokio::io::stdout();
+            while let Some(output) = outputs.next().await {
+                write_line(&mut stdout, &output?).await?;
+            }
+            Ok(())
+        };
+        match &self.on_change {
+            Some(on_change) => {
+                let notify = self.notify_changes(on_change.clone().start(), watcher.subscribe());
+                tokio::select! {
+                    result = print => result,
+                    () = notify => Ok(()),
                 }
-            };
-            let field = Field::new(info_name.as_str(), field_value);
-            battery_output.fields.push(field);
+            }
+            None => print.await,
+        }
+    }
+
+    /// Notifies `notifier` of every snapshot, until the watcher is dropped.
+    async fn notify_changes(
+        &self,
+        mut notifier: OnChangeNotifier<'_>,
+        mut receiver: watch::Receiver<SnapshotResult>,
+    ) {
+        loop {
+            if let Ok(snapshot) = &*receiver.borrow_and_update() {
+                notifier.notify(&self.context.get_output(snapshot));
+            }
+            if receiver.changed().await.is_err() {
+                return;
+            }
         }
+    }
 
-        if self.context.output_as_json {
-            serde_json::to_string(&battery_output).expect("always valid")
-        } else {
-            battery_output.to_string()
+    async fn poll(&self, options: PollOptions) -> Result<(), Error> {
+        let mut scheduler = options.scheduler()?;
+        let mut differ = OutputDiffer::new(RunMode::Poll(options), self.context.diff);
+        let mut notifier = self.on_change.clone().map(OnChange::start);
+        let mut stdout = tokio::io::stdout();
+        loop {
+            scheduler.tick().await;
+            let snapshot = self.battery.snapshot()?;
+            let output = self.context.get_output(&snapshot);
+            if let Some(notifier) = &mut notifier {
+                notifier.notify(&output);
+            }
+            if let Some(output) = differ.next(output) {
+                // Ticks are missed instead of queued up while stdout is blocked
+                write_line(&mut stdout, &output.render()).await?;
+            }
         }
     }
+
+    fn get_output_string(&self) -> Result<String, Error> {
+        Ok(self.context.get_output_string(&self.battery.snapshot()?))
+    }
 }
 
-// TODO: proper error handling
-pub fn exec(args: &ArgMatches) {
-    let format_output = args
-        .get_one::<FormatOutputType>("format_output")
-        .expect("has a default value");
-    let separator = args
-        .get_one::<String>("separator")
-        .expect("has a default value");
-    let output_as_json = args.get_one::<bool>("json").expect("has a default value");
-    let input_info_names = args
-        .get_many::<BatteryInfoName>("info_names")
-        .expect("has a default value")
-        .collect::<Vec<_>>();
-    let default_battery_name = get_main_battery_name().unwrap();
-    let battery_name = args
-        .get_one::<String>("name")
-        .unwrap_or(&default_battery_name);
-
-    let batteries = Batteries::init().unwrap();
-
-    let mut battery_subcommand = BatterySubcommand::new(
-        batteries,
-        &input_info_names,
-        BatteryContext {
-            battery_name,
-            format_output,
-            separator,
-            output_as_json: *output_as_json,
-        },
-    );
-
-    if args.get_flag("watch") {
-        battery_subcommand.watch();
-    } else if let Some(milliseconds) = args.get_one::<u64>("poll") {
-        battery_subcommand.poll(*milliseconds)
-    } else {
-        println!("{}", battery_subcommand.get_output_string());
+pub async fn exec(args: &ArgMatches, config: &BatteryConfig) -> Result<(), Error> {
+    let context = BatteryContext::from_args(args, config)?;
+    let mode = RunMode::from_args(args, &config.common.run_mode());
+    let on_change = OnChange::from_args(args, &config.common.on_change())?;
+    if matches!(mode, RunMode::Once) && args.is_from_command_line("on_change") {
+        return Err(Error::InvalidArgument {
+            message: "--on-change requires --watch or --poll".to_string(),
+        });
+    }
+    let batteries = Batteries::init()?;
+    let battery = context.find_battery(&batteries)?.clone();
+
+    let battery_subcommand = BatterySubcommand::new(battery, context, on_change);
+
+    match mode {
+        RunMode::Watch => battery_subcommand.watch().await,
+        RunMode::Poll(options) => battery_subcommand.poll(options).await,
+        RunMode::Once => {
+            println!("{}", battery_subcommand.get_output_string()?);
+            Ok(())
+        }
     }
 }
 
-#[inline]
-fn as_amps(micro_amps: i32) -> i32 {
-    micro_amps / 1000
+/// The fields of `getinfo battery`.
+pub fn schema() -> ModuleSchema {
+    let fields = BatteryInfoName::ALL
+        .iter()
+        .map(|info_name| {
+            FieldSchema::new(
+                info_name.as_str(),
+                info_name.aliases(),
+                info_name.unit(),
+                !info_name.files_to_watch().is_empty(),
+            )
+        })
+        .collect();
+    ModuleSchema::new(&cli(), fields)
 }
+
+/// Resolves a field name in `--precision` or `--template`, which may be an alias.
+pub fn resolve_info_name(name: &str) -> Result<String, Error> {
+    Ok(BatteryInfoName::from_str(name)?.as_str().to_string())
+}
+
+pub const FIELD_NAMES: FieldNames = FieldNames {
+    resolve: resolve_info_name,
+    is_numeric: |name| {
+        BatteryInfoName::from_str(name).is_ok_and(|info_name| info_name.is_numeric())
+    },
+};
@@ -0,0 +1,671 @@
+//! Exposes the daemon's watchers as a D-Bus service, with one object per battery at
+//! `/io/github/fqidz/getinfo/battery/<BAT>`, and the media player that `getinfo media` selects by
+//! default at `/io/github/fqidz/getinfo/media`:
+//!
+//! ```text
+//! $ busctl --user get-property io.github.fqidz.getinfo /io/github/fqidz/getinfo/battery/BAT0 \
+//!     io.github.fqidz.getinfo.Battery Capacity
+//! d 0.83
+//! ```
+//!
+//! Every property is in the same units as `--format-output raw`, and changes are signalled with
+//! `org.freedesktop.DBus.Properties.PropertiesChanged`. While a battery can't be read, its `Error`
+//! property holds the reason and the other properties are invalidated. The media player's
+//! properties are empty, or 0, while no player is selected.
+
+use std::{borrow::Cow, collections::HashMap, pin::pin, sync::Arc, time::Instant};
+
+use crate::error::Error;
+use futures_lite::{Stream, StreamExt};
+use gi_battery::{Batteries, Battery, BatteryInfoName, BatterySnapshot};
+use gi_media_player::{
+    registry::{Player, PlayerEvent},
+    selection::{self, Selection, SelectionPolicy},
+};
+use tokio::sync::watch;
+use zbus::{
+    Connection, connection, fdo, interface,
+    object_server::{Interface, InterfaceRef},
+    zvariant::{OwnedObjectPath, Value},
+};
+
+use super::Daemon;
+use crate::commands::{battery::SnapshotResult, media};
+
+pub const SERVICE_NAME: &str = "io.github.fqidz.getinfo";
+const BATTERY_OBJECT_PATH: &str = "/io/github/fqidz/getinfo/battery";
+const MEDIA_OBJECT_PATH: &str = "/io/github/fqidz/getinfo/media";
+
+struct BatteryInterface {
+    snapshot: SnapshotResult,
+}
+
+impl BatteryInterface {
+    fn snapshot(&self) -> fdo::Result<&BatterySnapshot> {
+        self.snapshot
+            .as_ref()
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+fn error_message(snapshot: &SnapshotResult) -> String {
+    snapshot
+        .as_ref()
+        .err()
+        .map(ToString::to_string)
+        .unwrap_or_default()
+}
+
+#[interface(name = "io.github.fqidz.getinfo.Battery")]
+impl BatteryInterface {
+    /// Charge when the battery is full, in µAh
+    #[zbus(property)]
+    fn charge_full(&self) -> fdo::Result<i32> {
+        Ok(self.snapshot()?.charge_full)
+    }
+
+    /// Current charge, in µAh
+    #[zbus(property)]
+    fn charge_now(&self) -> fdo::Result<i32> {
+        Ok(self.snapshot()?.charge_now)
+    }
+
+    /// Current charge, as a fraction of `ChargeFull`
+    #[zbus(property)]
+    fn capacity(&self) -> fdo::Result<f64> {
+        Ok(self.snapshot()?.capacity())
+    }
+
+    /// Current flowing in or out of the battery, in µA
+    #[zbus(property)]
+    fn current_now(&self) -> fdo::Result<i32> {
+        Ok(self.snapshot()?.current_now)
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> fdo::Result<String> {
+        Ok(self.snapshot()?.status.to_string())
+    }
+
+    /// Seconds until the battery is full or empty
+    #[zbus(property)]
+    fn time_remaining(&self) -> fdo::Result<u64> {
+        Ok(self.snapshot()?.time_remaining())
+    }
+
+    /// Why the battery can't be read, or empty if it can
+    #[zbus(property)]
+    fn error(&self) -> String {
+        error_message(&self.snapshot)
+    }
+}
+
+fn property_name(info_name: &BatteryInfoName) -> &'static str {
+    match info_name {
+        BatteryInfoName::ChargeFull => "ChargeFull",
+        BatteryInfoName::ChargeNow => "ChargeNow",
+        BatteryInfoName::Capacity => "Capacity",
+        BatteryInfoName::CurrentNow => "CurrentNow",
+        BatteryInfoName::Status => "Status",
+        BatteryInfoName::TimeRemaining => "TimeRemaining",
+    }
+}
+
+fn property_value(info_name: &BatteryInfoName, snapshot: &BatterySnapshot) -> Value<'static> {
+    match info_name {
+        BatteryInfoName::ChargeFull => snapshot.charge_full.into(),
+        BatteryInfoName::ChargeNow => snapshot.charge_now.into(),
+        BatteryInfoName::Capacity => snapshot.capacity().into(),
+        BatteryInfoName::CurrentNow => snapshot.current_now.into(),
+        BatteryInfoName::Status => snapshot.status.to_string().into(),
+        BatteryInfoName::TimeRemaining => snapshot.time_remaining().into(),
+    }
+}
+
+/// The player selected from every player on the session bus with the default policy.
+struct MediaInterface {
+    selection: Selection,
+}
+
+impl MediaInterface {
+    fn selected(&self) -> Option<&Player> {
+        self.selection.selected()
+    }
+
+    /// The value of every property that's signalled when it changes, by name.
+    fn values(&self) -> HashMap<&'static str, Value<'static>> {
+        HashMap::from([
+            ("Player", self.player().into()),
+            ("Status", self.status().into()),
+            ("Title", self.title().into()),
+            ("Artist", self.artist().into()),
+            ("Album", self.album().into()),
+            ("Length", self.length().into()),
+            ("Volume", self.volume().into()),
+        ])
+    }
+}
+
+#[interface(name = "io.github.fqidz.getinfo.Media")]
+impl MediaInterface {
+    /// The player's name without the `org.mpris.MediaPlayer2.` prefix, e.g. `spotify`
+    #[zbus(property)]
+    fn player(&self) -> String {
+        self.selected()
+            .map(|player| selection::player_name(player).to_string())
+            .unwrap_or_default()
+    }
+
+    /// `Playing`, `Paused` or `Stopped`
+    #[zbus(property)]
+    fn status(&self) -> String {
+        self.selected()
+            .map(|player| player.properties.playback_status.to_string())
+            .unwrap_or_default()
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> String {
+        self.selected()
+            .and_then(|player| player.properties.metadata.title())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Every artist, joined with commas
+    #[zbus(property)]
+    fn artist(&self) -> String {
+        self.selected()
+            .and_then(|player| player.properties.metadata.artist())
+            .map(|artists| artists.join(", "))
+            .unwrap_or_default()
+    }
+
+    #[zbus(property)]
+    fn album(&self) -> String {
+        self.selected()
+            .and_then(|player| player.properties.metadata.album())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Length of the track, in µs
+    #[zbus(property)]
+    fn length(&self) -> i64 {
+        self.selected()
+            .and_then(|player| player.properties.metadata.length())
+            .unwrap_or_default()
+    }
+
+    /// Position in the track, in µs, interpolated from when the player last reported it. Not
+    /// signalled, as it changes continuously while playing.
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.selection
+            .selected_position()
+            .map(|position| position.position(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    /// Volume, as a fraction
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.selected()
+            .and_then(|player| player.properties.volume)
+            .unwrap_or_default()
+    }
+}
+
+/// Connects to the bus at `address`, or the session bus if `None`, and exports every battery and
+/// the selected media player. The service stays up for as long as the returned connection is kept
+/// alive.
+pub async fn serve(daemon: Arc<Daemon>, address: Option<&str>) -> Result<Connection, Error> {
+    let connection = connect(address).await?;
+    for battery in Batteries::init()?.iter() {
+        export_battery(&connection, &daemon, battery).await?;
+    }
+    // Batteries are still served without a session bus to find players on.
+    match daemon.subscribe_media().await {
+        Ok((_, events)) => export_media(&connection, events).await?,
+        Err(err) => eprintln!("getinfo: media isn't served over D-Bus: {}", err),
+    }
+
+    Ok(connection)
+}
+
+async fn connect(address: Option<&str>) -> Result<Connection, Error> {
+    let builder = match address {
+        Some(address) => connection::Builder::address(address),
+        None => connection::Builder::session(),
+    }?;
+    builder
+        .name(SERVICE_NAME)?
+        .build()
+        .await
+        .map_err(Error::from)
+}
+
+/// Exports `battery` at `/io/github/fqidz/getinfo/battery/<name>`, kept up to date by its watcher.
+async fn export_battery(
+    connection: &Connection,
+    daemon: &Daemon,
+    battery: &Battery,
+) -> Result<(), Error> {
+    let receiver = daemon.subscribe_battery(battery)?;
+    let path = OwnedObjectPath::try_from(format!("{}/{}", BATTERY_OBJECT_PATH, battery.name))
+        .map_err(zbus::Error::from)?;
+
+    let interface = BatteryInterface {
+        snapshot: receiver.borrow().clone(),
+    };
+    connection.object_server().at(&path, interface).await?;
+    let interface_ref = connection
+        .object_server()
+        .interface::<_, BatteryInterface>(&path)
+        .await?;
+
+    tokio::spawn(update_battery(interface_ref, receiver));
+    Ok(())
+}
+
+/// Updates the battery object with every new snapshot, signalling the properties that changed.
+async fn update_battery(
+    interface_ref: InterfaceRef<BatteryInterface>,
+    mut receiver: watch::Receiver<SnapshotResult>,
+) {
+    while receiver.changed().await.is_ok() {
+        let snapshot = receiver.borrow_and_update().clone();
+        let mut interface = interface_ref.get_mut().await;
+
+        let mut changed = match (&interface.snapshot, &snapshot) {
+            (Ok(previous), Ok(current)) => BatteryInfoName::ALL
+                .iter()
+                .filter_map(|info_name| {
+                    let value = property_value(info_name, current);
+                    (property_value(info_name, previous) != value)
+                        .then(|| (property_name(info_name), value))
+                })
+                .collect::<HashMap<_, _>>(),
+            (Err(_), Ok(current)) => BatteryInfoName::ALL
+                .iter()
+                .map(|info_name| (property_name(info_name), property_value(info_name, current)))
+                .collect(),
+            (_, Err(_)) => HashMap::new(),
+        };
+        // Getting any other property fails until the battery can be read again.
+        let invalidated: Vec<&str> = match (&interface.snapshot, &snapshot) {
+            (Ok(_), Err(_)) => BatteryInfoName::ALL.iter().map(property_name).collect(),
+            _ => Vec::new(),
+        };
+        let error = error_message(&snapshot);
+        if error != error_message(&interface.snapshot) {
+            changed.insert("Error", error.into());
+        }
+        interface.snapshot = snapshot;
+        drop(interface);
+
+        if changed.is_empty() && invalidated.is_empty() {
+            continue;
+        }
+        // Errors are from the bus connection closing, in which case the service is already gone.
+        let _ = fdo::Properties::properties_changed(
+            interface_ref.signal_emitter(),
+            BatteryInterface::name(),
+            changed,
+            Cow::Owned(invalidated),
+        )
+        .await;
+    }
+}
+
+/// Exports the player selected from the players of `events`, which start with the players that
+/// are already on the bus, at `/io/github/fqidz/getinfo/media`.
+async fn export_media(
+    connection: &Connection,
+    events: impl Stream<Item = PlayerEvent> + Send + 'static,
+) -> Result<(), Error> {
+    let mut events = Box::pin(events);
+    let mut selection = Selection::new(SelectionPolicy::default());
+    media::update_ready(&mut selection, &mut events).await;
+
+    connection
+        .object_server()
+        .at(MEDIA_OBJECT_PATH, MediaInterface { selection })
+        .await?;
+    let interface_ref = connection
+        .object_server()
+        .interface::<_, MediaInterface>(MEDIA_OBJECT_PATH)
+        .await?;
+
+    tokio::spawn(update_media(interface_ref, events));
+    Ok(())
+}
+
+/// Updates the media object with every event, signalling the properties that changed.
+async fn update_media(
+    interface_ref: InterfaceRef<MediaInterface>,
+    events: impl Stream<Item = PlayerEvent>,
+) {
+    let mut events = pin!(events);
+    while let Some(event) = events.next().await {
+        let mut interface = interface_ref.get_mut().await;
+        let previous = interface.values();
+        if !interface.selection.update(event) {
+            continue;
+        }
+        let changed = interface
+            .values()
+            .into_iter()
+            .filter(|(name, value)| previous.get(name) != Some(value))
+            .collect::<HashMap<_, _>>();
+        drop(interface);
+
+        if changed.is_empty() {
+            continue;
+        }
+        // Errors are from the bus connection closing, in which case the service is already gone.
+        let _ = fdo::Properties::properties_changed(
+            interface_ref.signal_emitter(),
+            MediaInterface::name(),
+            changed,
+            Cow::Borrowed(&[]),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{BufRead, BufReader},
+        path::PathBuf,
+        process::{Child, Command, Stdio},
+        time::Duration,
+    };
+
+    use futures_lite::StreamExt;
+    use gi_media_player::registry::PlayerRegistry;
+    use zbus::{
+        fdo::PropertiesChanged,
+        zvariant::{ObjectPath, OwnedValue},
+    };
+
+    use super::*;
+
+    /// A private bus from `dbus-daemon`, stopped when dropped.
+    struct Bus {
+        process: Child,
+        address: String,
+    }
+
+    impl Bus {
+        fn start() -> Self {
+            let mut process = Command::new("dbus-daemon")
+                .args(["--session", "--print-address", "--nofork"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("dbus-daemon should be installed");
+            let mut address = String::new();
+            BufReader::new(process.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            Self {
+                process,
+                address: address.trim().to_string(),
+            }
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    /// A battery backed by a temporary directory instead of `/sys/class/power_supply`.
+    struct FakeBattery {
+        battery: Battery,
+    }
+
+    impl FakeBattery {
+        fn new(name: &str) -> Self {
+            let path: PathBuf =
+                std::env::temp_dir().join(format!("getinfo-dbus-{}-{}", std::process::id(), name));
+            fs::create_dir_all(&path).unwrap();
+            let fake = Self {
+                battery: Battery {
+                    path,
+                    name: name.to_string(),
+                    charge_full: 4_000_000,
+                },
+            };
+            fake.write("charge_now", "3000000");
+            fake.write("current_now", "-1500000");
+            fake.write("status", "Discharging");
+            fake
+        }
+
+        fn write(&self, filename: &str, contents: &str) {
+            fs::write(self.battery.path.join(filename), contents).unwrap();
+        }
+    }
+
+    impl Drop for FakeBattery {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.battery.path);
+        }
+    }
+
+    /// The properties of `org.mpris.MediaPlayer2.Player` that a player must have.
+    struct FakePlayer {
+        title: String,
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl FakePlayer {
+        #[zbus(property)]
+        fn playback_status(&self) -> &str {
+            "Playing"
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<&str, OwnedValue> {
+            HashMap::from([
+                (
+                    "mpris:trackid",
+                    OwnedValue::from(ObjectPath::from_static_str_unchecked("/track/1")),
+                ),
+                ("mpris:length", OwnedValue::from(200_000_000i64)),
+                (
+                    "xesam:title",
+                    Value::from(self.title.as_str()).try_into().unwrap(),
+                ),
+                (
+                    "xesam:artist",
+                    Value::from(vec!["A", "B"]).try_into().unwrap(),
+                ),
+            ])
+        }
+
+        #[zbus(property)]
+        fn volume(&self) -> f64 {
+            0.5
+        }
+
+        #[zbus(property(emits_changed_signal = "false"))]
+        fn position(&self) -> i64 {
+            10_000_000
+        }
+
+        #[zbus(property)]
+        fn can_go_next(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_go_previous(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_play(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_pause(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_seek(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_control(&self) -> bool {
+            true
+        }
+    }
+
+    async fn next_change(
+        changes: &mut (impl futures_lite::Stream<Item = PropertiesChanged> + Unpin),
+    ) -> PropertiesChanged {
+        tokio::time::timeout(Duration::from_secs(5), changes.next())
+            .await
+            .expect("a change should be signalled")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn battery_is_served_and_changes_are_signalled() {
+        let bus = Bus::start();
+        let fake = FakeBattery::new("BAT0");
+        let daemon = Daemon::default();
+
+        let service = connect(Some(&bus.address)).await.unwrap();
+        export_battery(&service, &daemon, &fake.battery)
+            .await
+            .unwrap();
+
+        let client = connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let properties = fdo::PropertiesProxy::builder(&client)
+            .destination(SERVICE_NAME)
+            .unwrap()
+            .path(format!("{}/BAT0", BATTERY_OBJECT_PATH))
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let get = async |name| properties.get(BatteryInterface::name(), name).await;
+
+        assert_eq!(f64::try_from(get("Capacity").await.unwrap()).unwrap(), 0.75);
+        assert_eq!(String::try_from(get("Error").await.unwrap()).unwrap(), "");
+
+        let mut changes = properties.receive_properties_changed().await.unwrap();
+        fake.write("charge_now", "2000000");
+        let change = next_change(&mut changes).await;
+        let args = change.args().unwrap();
+        assert_eq!(args.interface_name(), &BatteryInterface::name());
+        assert_eq!(
+            args.changed_properties()["ChargeNow"],
+            Value::from(2_000_000)
+        );
+        assert_eq!(args.changed_properties()["Capacity"], Value::from(0.5));
+        assert!(!args.changed_properties().contains_key("Status"));
+
+        fake.write("charge_now", "not a number");
+        let change = next_change(&mut changes).await;
+        let args = change.args().unwrap();
+        let Value::Str(error) = &args.changed_properties()["Error"] else {
+            panic!("Error should be a string");
+        };
+        assert!(!error.is_empty());
+        assert!(args.invalidated_properties().contains(&"Capacity"));
+        assert!(get("Capacity").await.is_err());
+
+        fake.write("charge_now", "1000000");
+        let change = next_change(&mut changes).await;
+        let args = change.args().unwrap();
+        assert_eq!(args.changed_properties()["Error"], Value::from(""));
+        assert_eq!(args.changed_properties()["Capacity"], Value::from(0.25));
+    }
+
+    #[tokio::test]
+    async fn media_is_served_and_changes_are_signalled() {
+        let bus = Bus::start();
+        let player = connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name("org.mpris.MediaPlayer2.fake")
+            .unwrap()
+            .serve_at(
+                "/org/mpris/MediaPlayer2",
+                FakePlayer {
+                    title: "First".to_string(),
+                },
+            )
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let service = connect(Some(&bus.address)).await.unwrap();
+        let (_registry, events) = PlayerRegistry::watch(&service).await.unwrap();
+        export_media(&service, events).await.unwrap();
+
+        let client = connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let properties = fdo::PropertiesProxy::builder(&client)
+            .destination(SERVICE_NAME)
+            .unwrap()
+            .path(MEDIA_OBJECT_PATH)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let get = async |name| properties.get(MediaInterface::name(), name).await.unwrap();
+
+        assert_eq!(String::try_from(get("Player").await).unwrap(), "fake");
+        assert_eq!(String::try_from(get("Status").await).unwrap(), "Playing");
+        assert_eq!(String::try_from(get("Title").await).unwrap(), "First");
+        assert_eq!(String::try_from(get("Artist").await).unwrap(), "A, B");
+        assert_eq!(String::try_from(get("Album").await).unwrap(), "");
+        assert_eq!(i64::try_from(get("Length").await).unwrap(), 200_000_000);
+        assert_eq!(f64::try_from(get("Volume").await).unwrap(), 0.5);
+        assert!(i64::try_from(get("Position").await).unwrap() >= 10_000_000);
+
+        let mut changes = properties.receive_properties_changed().await.unwrap();
+        let fake = player
+            .object_server()
+            .interface::<_, FakePlayer>("/org/mpris/MediaPlayer2")
+            .await
+            .unwrap();
+        fake.get_mut().await.title = "Second".to_string();
+        fake.get()
+            .await
+            .metadata_changed(fake.signal_emitter())
+            .await
+            .unwrap();
+        let change = next_change(&mut changes).await;
+        let args = change.args().unwrap();
+        assert_eq!(args.interface_name(), &MediaInterface::name());
+        assert_eq!(args.changed_properties()["Title"], Value::from("Second"));
+        assert!(!args.changed_properties().contains_key("Artist"));
+
+        drop((fake, player));
+        let change = next_change(&mut changes).await;
+        let args = change.args().unwrap();
+        assert_eq!(args.changed_properties()["Player"], Value::from(""));
+        assert_eq!(args.changed_properties()["Title"], Value::from(""));
+    }
+}
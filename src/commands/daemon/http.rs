@@ -0,0 +1,369 @@
+//! Serves the daemon's watchers over HTTP, for web-based widgets:
+//!
+//! - `GET /modules/battery` returns the battery's info as JSON.
+//! - `GET /modules/battery/stream` returns Server-Sent Events, with the info as JSON in an event
+//!   for every change, and an `error` event whenever the battery can't be read.
+//!
+//! Both take options of `getinfo battery` as query parameters, e.g.
+//! `/modules/battery?name=BAT1&fields=capacity,status&units=Ah`.
+//!
+//! As the info isn't authenticated, it's only served on loopback addresses or a Unix socket. On
+//! loopback addresses, requests must also be addressed to it by its `Host` header, so that a
+//! website can't read the info by resolving its own domain to the address (DNS rebinding).
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use gi_battery::Batteries;
+use gi_core::exit_code;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, UnixListener},
+    sync::watch,
+};
+
+use super::{Daemon, prepare_socket_path};
+use crate::{
+    commands::{
+        OutputFormat,
+        battery::{self, BatteryContext, BatteryOutputs, SnapshotResult},
+        http::{Request, Response, write_event, write_event_stream_head},
+    },
+    config::BatteryConfig,
+    error::Error,
+};
+
+/// Query parameters that are passed to `getinfo battery` as `--<name>=<value>`, with `_` as `-`.
+const BATTERY_OPTIONS: &[&str] = &[
+    "name",
+    "units",
+    "precision",
+    "rounding",
+    "duration_format",
+    "format_output",
+    "threshold",
+];
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub async fn bind_tcp(address: SocketAddr) -> Result<Self, Error> {
+        if !address.ip().is_loopback() {
+            return Err(gi_core::Error::InvalidArgument {
+                message: format!(
+                    "Refusing to serve HTTP on {}, as only loopback addresses (e.g. 127.0.0.1) are allowed",
+                    address
+                ),
+            }.into());
+        }
+        TcpListener::bind(address)
+            .await
+            .map(Listener::Tcp)
+            .map_err(|err| Error::Listen {
+                address: address.to_string(),
+                source: Arc::new(err),
+            })
+    }
+
+    pub async fn bind_unix(path: &Path) -> Result<Self, Error> {
+        prepare_socket_path(path).await?;
+        UnixListener::bind(path)
+            .map(Listener::Unix)
+            .map_err(|err| Error::Listen {
+                address: path.display().to_string(),
+                source: Arc::new(err),
+            })
+    }
+}
+
+/// Accepts requests until accepting fails.
+pub async fn serve(daemon: Arc<Daemon>, listener: Listener) -> Result<(), Error> {
+    loop {
+        let daemon = daemon.clone();
+        // Errors in handling are from the client disconnecting, which only ends its own request.
+        match &listener {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept().await?;
+                let address = stream.local_addr()?;
+                tokio::spawn(async move { handle_request(&daemon, stream, Some(address)).await });
+            }
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                tokio::spawn(async move { handle_request(&daemon, stream, None).await });
+            }
+        }
+    }
+}
+
+/// `address` is the TCP address that the request was received on, which its `Host` header has to
+/// name, or `None` for Unix sockets.
+async fn handle_request(
+    daemon: &Daemon,
+    stream: impl AsyncRead + AsyncWrite + Unpin,
+    address: Option<SocketAddr>,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let request = match Request::read(reader).await {
+        Ok(request) => request,
+        Err(err) => {
+            return Response::text(400, format!("{}\n", err))
+                .write(&mut writer)
+                .await;
+        }
+    };
+    if let Some(address) = address
+        && !request
+            .host
+            .as_deref()
+            .is_some_and(|host| is_loopback_host(host, address))
+    {
+        return error_response(403, "The Host header must be the address that is served")
+            .write(&mut writer)
+            .await;
+    }
+    if request.method != "GET" {
+        return error_response(405, "Only GET is allowed")
+            .write(&mut writer)
+            .await;
+    }
+
+    match request.path.as_str() {
+        "/modules/battery" => {
+            let output = battery_output(daemon, &request);
+            daemon.drop_unused_watchers();
+            let response = match output {
+                Ok(output) => Response::new(200, "application/json", output),
+                Err(err) => error_response(status_code(&err), &err.to_string()),
+            };
+            response.write(&mut writer).await
+        }
+        "/modules/battery/stream" => {
+            let (context, receiver) = match battery_context(&request).and_then(|context| {
+                let battery = context.find_battery(&Batteries::init()?)?.clone();
+                Ok((context, daemon.subscribe_battery(&battery)?))
+            }) {
+                Ok(subscription) => subscription,
+                Err(err) => {
+                    return error_response(status_code(&err), &err.to_string())
+                        .write(&mut writer)
+                        .await;
+                }
+            };
+
+            let result = serve_battery_stream(&mut writer, &context, receiver).await;
+            daemon.drop_unused_watchers();
+            result
+        }
+        _ => error_response(404, "Not found").write(&mut writer).await,
+    }
+}
+
+async fn serve_battery_stream(
+    writer: &mut (impl AsyncWrite + Unpin),
+    context: &BatteryContext,
+    receiver: watch::Receiver<SnapshotResult>,
+) -> std::io::Result<()> {
+    write_event_stream_head(writer).await?;
+    let mut outputs = BatteryOutputs::new(context, receiver);
+    while let Some(output) = outputs.next().await {
+        match output {
+            Ok(output) => write_event(writer, None, &output).await?,
+            Err(err) => write_event(writer, Some("error"), &error_json(&err.into())).await?,
+        }
+    }
+    Ok(())
+}
+
+fn battery_output(daemon: &Daemon, request: &Request) -> Result<String, Error> {
+    let context = battery_context(request)?;
+    let battery = context.find_battery(&Batteries::init()?)?.clone();
+    let receiver = daemon.subscribe_battery(&battery)?;
+    let snapshot = receiver.borrow();
+    Ok(context.get_output_string(snapshot.as_ref().map_err(Clone::clone)?))
+}
+
+/// The context of `getinfo battery --output json` with the request's query parameters as
+/// options, so that they are validated the same way as on the command line.
+fn battery_context(request: &Request) -> Result<BatteryContext, Error> {
+    let mut args = vec![
+        "battery".to_string(),
+        "--output".to_string(),
+        OutputFormat::Json.to_string(),
+    ];
+    let mut fields = Vec::new();
+    for (key, value) in request.query_pairs() {
+        match key.as_str() {
+            "fields" => fields.push(value),
+            "timestamp" if value != "false" => args.push("--timestamp".to_string()),
+            "timestamp" => {}
+            key if BATTERY_OPTIONS.contains(&key) => {
+                args.push(format!("--{}={}", key.replace('_', "-"), value));
+            }
+            key => {
+                return Err(gi_core::Error::InvalidArgument {
+                    message: format!(
+                        "Unknown query parameter \"{}\". Expected one of: fields, timestamp, {}",
+                        key,
+                        BATTERY_OPTIONS.join(", ")
+                    ),
+                }
+                .into());
+            }
+        }
+    }
+    // After `--`, so that fields can't be parsed as options
+    args.push("--".to_string());
+    args.extend(fields);
+
+    let matches = battery::cli().try_get_matches_from(args).map_err(|err| {
+        gi_core::Error::InvalidArgument {
+            // Only the message, without clap's usage and `--help` hints
+            message: err
+                .to_string()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches("error: ")
+                .to_string(),
+        }
+    })?;
+    Ok(BatteryContext::from_args(
+        &matches,
+        &BatteryConfig::default(),
+    )?)
+}
+
+/// Whether `host` is `localhost` or the IP of `address`, with the port of `address`, which can be
+/// left out if it's 80.
+fn is_loopback_host(host: &str, address: SocketAddr) -> bool {
+    let ip = match address.ip() {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{}]", ip),
+    };
+    let (name, port) = match host.rsplit_once(':') {
+        // The colons of an IPv6 address without a port are inside the brackets
+        Some((name, port)) if !port.ends_with(']') => (name, port.parse::<u16>().ok()),
+        _ => (host, Some(80)),
+    };
+    port == Some(address.port()) && (name.eq_ignore_ascii_case("localhost") || name == ip)
+}
+
+fn status_code(err: &Error) -> u16 {
+    match err.exit_code() {
+        exit_code::USAGE => 400,
+        exit_code::NOT_FOUND => 404,
+        _ => 500,
+    }
+}
+
+fn error_json(err: &Error) -> String {
+    serde_json::json!({ "error": err.to_string() }).to_string()
+}
+
+fn error_response(status: u16, message: &str) -> Response {
+    Response::new(
+        status,
+        "application/json",
+        serde_json::json!({ "error": message }).to_string(),
+    )
+}
+
+/// `--http` values that are paths are served as Unix sockets, and others as TCP addresses.
+#[derive(Clone)]
+pub enum HttpAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for HttpAddress {
+    type Err = gi_core::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('/') {
+            return Ok(HttpAddress::Unix(PathBuf::from(s)));
+        }
+        s.parse::<SocketAddr>()
+            .map(HttpAddress::Tcp)
+            .map_err(|_| gi_core::Error::InvalidArgument {
+                message: format!(
+                    "Invalid HTTP address \"{}\". Expected an address such as \"127.0.0.1:8080\", or the path of a Unix socket",
+                    s
+                ),
+            })
+    }
+}
+
+impl HttpAddress {
+    pub async fn bind(&self) -> Result<Listener, Error> {
+        match self {
+            HttpAddress::Tcp(address) => Listener::bind_tcp(*address).await,
+            HttpAddress::Unix(path) => Listener::bind_unix(path).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(query: &str) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: "/modules/battery".to_string(),
+            query: query.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn fields_are_not_parsed_as_options() {
+        assert!(battery_context(&request("fields=capacity,status&units=Ah")).is_ok());
+        for query in [
+            "fields=--poll=1",
+            "fields=-p1",
+            "fields=--help",
+            "fields=--watch",
+        ] {
+            assert!(
+                matches!(
+                    battery_context(&request(query)),
+                    Err(Error::Core(gi_core::Error::InvalidArgument { .. }))
+                ),
+                "{query} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn host_must_be_the_served_address() {
+        let v4 = "127.0.0.1:8080".parse().unwrap();
+        for host in ["127.0.0.1:8080", "localhost:8080", "LOCALHOST:8080"] {
+            assert!(is_loopback_host(host, v4), "{host} should be allowed");
+        }
+        for host in [
+            "127.0.0.1",
+            "127.0.0.1:80",
+            "127.0.0.2:8080",
+            "example.com:8080",
+            "localhost.example.com:8080",
+            "[::1]:8080",
+            "",
+        ] {
+            assert!(!is_loopback_host(host, v4), "{host} should be rejected");
+        }
+
+        let v6 = "[::1]:80".parse().unwrap();
+        for host in ["[::1]", "[::1]:80", "localhost"] {
+            assert!(is_loopback_host(host, v6), "{host} should be allowed");
+        }
+        for host in ["[::1]:8080", "::1", "evil.example:80"] {
+            assert!(!is_loopback_host(host, v6), "{host} should be rejected");
+        }
+    }
+}
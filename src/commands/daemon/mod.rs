@@ -0,0 +1,689 @@
+//! `getinfo daemon` owns the module watchers and serves their output over a Unix socket, so that
+//! any number of `getinfo --connect <SUBCOMMAND>` clients can share them.
+//!
+//! The protocol is line-delimited JSON. A client sends a single [`Request`] line, then the daemon
+//! replies with [`Response`] lines until the client disconnects, or until an error:
+//!
+//! ```text
+//! -> {"mode":"watch","module":"battery","battery_name":null,"info_names":["capacity"],...}
+//! <- {"output":"83"}
+//! <- {"output":"82"}
+//! ```
+//!
+//! The socket is only accessible by the user running the daemon.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::error::Error;
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use futures_lite::Stream;
+use gi_battery::{Batteries, Battery, BatteryInfoName};
+use gi_media_player::registry::PlayerEvent;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::watch,
+};
+use zbus::Connection;
+
+use crate::{
+    commands::{
+        OutputDiffer, RunMode,
+        battery::{self, BatteryContext, BatteryOutputs, BatteryWatcher, SnapshotResult},
+        media::{self, MediaContext, MediaOutputs, MediaWatcher},
+        write_line,
+    },
+    config::{ArgMatchesExt, Profile},
+};
+
+mod dbus;
+mod http;
+
+const SOCKET_NAME: &str = "getinfo.sock";
+
+/// Longer request lines are rejected, as a request is a few kilobytes at most.
+const MAX_REQUEST_LENGTH: u64 = 64 * 1024;
+
+#[derive(Serialize, Deserialize)]
+pub struct Request {
+    pub mode: RunMode,
+    #[serde(flatten)]
+    pub module: ModuleRequest,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "module", rename_all = "snake_case")]
+pub enum ModuleRequest {
+    Battery(BatteryContext),
+    Media(MediaContext),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Output(String),
+    /// Sent before the daemon ends the session, so that the client can exit with `exit_code`,
+    /// as it would when running locally.
+    Error {
+        message: String,
+        exit_code: u8,
+    },
+}
+
+impl From<&Error> for Response {
+    fn from(err: &Error) -> Self {
+        Response::Error {
+            message: err.to_string(),
+            exit_code: err.exit_code(),
+        }
+    }
+}
+
+impl From<&gi_core::Error> for Response {
+    fn from(err: &gi_core::Error) -> Self {
+        Response::from(&Error::from(err.clone()))
+    }
+}
+
+pub fn cli() -> Command {
+    Command::new("daemon")
+        .about("Serves info to `--connect` clients over a Unix socket, and optionally over D-Bus and HTTP, sharing watchers between them")
+        .arg(
+            Arg::new("socket")
+                .long("socket")
+                .value_name("SOCKET")
+                .value_parser(value_parser!(PathBuf))
+                .help("Path of the Unix socket to listen on. Defaults to $XDG_RUNTIME_DIR/getinfo.sock"),
+        )
+        .arg(
+            Arg::new("dbus")
+                .long("dbus")
+                .action(ArgAction::SetTrue)
+                .help(format!("Also expose info as the `{}` service on the session bus", dbus::SERVICE_NAME)),
+        )
+        .arg(
+            Arg::new("bus_address")
+                .long("bus-address")
+                .value_name("ADDRESS")
+                .requires("dbus")
+                .help("Address of the bus to expose the D-Bus service on, instead of the session bus (e.g. 'unix:path=/tmp/bus')"),
+        )
+        .arg(
+            Arg::new("http")
+                .long("http")
+                .value_name("ADDRESS")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(http::HttpAddress))
+                .help("Also serve info over HTTP at /modules/battery, and as Server-Sent Events at /modules/battery/stream. ADDRESS is a loopback address (e.g. '127.0.0.1:8080') or the path of a Unix socket. Can be passed multiple times"),
+        )
+}
+
+/// `$XDG_RUNTIME_DIR/getinfo.sock`
+pub fn default_socket_path() -> Result<PathBuf, Error> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join(SOCKET_NAME))
+        .ok_or_else(|| Error::EnvVarNotSet {
+            name: "XDG_RUNTIME_DIR".to_string(),
+        })
+}
+
+#[derive(Default)]
+struct Daemon {
+    /// Keyed by battery name
+    battery_watchers: Mutex<HashMap<String, BatteryWatcher>>,
+    media_watcher: Mutex<Option<MediaWatcher>>,
+}
+
+impl Daemon {
+    /// Subscribes to the watcher of `battery`, starting it if no client has subscribed to it yet.
+    ///
+    /// Call [`drop_unused_watchers`](Self::drop_unused_watchers) once the receiver is dropped, so
+    /// that the watcher is stopped if it was the last one.
+    fn subscribe_battery(
+        &self,
+        battery: &Battery,
+    ) -> Result<watch::Receiver<SnapshotResult>, gi_core::Error> {
+        let mut battery_watchers = self.lock_battery_watchers();
+        if let Some(watcher) = battery_watchers.get(&battery.name) {
+            return Ok(watcher.subscribe());
+        }
+
+        // Watch every file, as later clients may ask for different info names.
+        let files_to_watch: HashSet<_> = battery::files_to_watch(BatteryInfoName::ALL);
+        let watcher = BatteryWatcher::new(battery.clone(), &files_to_watch)?;
+        let receiver = watcher.subscribe();
+        battery_watchers.insert(battery.name.clone(), watcher);
+        Ok(receiver)
+    }
+
+    /// Subscribes to the media watcher, starting it if no client has subscribed to it yet.
+    ///
+    /// Call [`drop_unused_watchers`](Self::drop_unused_watchers) once the stream is dropped, so
+    /// that the watcher is stopped if it was the last one.
+    async fn subscribe_media(
+        &self,
+    ) -> Result<(Connection, impl Stream<Item = PlayerEvent> + use<>), Error> {
+        if let Some(watcher) = &*lock(&self.media_watcher) {
+            return Ok(watcher.subscribe());
+        }
+
+        // Started without holding the lock, so another client may have started one meanwhile.
+        let watcher = MediaWatcher::new().await?;
+        let mut media_watcher = lock(&self.media_watcher);
+        Ok(media_watcher.get_or_insert(watcher).subscribe())
+    }
+
+    /// Stops the watchers that no client is subscribed to anymore. A watching client that
+    /// disconnected is only noticed once its session next writes, i.e. on the next change.
+    fn drop_unused_watchers(&self) {
+        self.lock_battery_watchers()
+            .retain(|_, watcher| watcher.has_subscribers());
+        let mut media_watcher = lock(&self.media_watcher);
+        if media_watcher
+            .as_ref()
+            .is_some_and(|watcher| !watcher.has_subscribers())
+        {
+            *media_watcher = None;
+        }
+    }
+
+    fn lock_battery_watchers(&self) -> MutexGuard<'_, HashMap<String, BatteryWatcher>> {
+        lock(&self.battery_watchers)
+    }
+
+    async fn handle_client(&self, stream: UnixStream) -> Result<(), Error> {
+        let (reader, mut writer) = stream.into_split();
+        let request = match read_request(reader).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(err) => return write_response(&mut writer, &Response::from(&err)).await,
+        };
+
+        match request.module {
+            ModuleRequest::Battery(context) => {
+                let receiver = match Batteries::init()
+                    .and_then(|batteries| self.subscribe_battery(context.find_battery(&batteries)?))
+                {
+                    Ok(receiver) => receiver,
+                    Err(err) => {
+                        return write_response(&mut writer, &Response::from(&err)).await;
+                    }
+                };
+                let result = serve_battery(&mut writer, request.mode, &context, receiver).await;
+                self.drop_unused_watchers();
+                result
+            }
+            ModuleRequest::Media(context) => {
+                let (connection, events) = match self.subscribe_media().await {
+                    Ok(subscription) => subscription,
+                    Err(err) => {
+                        return write_response(&mut writer, &Response::from(&err)).await;
+                    }
+                };
+                let result =
+                    serve_media(&mut writer, request.mode, &context, connection, events).await;
+                self.drop_unused_watchers();
+                result
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .expect("never poisoned, as nothing panics while holding the lock")
+}
+
+/// Reads the request line, or `None` if the client disconnected without sending one.
+async fn read_request(reader: impl AsyncRead + Unpin) -> Result<Option<Request>, Error> {
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_LENGTH));
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && reader.get_ref().limit() == 0 {
+        return Err(Error::InvalidDaemonMessage {
+            message: format!("request is longer than {} bytes", MAX_REQUEST_LENGTH),
+        });
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|err| Error::InvalidDaemonMessage {
+            message: err.to_string(),
+        })
+}
+
+async fn serve_battery(
+    writer: &mut (impl AsyncWrite + Unpin),
+    mode: RunMode,
+    context: &BatteryContext,
+    mut receiver: watch::Receiver<SnapshotResult>,
+) -> Result<(), Error> {
+    match mode {
+        RunMode::Once => {
+            let response = match &*receiver.borrow() {
+                Ok(snapshot) => Response::Output(context.get_output_string(snapshot)),
+                Err(err) => Response::from(err),
+            };
+            write_response(writer, &response).await
+        }
+        RunMode::Poll(options) => {
+            let mut scheduler = match options.scheduler() {
+                Ok(scheduler) => scheduler,
+                Err(err) => return write_response(writer, &Response::from(&err)).await,
+            };
+            let mut differ = OutputDiffer::new(mode, context.diff);
+            loop {
+                scheduler.tick().await;
+                let output = match &*receiver.borrow_and_update() {
+                    Ok(snapshot) => Ok(differ
+                        .next(context.get_output(snapshot))
+                        .map(|output| output.render())),
+                    Err(err) => Err(Response::from(err)),
+                };
+                match output {
+                    Ok(Some(output)) => write_response(writer, &Response::Output(output)).await?,
+                    Ok(None) => {}
+                    Err(response) => return write_response(writer, &response).await,
+                }
+            }
+        }
+        RunMode::Watch => {
+            let mut outputs = BatteryOutputs::new(context, receiver);
+            while let Some(output) = outputs.next().await {
+                match output {
+                    Ok(output) => write_response(writer, &Response::Output(output)).await?,
+                    Err(err) => return write_response(writer, &Response::from(&err)).await,
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn serve_media(
+    writer: &mut (impl AsyncWrite + Unpin),
+    mode: RunMode,
+    context: &MediaContext,
+    connection: Connection,
+    events: impl Stream<Item = PlayerEvent> + Send,
+) -> Result<(), Error> {
+    let mut outputs = match MediaOutputs::new(context, connection, events, mode).await {
+        Ok(outputs) => outputs,
+        Err(err) => return write_response(writer, &Response::from(&err)).await,
+    };
+    if let RunMode::Once = mode {
+        let response = match outputs.selection().selected() {
+            Some(_) => Response::Output(context.get_output(outputs.selection()).render()),
+            None => Response::from(&media::not_found(&context.policy)),
+        };
+        return write_response(writer, &response).await;
+    }
+    while let Some(output) = outputs.next().await {
+        write_response(writer, &Response::Output(output)).await?;
+    }
+    Ok(())
+}
+
+async fn write_response(
+    writer: &mut (impl AsyncWrite + Unpin),
+    response: &Response,
+) -> Result<(), Error> {
+    write_line(
+        writer,
+        &serde_json::to_string(response).expect("always valid"),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn exec(args: &ArgMatches) -> Result<(), Error> {
+    let socket_path = match args.get_one::<PathBuf>("socket") {
+        Some(path) => path.clone(),
+        None => default_socket_path()?,
+    };
+
+    let listener = bind_socket(&socket_path).await?;
+    let daemon = Arc::new(Daemon::default());
+
+    // Kept alive for as long as the daemon runs
+    let _dbus_connection = if args.get_flag("dbus") {
+        Some(
+            dbus::serve(
+                daemon.clone(),
+                args.get_one::<String>("bus_address").map(String::as_str),
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
+
+    for address in args
+        .get_many::<http::HttpAddress>("http")
+        .into_iter()
+        .flatten()
+    {
+        let http_listener = address.bind().await?;
+        let daemon = daemon.clone();
+        tokio::spawn(async move {
+            if let Err(err) = http::serve(daemon, http_listener).await {
+                eprintln!("getinfo: HTTP server stopped: {}", err);
+            }
+        });
+    }
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let daemon = daemon.clone();
+        tokio::spawn(async move {
+            // Errors here are from the client disconnecting, which only ends its own session.
+            let _ = daemon.handle_client(stream).await;
+        });
+    }
+}
+
+/// Listens on `path`, which only the current user can connect to.
+async fn bind_socket(path: &Path) -> Result<UnixListener, Error> {
+    prepare_socket_path(path).await?;
+    let listener = UnixListener::bind(path)?;
+    // Other users could otherwise connect to a socket outside of `$XDG_RUNTIME_DIR`.
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Fails if another daemon is listening on `path`, otherwise removes the socket left behind by a
+/// daemon that didn't exit cleanly.
+async fn prepare_socket_path(path: &Path) -> Result<(), Error> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(Error::DaemonAlreadyRunning {
+                path: path.display().to_string(),
+            });
+        }
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Sends the request for `matches`'s subcommand to the daemon, and prints the daemon's output.
+pub async fn connect(
+    socket_path: Option<&Path>,
+    matches: &ArgMatches,
+    profile: &Profile,
+) -> Result<(), Error> {
+    let request = match matches.subcommand() {
+        // The daemon only sends outputs, so commands would have to be run by the client
+        Some(("battery" | "media", sub_matches))
+            if sub_matches.is_from_command_line("on_change") =>
+        {
+            return Err(gi_core::Error::InvalidArgument {
+                message: "--on-change cannot be used with --connect".to_string(),
+            }
+            .into());
+        }
+        Some(("battery", sub_matches)) => Request {
+            mode: RunMode::from_args(sub_matches, &profile.battery.common.run_mode()),
+            module: ModuleRequest::Battery(BatteryContext::from_args(
+                sub_matches,
+                &profile.battery,
+            )?),
+        },
+        // Controls are sent to the player directly, as there's nothing to share
+        Some(("media", sub_matches)) if sub_matches.subcommand().is_some() => {
+            return Err(gi_core::Error::InvalidArgument {
+                message: "Media controls cannot be used with --connect".to_string(),
+            }
+            .into());
+        }
+        Some(("media", sub_matches)) => Request {
+            mode: RunMode::from_args(sub_matches, &profile.media.common.run_mode()),
+            module: ModuleRequest::Media(MediaContext::from_args(sub_matches, &profile.media)?),
+        },
+        Some((name, _)) => {
+            return Err(Error::UnsupportedByDaemon {
+                name: name.to_string(),
+            });
+        }
+        None => unreachable!("subcommand_required prevents `None`"),
+    };
+
+    let socket_path = match socket_path {
+        Some(path) => path.to_path_buf(),
+        None => default_socket_path()?,
+    };
+    let stream = UnixStream::connect(&socket_path)
+        .await
+        .map_err(|err| Error::ConnectDaemon {
+            path: socket_path.display().to_string(),
+            source: Arc::new(err),
+        })?;
+    let (reader, mut writer) = stream.into_split();
+
+    write_line(
+        &mut writer,
+        &serde_json::to_string(&request).expect("always valid"),
+    )
+    .await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let response =
+            serde_json::from_str::<Response>(&line).map_err(|err| Error::InvalidDaemonMessage {
+                message: err.to_string(),
+            })?;
+        match response {
+            Response::Output(output) => println!("{}", output),
+            Response::Error { message, exit_code } => {
+                return Err(Error::Daemon { message, exit_code });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use gi_battery::{BatterySnapshot, BatteryStatus};
+    use gi_core::exit_code;
+    use tokio::io::{AsyncWriteExt, DuplexStream, Lines, duplex};
+
+    use super::*;
+    use crate::{
+        commands::media::MediaField,
+        config::{BatteryConfig, MediaConfig},
+    };
+
+    fn snapshot(charge_now: i32) -> SnapshotResult {
+        Ok(BatterySnapshot {
+            charge_full: 4_000_000,
+            charge_now,
+            current_now: -1_500_000,
+            status: BatteryStatus::Discharging,
+        })
+    }
+
+    fn battery_context(args: &[&str]) -> BatteryContext {
+        let args = battery::cli().get_matches_from([&["battery"], args].concat());
+        BatteryContext::from_args(&args, &BatteryConfig::default()).unwrap()
+    }
+
+    async fn next_response(lines: &mut Lines<BufReader<DuplexStream>>) -> Option<Response> {
+        let line = lines.next_line().await.unwrap()?;
+        Some(serde_json::from_str(&line).unwrap())
+    }
+
+    /// The responses to `request`, which is sent as is.
+    async fn responses(request: &[u8]) -> Vec<String> {
+        let (client, server) = UnixStream::pair().unwrap();
+        let daemon = Daemon::default();
+        let (reader, mut writer) = client.into_split();
+        let request = request.to_vec();
+        // Written concurrently, as the daemon may stop reading before the end.
+        tokio::spawn(async move { writer.write_all(&request).await });
+        daemon.handle_client(server).await.unwrap();
+
+        let mut lines = BufReader::new(reader).lines();
+        let mut responses = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            responses.push(line);
+        }
+        responses
+    }
+
+    fn error_message(response: &str) -> (String, u8) {
+        match serde_json::from_str(response).unwrap() {
+            Response::Error { message, exit_code } => (message, exit_code),
+            Response::Output(output) => panic!("expected an error, got {output:?}"),
+        }
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let request = Request {
+            mode: RunMode::Watch,
+            module: ModuleRequest::Battery(battery_context(&["capacity", "--precision", "1"])),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        let Request {
+            mode: RunMode::Watch,
+            module: ModuleRequest::Battery(context),
+        } = serde_json::from_str(&json).unwrap()
+        else {
+            panic!("expected a battery watch request: {json}");
+        };
+        assert!(context.info_names == [BatteryInfoName::Capacity]);
+        assert!(context.format.precision.default == Some(1));
+
+        let args = media::cli().get_matches_from([
+            "media",
+            "title,kde:mediaSrc",
+            "--player",
+            "spot*",
+            "--ignore",
+            "firefox*",
+        ]);
+        let request = Request {
+            mode: RunMode::Once,
+            module: ModuleRequest::Media(
+                MediaContext::from_args(&args, &MediaConfig::default()).unwrap(),
+            ),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains(r#""module":"media""#), "{json}");
+        let Request {
+            mode: RunMode::Once,
+            module: ModuleRequest::Media(context),
+        } = serde_json::from_str(&json).unwrap()
+        else {
+            panic!("expected a media request: {json}");
+        };
+        assert_eq!(
+            context.fields,
+            [
+                MediaField::Title,
+                MediaField::Extra("kde:mediaSrc".to_string())
+            ]
+        );
+        assert_eq!(context.policy.player.as_deref(), Some("spot*"));
+        assert_eq!(context.policy.ignore, ["firefox*"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_are_answered_with_an_error() {
+        for request in [&b"not json\n"[..], br#"{"mode":"once","module":"cpu"}"#] {
+            let responses = responses(request).await;
+            assert_eq!(responses.len(), 1);
+            let (message, exit_code) = error_message(&responses[0]);
+            assert!(message.starts_with("Invalid daemon message"), "{message}");
+            assert_eq!(exit_code, exit_code::FAILURE);
+        }
+    }
+
+    #[tokio::test]
+    async fn long_requests_are_rejected() {
+        // Exactly as long as is read, as closing a socket with unread data resets it.
+        let request = vec![b'a'; MAX_REQUEST_LENGTH as usize];
+        let responses = responses(&request).await;
+        assert_eq!(responses.len(), 1);
+        let (message, _) = error_message(&responses[0]);
+        assert!(
+            message.ends_with("request is longer than 65536 bytes"),
+            "{message}"
+        );
+    }
+
+    #[tokio::test]
+    async fn disconnecting_without_a_request_sends_nothing() {
+        assert!(responses(b"").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn battery_watch_ends_after_an_error() {
+        let context = battery_context(&["capacity"]);
+        let (sender, receiver) = watch::channel(snapshot(3_000_000));
+        let (client, mut server) = duplex(1024);
+        let session = tokio::spawn(async move {
+            serve_battery(&mut server, RunMode::Watch, &context, receiver).await
+        });
+        let mut lines = BufReader::new(client).lines();
+
+        assert!(matches!(
+            next_response(&mut lines).await,
+            Some(Response::Output(output)) if output == "75"
+        ));
+        sender.send_modify(|value| *value = snapshot(2_000_000));
+        assert!(matches!(
+            next_response(&mut lines).await,
+            Some(Response::Output(output)) if output == "50"
+        ));
+        sender.send_modify(|value| {
+            *value = Err(gi_core::Error::BatteryNotFound {
+                name: "BAT0".to_string(),
+            })
+        });
+        assert!(matches!(
+            next_response(&mut lines).await,
+            Some(Response::Error { exit_code, .. }) if exit_code == exit_code::NOT_FOUND
+        ));
+        session.await.unwrap().unwrap();
+        assert!(next_response(&mut lines).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn battery_poll_ends_after_an_error() {
+        let context = battery_context(&["capacity"]);
+        let mode = RunMode::from_args(
+            &battery::cli().get_matches_from(["battery", "--poll", "10"]),
+            &BatteryConfig::default().common.run_mode(),
+        );
+        let (_sender, receiver) = watch::channel(Err(gi_core::Error::BatteryNotFound {
+            name: "BAT0".to_string(),
+        }));
+        let mut writer = Vec::new();
+        serve_battery(&mut writer, mode, &context, receiver)
+            .await
+            .unwrap();
+        let responses = String::from_utf8(writer).unwrap();
+        assert_eq!(responses.lines().count(), 1);
+        assert_eq!(error_message(responses.trim_end()).1, exit_code::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn socket_is_only_accessible_by_the_user() {
+        let path = std::env::temp_dir().join(format!("getinfo-test-{}.sock", std::process::id()));
+        let _listener = bind_socket(&path).await.unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
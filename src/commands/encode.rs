@@ -0,0 +1,154 @@
+//! Encoding outputs as CSV/TSV records and YAML documents, which are simple enough for outputs'
+//! flat fields to not need a dependency.
+
+use serde_json::Value;
+
+/// A CSV record (RFC 4180), where values are quoted if they contain a comma, quote, or newline.
+pub fn csv_record(values: &[&str]) -> String {
+    values
+        .iter()
+        .map(|value| {
+            if value.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A TSV record, where tabs and newlines in values are replaced with spaces, as TSV has no way to
+/// escape them.
+pub fn tsv_record(values: &[&str]) -> String {
+    values
+        .iter()
+        .map(|value| value.replace(['\t', '\n', '\r'], " "))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// A YAML document starting with `---`, so that a stream of outputs is a stream of documents.
+pub fn yaml_document(value: &Value) -> String {
+    let mut document = String::from("---");
+    write_yaml(&mut document, value, 0);
+    document
+}
+
+fn write_yaml(output: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                output.push('\n');
+                output.push_str(&"  ".repeat(indent));
+                output.push_str(&yaml_scalar(&Value::String(key.clone())));
+                output.push(':');
+                write_yaml(output, value, indent + 1);
+            }
+        }
+        Value::Array(values) if !values.is_empty() => {
+            for value in values {
+                output.push('\n');
+                output.push_str(&"  ".repeat(indent));
+                output.push('-');
+                write_yaml(output, value, indent + 1);
+            }
+        }
+        value => {
+            output.push(' ');
+            output.push_str(&yaml_scalar(value));
+        }
+    }
+}
+
+/// Strings are double-quoted unless they're plain words, as JSON strings are also valid YAML.
+/// Words that YAML 1.1 reads as booleans or null, e.g. `y` and `off`, are quoted too.
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::String(s)
+            if !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+                && s.starts_with(|c: char| c.is_alphabetic())
+                && !matches!(
+                    s.to_lowercase().as_str(),
+                    "true" | "false" | "y" | "n" | "yes" | "no" | "on" | "off" | "null"
+                ) =>
+        {
+            s.clone()
+        }
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn csv_quotes_when_needed() {
+        assert_eq!(csv_record(&["plain", "two words", ""]), "plain,two words,");
+        assert_eq!(csv_record(&["a,b"]), "\"a,b\"");
+        assert_eq!(csv_record(&["say \"hi\""]), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_record(&["line\nbreak", "x"]), "\"line\nbreak\",x");
+        assert_eq!(csv_record(&["cr\r"]), "\"cr\r\"");
+    }
+
+    #[test]
+    fn tsv_replaces_tabs_and_newlines() {
+        assert_eq!(tsv_record(&["a", "b c", ""]), "a\tb c\t");
+        assert_eq!(tsv_record(&["a\tb", "c\r\nd"]), "a b\tc  d");
+        assert_eq!(tsv_record(&["\"quoted\", comma"]), "\"quoted\", comma");
+    }
+
+    #[test]
+    fn yaml_documents() {
+        let value = json!({
+            "status": "Discharging",
+            "capacity": 0.5,
+            "time_remaining": { "h": 1, "m": 2, "s": 3 },
+            "states": {},
+            "artists": ["A", "B"],
+        });
+        assert_eq!(
+            yaml_document(&value),
+            "---\nstatus: Discharging\ncapacity: 0.5\ntime_remaining:\n  h: 1\n  m: 2\n  s: 3\nstates: {}\nartists:\n  - A\n  - B"
+        );
+        assert_eq!(yaml_document(&json!("x")), "--- x");
+    }
+
+    #[test]
+    fn yaml_quotes_strings_that_arent_plain_words() {
+        for (string, scalar) in [
+            ("Playing", "Playing"),
+            ("time_remaining", "time_remaining"),
+            ("", "\"\""),
+            ("two words", "\"two words\""),
+            ("1.5", "\"1.5\""),
+            ("12:30", "\"12:30\""),
+            ("-x", "\"-x\""),
+            ("a: b", "\"a: b\""),
+            ("#comment", "\"#comment\""),
+            ("say \"hi\"", "\"say \\\"hi\\\"\""),
+            ("line\nbreak", "\"line\\nbreak\""),
+        ] {
+            assert_eq!(yaml_scalar(&Value::from(string)), scalar);
+        }
+    }
+
+    #[test]
+    fn yaml_quotes_booleans_and_null() {
+        for string in [
+            "true", "False", "y", "Y", "n", "N", "yes", "No", "on", "OFF", "null",
+        ] {
+            assert_eq!(yaml_scalar(&Value::from(string)), format!("\"{}\"", string));
+        }
+        assert_eq!(yaml_scalar(&json!(true)), "true");
+        assert_eq!(yaml_scalar(&json!(null)), "null");
+        assert_eq!(yaml_scalar(&json!(42)), "42");
+    }
+}
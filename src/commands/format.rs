@@ -0,0 +1,822 @@
+//! Precision, rounding, and templates for rendering an [`Output`](super::Output).
+
+use std::{fmt::Display, str::FromStr};
+
+use gi_core::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::commands::threshold::State;
+
+/// Most decimal places that numbers can be rendered with, about as many significant digits as an
+/// `f64` has.
+pub const MAX_PRECISION: usize = 17;
+
+/// How numbers are rounded to their precision.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    /// Round half away from zero
+    #[default]
+    Nearest,
+    Down,
+    Up,
+    /// Round towards zero
+    Truncate,
+}
+
+/// Number of decimal places to render numbers with, e.g. `1,capacity=0` renders every number with
+/// one decimal place, except for capacity which has none.
+#[derive(Clone, Default, PartialEq)]
+pub struct Precision {
+    pub default: Option<usize>,
+    pub fields: Vec<(String, usize)>,
+}
+
+/// A format string for the whole output, e.g. `{capacity:>3.0}% {status}`.
+///
+/// Fields are written as `{name}` or `{name:spec}`, where `spec` is
+/// `[[fill]align][width][.precision]` like in Rust's `format!`, with `align` being `<`, `^`, or
+/// `>`. Literal braces are written as `{{` and `}}`.
+///
+/// Sections are only rendered in a state, e.g. `{#critical}!{/critical}` when the output is
+/// critical, or `{#capacity.warning}low{/capacity.warning}` when capacity is at its warning
+/// threshold. Inverted sections like `{^good}...{/good}` are rendered when not in the state.
+/// Fields without a threshold are always good.
+#[derive(Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Clone, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Field {
+        name: String,
+        spec: FormatSpec,
+    },
+    Section {
+        condition: Condition,
+        inverted: bool,
+        parts: Vec<TemplatePart>,
+    },
+}
+
+#[derive(Clone, PartialEq)]
+struct Condition {
+    /// The whole output's state if `None`
+    field: Option<String>,
+    state: State,
+}
+
+/// What a [`Template`] is rendered from.
+pub trait TemplateContext {
+    /// The rendered value of the field named `name` with `precision` if set, and whether the value
+    /// is a number.
+    fn field(&self, name: &str, precision: Option<usize>) -> Option<(String, bool)>;
+
+    /// The state of the field named `name`, or of the whole output if `None`.
+    fn state(&self, name: Option<&str>) -> Option<State>;
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct FormatSpec {
+    pub fill: Option<char>,
+    pub align: Option<Align>,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Rounding {
+    pub fn round(&self, value: f64, precision: usize) -> f64 {
+        let factor = 10f64.powi(precision.min(MAX_PRECISION) as i32);
+        let scaled = value * factor;
+        let rounded = match self {
+            Rounding::Nearest => scaled.round(),
+            Rounding::Down => scaled.floor(),
+            Rounding::Up => scaled.ceil(),
+            Rounding::Truncate => scaled.trunc(),
+        };
+        rounded / factor
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rounding::Nearest => "nearest",
+            Rounding::Down => "down",
+            Rounding::Up => "up",
+            Rounding::Truncate => "truncate",
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Self::Nearest),
+            "down" => Ok(Self::Down),
+            "up" => Ok(Self::Up),
+            "truncate" => Ok(Self::Truncate),
+            _ => Err(Self::Err::InvalidArgument {
+                message: format!(
+                    "Invalid rounding \"{}\". Expected \"nearest\", \"down\", \"up\", or \"truncate\"",
+                    s
+                ),
+            }),
+        }
+    }
+}
+
+impl Display for Rounding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Precision {
+    pub fn for_field(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, precision)| *precision)
+            .or(self.default)
+    }
+
+    /// Replaces every field name with what `resolve` returns for it, e.g. to check that the
+    /// fields exist and to replace aliases with their canonical names.
+    pub fn resolve_names(
+        mut self,
+        resolve: impl Fn(&str) -> Result<String, Error>,
+    ) -> Result<Self, Error> {
+        for (name, _) in &mut self.fields {
+            *name = resolve(name)?;
+        }
+        Ok(self)
+    }
+}
+
+impl FromStr for Precision {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_decimal_places = |value: &str| {
+            let decimal_places = value.parse::<usize>().map_err(|_| Error::InvalidArgument {
+                message: format!(
+                    "Invalid precision \"{}\". Expected a number of decimal places",
+                    value
+                ),
+            })?;
+            check_decimal_places(decimal_places)
+        };
+
+        let mut precision = Precision::default();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            match item.split_once('=') {
+                Some((name, value)) => precision
+                    .fields
+                    .push((name.trim().to_string(), parse_decimal_places(value.trim())?)),
+                None => precision.default = Some(parse_decimal_places(item)?),
+            }
+        }
+        Ok(precision)
+    }
+}
+
+fn check_decimal_places(decimal_places: usize) -> Result<usize, Error> {
+    if decimal_places > MAX_PRECISION {
+        return Err(Error::InvalidArgument {
+            message: format!(
+                "Invalid precision {}. Expected at most {} decimal places",
+                decimal_places, MAX_PRECISION
+            ),
+        });
+    }
+    Ok(decimal_places)
+}
+
+impl Display for Precision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let items = self
+            .default
+            .iter()
+            .map(usize::to_string)
+            .chain(
+                self.fields
+                    .iter()
+                    .map(|(name, precision)| format!("{}={}", name, precision)),
+            )
+            .collect::<Vec<_>>();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+impl Serialize for Precision {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Also accepts a plain number, so that configs can have `precision = 1`.
+impl<'de> Deserialize<'de> for Precision {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum PrecisionValue {
+            DecimalPlaces(usize),
+            String(String),
+        }
+
+        match PrecisionValue::deserialize(deserializer)? {
+            PrecisionValue::DecimalPlaces(decimal_places) => Ok(Precision {
+                default: Some(check_decimal_places(decimal_places).map_err(de::Error::custom)?),
+                fields: Vec::new(),
+            }),
+            PrecisionValue::String(s) => Precision::from_str(&s).map_err(de::Error::custom),
+        }
+    }
+}
+
+impl Template {
+    /// Names of the fields in the template, including those in sections' conditions.
+    pub fn field_names(&self) -> Vec<&str> {
+        fn collect<'a>(parts: &'a [TemplatePart], names: &mut Vec<&'a str>) {
+            for part in parts {
+                match part {
+                    TemplatePart::Literal(_) => {}
+                    TemplatePart::Field { name, .. } => names.push(name),
+                    TemplatePart::Section {
+                        condition, parts, ..
+                    } => {
+                        names.extend(condition.field.as_deref());
+                        collect(parts, names);
+                    }
+                }
+            }
+        }
+
+        let mut names = Vec::new();
+        collect(&self.parts, &mut names);
+        names
+    }
+
+    /// See [`Precision::resolve_names`].
+    pub fn resolve_names(
+        mut self,
+        resolve: impl Fn(&str) -> Result<String, Error>,
+    ) -> Result<Self, Error> {
+        fn resolve_parts(
+            parts: &mut [TemplatePart],
+            resolve: &impl Fn(&str) -> Result<String, Error>,
+        ) -> Result<(), Error> {
+            for part in parts {
+                match part {
+                    TemplatePart::Literal(_) => {}
+                    TemplatePart::Field { name, .. } => *name = resolve(name)?,
+                    TemplatePart::Section {
+                        condition, parts, ..
+                    } => {
+                        if let Some(field) = &mut condition.field {
+                            *field = resolve(field)?;
+                        }
+                        resolve_parts(parts, resolve)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        resolve_parts(&mut self.parts, &resolve)?;
+        Ok(self)
+    }
+
+    pub fn render(&self, context: &impl TemplateContext) -> String {
+        let mut output = String::new();
+        render_parts(&self.parts, context, &mut output);
+        output
+    }
+}
+
+fn render_parts(parts: &[TemplatePart], context: &impl TemplateContext, output: &mut String) {
+    for part in parts {
+        match part {
+            TemplatePart::Literal(literal) => output.push_str(literal),
+            TemplatePart::Field { name, spec } => {
+                let (value, is_number) = context.field(name, spec.precision).unwrap_or_default();
+                let default_align = if is_number { Align::Right } else { Align::Left };
+                output.push_str(&spec.pad(&value, default_align));
+            }
+            TemplatePart::Section {
+                condition,
+                inverted,
+                parts,
+            } => {
+                let state = context
+                    .state(condition.field.as_deref())
+                    .unwrap_or(State::Good);
+                if (state == condition.state) != *inverted {
+                    render_parts(parts, context, output);
+                }
+            }
+        }
+    }
+}
+
+/// A section that's still being parsed.
+struct OpenSection {
+    /// As written in the template, to match the closing tag against
+    tag: String,
+    condition: Condition,
+    inverted: bool,
+    parts: Vec<TemplatePart>,
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: String| Error::InvalidArgument {
+            message: format!("Invalid template \"{}\": {}", s, message),
+        };
+
+        let mut sections: Vec<OpenSection> = Vec::new();
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => {
+                    return Err(invalid(
+                        "unmatched '}'. Literal braces are written as '}}'".to_string(),
+                    ));
+                }
+                '{' => {
+                    let mut tag = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => tag.push(c),
+                            None => {
+                                return Err(invalid(
+                                    "unmatched '{'. Literal braces are written as '{{'".to_string(),
+                                ));
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    if let Some(section) = tag.strip_prefix('#').or_else(|| tag.strip_prefix('^')) {
+                        sections.push(OpenSection {
+                            tag: section.trim().to_string(),
+                            condition: Condition::from_str(section.trim())?,
+                            inverted: tag.starts_with('^'),
+                            parts: std::mem::take(&mut parts),
+                        });
+                        continue;
+                    }
+                    if let Some(closing) = tag.strip_prefix('/') {
+                        let section = sections
+                            .pop()
+                            .filter(|section| section.tag == closing.trim())
+                            .ok_or_else(|| {
+                                invalid(format!("'{{/{}}}' doesn't close a section", closing))
+                            })?;
+                        let section_parts = std::mem::replace(&mut parts, section.parts);
+                        parts.push(TemplatePart::Section {
+                            condition: section.condition,
+                            inverted: section.inverted,
+                            parts: section_parts,
+                        });
+                        continue;
+                    }
+
+                    let (name, spec) = match tag.split_once(':') {
+                        Some((name, spec)) => (name, FormatSpec::from_str(spec)?),
+                        None => (tag.as_str(), FormatSpec::default()),
+                    };
+                    let name = name.trim();
+                    if name.is_empty() {
+                        return Err(invalid("field names can't be empty".to_string()));
+                    }
+                    parts.push(TemplatePart::Field {
+                        name: name.to_string(),
+                        spec,
+                    });
+                }
+                c => literal.push(c),
+            }
+        }
+        if let Some(section) = sections.pop() {
+            return Err(invalid(format!(
+                "section '{}' is never closed with '{{/{}}}'",
+                section.tag, section.tag
+            )));
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    /// `state` or `field.state`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit_once('.') {
+            Some((field, state)) => Ok(Self {
+                field: Some(field.to_string()),
+                state: State::from_str(state)?,
+            }),
+            None => Ok(Self {
+                field: None,
+                state: State::from_str(s)?,
+            }),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}.{}", field, self.state),
+            None => write!(f, "{}", self.state),
+        }
+    }
+}
+
+fn fmt_parts(parts: &[TemplatePart], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for part in parts {
+        match part {
+            TemplatePart::Literal(literal) => {
+                write!(f, "{}", literal.replace('{', "{{").replace('}', "}}"))?
+            }
+            TemplatePart::Field { name, spec } if *spec == FormatSpec::default() => {
+                write!(f, "{{{}}}", name)?
+            }
+            TemplatePart::Field { name, spec } => write!(f, "{{{}:{}}}", name, spec)?,
+            TemplatePart::Section {
+                condition,
+                inverted,
+                parts,
+            } => {
+                write!(f, "{{{}{}}}", if *inverted { '^' } else { '#' }, condition)?;
+                fmt_parts(parts, f)?;
+                write!(f, "{{/{}}}", condition)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_parts(&self.parts, f)
+    }
+}
+
+impl Serialize for Template {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Template::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
+impl FormatSpec {
+    /// Pads `value` to the spec's width, aligned to `default_align` if the spec has no alignment.
+    /// Like in `format!`, numbers are right-aligned and everything else is left-aligned.
+    pub fn pad(&self, value: &str, default_align: Align) -> String {
+        let Some(width) = self.width else {
+            return value.to_string();
+        };
+        let padding = width.saturating_sub(value.chars().count());
+        let (before, after) = match self.align.unwrap_or(default_align) {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+        let fill = self.fill.unwrap_or(' ');
+        let mut padded = String::with_capacity(value.len() + padding);
+        padded.extend(std::iter::repeat_n(fill, before));
+        padded.push_str(value);
+        padded.extend(std::iter::repeat_n(fill, after));
+        padded
+    }
+}
+
+impl Align {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        }
+    }
+
+    fn as_char(&self) -> char {
+        match self {
+            Align::Left => '<',
+            Align::Center => '^',
+            Align::Right => '>',
+        }
+    }
+}
+
+impl FromStr for FormatSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidArgument {
+            message: format!(
+                "Invalid format spec \"{}\". Expected \"[[fill]align][width][.precision]\", e.g. \">3.0\"",
+                s
+            ),
+        };
+
+        let mut spec = FormatSpec::default();
+        let mut rest = s;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(fill), Some(align)) if Align::from_char(align).is_some() => {
+                spec.fill = Some(fill);
+                spec.align = Align::from_char(align);
+                rest = &s[fill.len_utf8() + 1..];
+            }
+            (Some(align), _) if Align::from_char(align).is_some() => {
+                spec.align = Align::from_char(align);
+                rest = &s[1..];
+            }
+            _ => {}
+        }
+
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => (width, Some(precision)),
+            None => (rest, None),
+        };
+        if !width.is_empty() {
+            spec.width = Some(width.parse().map_err(|_| invalid())?);
+        }
+        if let Some(precision) = precision {
+            let precision = precision.parse().map_err(|_| invalid())?;
+            spec.precision = Some(check_decimal_places(precision)?);
+        }
+        Ok(spec)
+    }
+}
+
+impl Display for FormatSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(fill) = self.fill {
+            write!(f, "{}", fill)?;
+        }
+        if let Some(align) = self.align {
+            write!(f, "{}", align.as_char())?;
+        }
+        if let Some(width) = self.width {
+            write!(f, "{}", width)?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounding_modes() {
+        let round = |rounding: Rounding, value| rounding.round(value, 1);
+
+        assert_eq!(round(Rounding::Nearest, 1.25), 1.3);
+        assert_eq!(round(Rounding::Down, 1.25), 1.2);
+        assert_eq!(round(Rounding::Up, 1.21), 1.3);
+        assert_eq!(round(Rounding::Truncate, 1.29), 1.2);
+
+        assert_eq!(round(Rounding::Nearest, -1.25), -1.3);
+        assert_eq!(round(Rounding::Down, -1.21), -1.3);
+        assert_eq!(round(Rounding::Up, -1.29), -1.2);
+        assert_eq!(round(Rounding::Truncate, -1.29), -1.2);
+
+        assert_eq!(Rounding::Nearest.round(83.5, 0), 84.0);
+        assert_eq!(Rounding::Down.round(83.5, 0), 83.0);
+    }
+
+    #[test]
+    fn rounding_to_max_precision_is_finite() {
+        let rounded = Rounding::Nearest.round(0.1, MAX_PRECISION);
+        assert!(rounded.is_finite());
+        assert_eq!(rounded, 0.1);
+    }
+
+    #[test]
+    fn precision_is_parsed() {
+        let precision = Precision::from_str("1, capacity=0").unwrap();
+        assert_eq!(precision.default, Some(1));
+        assert_eq!(precision.fields, vec![("capacity".to_string(), 0)]);
+        assert_eq!(precision.to_string(), "1,capacity=0");
+    }
+
+    #[test]
+    fn precision_is_limited() {
+        assert!(Precision::from_str(&MAX_PRECISION.to_string()).is_ok());
+        for s in ["18", "400", "70000", "1,capacity=18"] {
+            assert!(
+                matches!(Precision::from_str(s), Err(Error::InvalidArgument { .. })),
+                "{} should be rejected",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn precision_in_config_is_limited() {
+        #[derive(Deserialize)]
+        struct Config {
+            precision: Precision,
+        }
+
+        let config = toml::from_str::<Config>("precision = 2").unwrap();
+        assert_eq!(config.precision.default, Some(2));
+        assert!(toml::from_str::<Config>("precision = 400").is_err());
+        assert!(toml::from_str::<Config>("precision = \"capacity=400\"").is_err());
+    }
+
+    #[test]
+    fn format_spec_precision_is_limited() {
+        let spec = FormatSpec::from_str("0>5.17").unwrap();
+        assert_eq!(spec.fill, Some('0'));
+        assert_eq!(spec.width, Some(5));
+        assert_eq!(spec.precision, Some(17));
+
+        for s in [".18", ">3.70000"] {
+            assert!(
+                matches!(FormatSpec::from_str(s), Err(Error::InvalidArgument { .. })),
+                "{} should be rejected",
+                s
+            );
+        }
+    }
+
+    /// A battery at 15% capacity, which is critical, while discharging.
+    struct Context;
+
+    impl TemplateContext for Context {
+        fn field(&self, name: &str, precision: Option<usize>) -> Option<(String, bool)> {
+            match name {
+                "capacity" => Some((format!("{:.*}", precision.unwrap_or(0), 15.0), true)),
+                "status" => Some(("Discharging".to_string(), false)),
+                _ => None,
+            }
+        }
+
+        fn state(&self, name: Option<&str>) -> Option<State> {
+            match name {
+                None | Some("capacity") => Some(State::Critical),
+                _ => None,
+            }
+        }
+    }
+
+    fn render(template: &str) -> String {
+        Template::from_str(template).unwrap().render(&Context)
+    }
+
+    fn parse_error(template: &str) -> String {
+        match Template::from_str(template) {
+            Ok(_) => panic!("{} should be rejected", template),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn fields_are_rendered_with_their_spec() {
+        assert_eq!(render("{capacity}% {status}"), "15% Discharging");
+        assert_eq!(render("[{capacity:>4.1}]"), "[15.0]");
+        assert_eq!(render("[{capacity:5}]"), "[   15]");
+        assert_eq!(render("[{status:*^13}]"), "[*Discharging*]");
+        assert_eq!(render("[{status:>12}]"), "[ Discharging]");
+    }
+
+    #[test]
+    fn sections_are_rendered_in_their_state() {
+        assert_eq!(render("{#critical}!{/critical}{capacity}"), "!15");
+        assert_eq!(render("{#warning}!{/warning}{capacity}"), "15");
+        assert_eq!(render("{^good}not good{/good}"), "not good");
+        assert_eq!(render("{^critical}fine{/critical}"), "");
+        assert_eq!(
+            render("{#capacity.critical}low {capacity}%{/capacity.critical}"),
+            "low 15%"
+        );
+        // Fields without a threshold are always good
+        assert_eq!(
+            render("{#status.good}{status}{/status.good}"),
+            "Discharging"
+        );
+        assert_eq!(
+            render("{#critical}{^status.warning}{status}{/status.warning}{/critical}"),
+            "Discharging"
+        );
+    }
+
+    #[test]
+    fn braces_are_escaped_by_doubling() {
+        assert_eq!(render("{{{capacity}}}"), "{15}");
+        assert_eq!(render("}}{{"), "}{");
+
+        let template = Template::from_str("{{x}} {#critical}{capacity:>3}{/critical}").unwrap();
+        assert_eq!(
+            template.to_string(),
+            "{{x}} {#critical}{capacity:>3}{/critical}"
+        );
+        assert!(Template::from_str(&template.to_string()).unwrap() == template);
+    }
+
+    #[test]
+    fn field_names_include_conditions() {
+        let template =
+            Template::from_str("{#capacity.warning}{status}{/capacity.warning}").unwrap();
+        assert_eq!(template.field_names(), vec!["capacity", "status"]);
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert!(parse_error("{capacity").contains("unmatched '{'"));
+        assert!(parse_error("capacity}").contains("unmatched '}'"));
+        assert!(parse_error("{}").contains("field names can't be empty"));
+        assert!(parse_error("{#critical}!").contains("section 'critical' is never closed"));
+        assert!(parse_error("{#critical}!{/warning}").contains("'{/warning}' doesn't close"));
+        assert!(parse_error("{/critical}").contains("'{/critical}' doesn't close"));
+        assert!(matches!(
+            Template::from_str("{#capacity.low}!{/capacity.low}"),
+            Err(Error::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected_when_resolved() {
+        let resolve = |name: &str| match name {
+            "capacity" | "status" => Ok(name.to_string()),
+            "percentage" => Ok("capacity".to_string()),
+            _ => Err(Error::InvalidInfoName {
+                name: name.to_string(),
+            }),
+        };
+
+        let template = Template::from_str("{percentage}")
+            .unwrap()
+            .resolve_names(resolve)
+            .unwrap();
+        assert_eq!(template.to_string(), "{capacity}");
+
+        for template in ["{nope}", "{#nope.critical}!{/nope.critical}"] {
+            assert!(
+                matches!(
+                    Template::from_str(template).unwrap().resolve_names(resolve),
+                    Err(Error::InvalidInfoName { .. })
+                ),
+                "{} should be rejected",
+                template
+            );
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! `--on-change`, which runs a command whenever fields change while watching or polling.
+//!
+//! The command is run with `sh -c`, with the changed fields exported as `GETINFO_<FIELD>` (e.g.
+//! `GETINFO_STATUS=Discharging`), their names as `GETINFO_CHANGED` (e.g. `status,capacity`), and
+//! the changed fields as a JSON object on stdin. The first output counts as every field changing.
+//!
+//! Changes are debounced, so that a burst of changes runs the command once with all of them. A
+//! burst that never settles still runs it every [`MAX_DEBOUNCES`] times the debounce.
+//! Once `limit` commands are running, later changes are merged until one finishes, so that a slow
+//! command runs at most once more rather than piling up.
+
+use std::{io, process::Stdio, sync::Arc, time::Duration};
+
+use gi_core::Error;
+use serde_json::{Map, Value};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Command,
+    sync::{Semaphore, mpsc},
+    time::{Instant, sleep, sleep_until},
+};
+
+use crate::{
+    commands::Output,
+    config::{ArgMatchesExt, OnChangeConfig},
+};
+
+/// How many times its debounce a burst of changes can delay the command for.
+pub const MAX_DEBOUNCES: u32 = 4;
+
+/// A command to run on changes, resolved from `--on-change` and the config.
+#[derive(Clone)]
+pub struct OnChange {
+    pub command: String,
+    pub debounce: Duration,
+    /// How many commands can run at the same time.
+    pub limit: usize,
+}
+
+/// The changed fields of an output, in order, with their text and JSON values.
+#[derive(Default)]
+struct Changes {
+    fields: Vec<ChangedField>,
+}
+
+struct ChangedField {
+    label: String,
+    text: String,
+    json: Value,
+}
+
+/// Sends changes of the outputs it's given to the task that runs the command.
+pub struct OnChangeNotifier<'a> {
+    previous_output: Option<Output<'a>>,
+    sender: mpsc::UnboundedSender<Changes>,
+}
+
+impl OnChange {
+    /// `None` if no command was set.
+    pub fn from_args(
+        args: &clap::ArgMatches,
+        config: &OnChangeConfig,
+    ) -> Result<Option<Self>, Error> {
+        let Some(command) = args.get_or_config::<String>("on_change", config.command.as_ref())
+        else {
+            return Ok(None);
+        };
+        let debounce = args
+            .get_or_config::<u64>("on_change_debounce", config.debounce.as_ref())
+            .expect("has a default value");
+        let limit = args
+            .get_or_config::<usize>("on_change_limit", config.limit.as_ref())
+            .expect("has a default value");
+        if limit == 0 {
+            return Err(Error::InvalidArgument {
+                message: "--on-change-limit must be greater than 0".to_string(),
+            });
+        }
+        Ok(Some(Self {
+            command,
+            debounce: Duration::from_millis(debounce),
+            limit,
+        }))
+    }
+
+    /// Starts the task that runs the command, which ends once the notifier is dropped.
+    pub fn start<'a>(self) -> OnChangeNotifier<'a> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(self.run(receiver));
+        OnChangeNotifier {
+            previous_output: None,
+            sender,
+        }
+    }
+
+    async fn run(self, mut receiver: mpsc::UnboundedReceiver<Changes>) {
+        let semaphore = Arc::new(Semaphore::new(self.limit));
+        while let Some(mut changes) = self.debounce(&mut receiver).await {
+            // Wait for a command to finish if `limit` are running, merging every change in the
+            // meantime.
+            let Ok(permit) = semaphore.clone().acquire_owned().await else {
+                return;
+            };
+            while let Ok(next_changes) = receiver.try_recv() {
+                changes.merge(next_changes);
+            }
+
+            let command = self.command.clone();
+            tokio::spawn(async move {
+                if let Err(err) = run_command(&command, &changes).await {
+                    eprintln!("getinfo: --on-change: {}", err);
+                }
+                drop(permit);
+            });
+        }
+    }
+
+    /// Waits for changes, and then until nothing changed for `debounce`, or for at most
+    /// [`MAX_DEBOUNCES`] times it, merging every change in the meantime. `None` once the notifier
+    /// is dropped.
+    async fn debounce(&self, receiver: &mut mpsc::UnboundedReceiver<Changes>) -> Option<Changes> {
+        let mut changes = receiver.recv().await?;
+        let deadline = Instant::now() + self.debounce * MAX_DEBOUNCES;
+        loop {
+            tokio::select! {
+                Some(next_changes) = receiver.recv() => changes.merge(next_changes),
+                _ = sleep(self.debounce) => break,
+                _ = sleep_until(deadline) => break,
+            }
+        }
+        Some(changes)
+    }
+}
+
+async fn run_command(command: &str, changes: &Changes) -> io::Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(changes.env())
+        .stdin(Stdio::piped())
+        // stdout is for the output of getinfo itself
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let json = Value::Object(changes.json()).to_string();
+        // The command may exit without reading stdin.
+        let _ = stdin.write_all(json.as_bytes()).await;
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "`{}` failed with {}",
+            command, status
+        )));
+    }
+    Ok(())
+}
+
+impl Changes {
+    /// Adds `other`'s fields, replacing the values of fields that changed again.
+    fn merge(&mut self, other: Changes) {
+        for field in other.fields {
+            match self.fields.iter_mut().find(|f| f.label == field.label) {
+                Some(existing) => *existing = field,
+                None => self.fields.push(field),
+            }
+        }
+    }
+
+    fn env(&self) -> Vec<(String, String)> {
+        let mut env = self
+            .fields
+            .iter()
+            .map(|field| (env_var_name(&field.label), field.text.clone()))
+            .collect::<Vec<_>>();
+        let changed = self
+            .fields
+            .iter()
+            .map(|field| field.label.as_str())
+            .collect::<Vec<_>>();
+        env.push(("GETINFO_CHANGED".to_string(), changed.join(",")));
+        env
+    }
+
+    fn json(&self) -> Map<String, Value> {
+        self.fields
+            .iter()
+            .map(|field| (field.label.clone(), field.json.clone()))
+            .collect()
+    }
+}
+
+/// e.g. `GETINFO_TIME_REMAINING` for `time_remaining`.
+fn env_var_name(label: &str) -> String {
+    let name = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("GETINFO_{}", name)
+}
+
+impl<'a> OnChangeNotifier<'a> {
+    /// Runs the command with the fields of `output` that changed since the last output, if any.
+    pub fn notify(&mut self, output: &Output<'a>) {
+        let changed = match &self.previous_output {
+            Some(previous_output) => output.diff(previous_output),
+            None => output.clone(),
+        };
+        self.previous_output = Some(output.clone());
+        if changed.fields.is_empty() {
+            return;
+        }
+
+        let fields = changed
+            .fields
+            .iter()
+            .map(|field| {
+                let value = output.format.render(field, None);
+                ChangedField {
+                    label: field.label.to_string(),
+                    text: value.to_string(),
+                    json: serde_json::to_value(&value).expect("always valid"),
+                }
+            })
+            .collect();
+        // The task only ends once this is dropped.
+        let _ = self.sender.send(Changes { fields });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(100);
+
+    fn on_change() -> OnChange {
+        OnChange {
+            command: "true".to_string(),
+            debounce: DEBOUNCE,
+            limit: 1,
+        }
+    }
+
+    fn changes(fields: &[(&str, &str)]) -> Changes {
+        Changes {
+            fields: fields
+                .iter()
+                .map(|(label, text)| ChangedField {
+                    label: label.to_string(),
+                    text: text.to_string(),
+                    json: Value::from(*text),
+                })
+                .collect(),
+        }
+    }
+
+    /// Sends every change after its delay from the previous one, and then drops the sender.
+    fn send_after(changes: Vec<(Duration, Changes)>) -> mpsc::UnboundedReceiver<Changes> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for (delay, changes) in changes {
+                sleep(delay).await;
+                let _ = sender.send(changes);
+            }
+        });
+        receiver
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn merges_a_burst_of_changes() {
+        let start = Instant::now();
+        let mut receiver = send_after(vec![
+            (Duration::ZERO, changes(&[("status", "Charging")])),
+            (DEBOUNCE / 2, changes(&[("capacity", "50")])),
+            (DEBOUNCE / 2, changes(&[("capacity", "51")])),
+        ]);
+
+        let changes = on_change().debounce(&mut receiver).await.unwrap();
+        assert_eq!(start.elapsed(), DEBOUNCE * 2);
+        assert_eq!(
+            changes.env(),
+            [
+                ("GETINFO_STATUS".to_string(), "Charging".to_string()),
+                ("GETINFO_CAPACITY".to_string(), "51".to_string()),
+                ("GETINFO_CHANGED".to_string(), "status,capacity".to_string()),
+            ]
+        );
+        assert!(on_change().debounce(&mut receiver).await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn separate_changes_are_not_merged() {
+        let mut receiver = send_after(vec![
+            (Duration::ZERO, changes(&[("capacity", "50")])),
+            (DEBOUNCE * 2, changes(&[("capacity", "49")])),
+        ]);
+
+        let on_change = on_change();
+        let first = on_change.debounce(&mut receiver).await.unwrap();
+        assert_eq!(first.json(), changes(&[("capacity", "50")]).json());
+        let second = on_change.debounce(&mut receiver).await.unwrap();
+        assert_eq!(second.json(), changes(&[("capacity", "49")]).json());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn endless_changes_wait_at_most_max_debounces() {
+        let start = Instant::now();
+        let mut receiver = send_after(
+            (0..20)
+                .map(|i| (DEBOUNCE * 3 / 4, changes(&[("capacity", &i.to_string())])))
+                .collect(),
+        );
+
+        let on_change = on_change();
+        let changes = on_change.debounce(&mut receiver).await.unwrap();
+        // From the first change.
+        assert_eq!(start.elapsed(), DEBOUNCE * 3 / 4 + DEBOUNCE * MAX_DEBOUNCES);
+        assert_eq!(changes.json()["capacity"], "5");
+
+        let changes = on_change.debounce(&mut receiver).await.unwrap();
+        assert_eq!(changes.json()["capacity"], "11");
+    }
+
+    #[test]
+    fn env_var_names() {
+        assert_eq!(env_var_name("status"), "GETINFO_STATUS");
+        assert_eq!(env_var_name("time_remaining"), "GETINFO_TIME_REMAINING");
+        assert_eq!(env_var_name("kde:mediaSrc"), "GETINFO_KDE_MEDIASRC");
+    }
+}
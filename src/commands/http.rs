@@ -0,0 +1,246 @@
+//! Just enough of HTTP/1.1 for serving info to scrapers and browsers: reading a request's method,
+//! path, and query, and writing either a response, after which the connection is closed, or a
+//! stream of Server-Sent Events.
+
+use std::io;
+
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, Take,
+};
+
+/// Requests with a longer head are rejected, as nothing served needs more.
+const MAX_HEAD_LENGTH: usize = 8 * 1024;
+
+pub struct Request {
+    pub method: String,
+    /// Without the query string.
+    pub path: String,
+    /// Without the leading `?`, and not yet percent-decoded.
+    pub query: String,
+    /// The `Host` header, if sent.
+    pub host: Option<String>,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Request {
+    /// Reads the request line and the `Host` header, skipping other headers. The body is ignored,
+    /// as only `GET` is served.
+    pub async fn read(reader: impl AsyncRead + Unpin) -> io::Result<Self> {
+        // Nothing past the longest head allowed is read, so a client can't make it buffer more.
+        let mut reader = BufReader::new(reader.take(MAX_HEAD_LENGTH as u64));
+        let mut request_line = String::new();
+        read_head_line(&mut reader, &mut request_line).await?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target), Some(_version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid_data("invalid request line"));
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut request = Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            host: None,
+        };
+
+        loop {
+            let mut header = String::new();
+            read_head_line(&mut reader, &mut header).await?;
+            if header.trim_end().is_empty() {
+                return Ok(request);
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("host")
+            {
+                request.host = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    /// The decoded `key=value` pairs of the query, in order. A key without `=` has an empty value.
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        self.query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(key), percent_decode(value))
+            })
+            .collect()
+    }
+}
+
+/// Reads a whole line of a request's head into `line`, failing if the connection is closed or the
+/// head gets longer than [`MAX_HEAD_LENGTH`] before the end of it.
+async fn read_head_line(
+    reader: &mut BufReader<Take<impl AsyncRead + Unpin>>,
+    line: &mut String,
+) -> io::Result<()> {
+    reader.read_line(line).await?;
+    if line.ends_with('\n') {
+        Ok(())
+    } else if reader.get_ref().limit() == 0 {
+        Err(invalid_data("request head is too long"))
+    } else {
+        Err(invalid_data(
+            "connection closed before the end of the headers",
+        ))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Decodes `%XX` escapes and `+` as a space, as in `application/x-www-form-urlencoded`. Invalid
+/// escapes are kept as they are.
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = match (byte, tail) {
+            (b'%', [high, low, ..]) => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match (byte, escaped) {
+            (_, Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            (b'+', None) => {
+                bytes.push(b' ');
+                rest = tail;
+            }
+            (byte, None) => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body)
+    }
+
+    pub async fn write(&self, writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason_phrase(self.status),
+            self.content_type,
+            self.body.len()
+        );
+        writer.write_all(head.as_bytes()).await?;
+        writer.write_all(self.body.as_bytes()).await?;
+        writer.flush().await
+    }
+}
+
+/// Starts a `text/event-stream` response, which stays open for [`write_event`].
+pub async fn write_event_stream_head(writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+    writer.write_all(head.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Writes a Server-Sent Event with `data`, named `event` if set, or `message` otherwise.
+pub async fn write_event(
+    writer: &mut (impl AsyncWrite + Unpin),
+    event: Option<&str>,
+    data: &str,
+) -> io::Result<()> {
+    let mut message = String::new();
+    if let Some(event) = event {
+        message.push_str(&format!("event: {}\n", event));
+    }
+    for line in data.lines() {
+        message.push_str(&format!("data: {}\n", line));
+    }
+    message.push('\n');
+    writer.write_all(message.as_bytes()).await?;
+    writer.flush().await
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn request_is_read() {
+        let head = "GET /battery?fields=capacity&separator=%2C+ HTTP/1.1\r\nHost: localhost:8080\r\nAccept: */*\r\n\r\nbody";
+        let request = Request::read(head.as_bytes()).await.unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/battery");
+        assert_eq!(request.host.as_deref(), Some("localhost:8080"));
+        assert_eq!(
+            request.query_pairs(),
+            [
+                ("fields".to_string(), "capacity".to_string()),
+                ("separator".to_string(), ", ".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn endless_line_is_rejected() {
+        let err = Request::read(tokio::io::repeat(b'a')).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "request head is too long");
+    }
+
+    #[tokio::test]
+    async fn long_head_is_rejected() {
+        let head = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X-Padding: a\r\n".repeat(MAX_HEAD_LENGTH / 10)
+        );
+        let err = Request::read(head.as_bytes()).await.err().unwrap();
+        assert_eq!(err.to_string(), "request head is too long");
+
+        let head = format!("GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(100));
+        assert!(Request::read(head.as_bytes()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn unfinished_head_is_rejected() {
+        let err = Request::read(&b"GET / HTTP/1.1\r\nHost: localhost"[..])
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "connection closed before the end of the headers"
+        );
+    }
+}
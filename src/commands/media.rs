@@ -1,40 +1,932 @@
-use clap::{ArgMatches, Command};
-use gi_media_player::foo;
-use crate::commands::{FormatOutputType, SubCommandExt};
+use std::{
+    fmt::Display,
+    pin::{Pin, pin},
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Instant,
+};
+
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use futures_lite::{Stream, StreamExt, future, stream};
+use gi_core::{
+    scheduler::Scheduler,
+    units::{Quantity, Unit},
+};
+use gi_media_player::{
+    MetadataName,
+    control::Control,
+    media::properties::{LoopStatus, MetadataValue},
+    registry::{self, Player, PlayerEvent, PlayerRegistry},
+    selection::{self, Selection, SelectionPolicy},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use tokio::{sync::mpsc, task::JoinHandle};
+use zbus::Connection;
+
+use crate::{
+    commands::{
+        Field, FieldNames, FieldValue, FormatOptions, Output, OutputDiffer, RunMode, SubCommandExt,
+        diff_from_args,
+        hook::{OnChange, OnChangeNotifier},
+        schema::{FieldSchema, ModuleSchema},
+        write_line,
+    },
+    config::{ArgMatchesExt, MediaConfig},
+    error::Error,
+};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MediaField {
+    /// The player's name without the `org.mpris.MediaPlayer2.` prefix, e.g. `spotify`
+    Player,
+    Status,
+    Title,
+    Artist,
+    Album,
+    Length,
+    /// Interpolated between the times it's gotten from the player
+    Position,
+    /// `Position` as a fraction of `Length`
+    Progress,
+    Volume,
+    /// Any other entry of the player's `Metadata`, e.g. `genre` for `xesam:genre`
+    Metadata(MetadataName),
+    /// An entry of the player's `Metadata` that isn't in the spec, by its key, e.g. `kde:mediaSrc`
+    Extra(String),
+}
+
+impl MediaField {
+    const FIELDS: [MediaField; 9] = [
+        MediaField::Player,
+        MediaField::Status,
+        MediaField::Title,
+        MediaField::Artist,
+        MediaField::Album,
+        MediaField::Length,
+        MediaField::Position,
+        MediaField::Progress,
+        MediaField::Volume,
+    ];
+
+    /// Every field, followed by the metadata entries that aren't fields of their own.
+    pub fn all() -> impl Iterator<Item = MediaField> {
+        Self::FIELDS.into_iter().chain(
+            MetadataName::ALL
+                .into_iter()
+                .map(Self::from_metadata_name)
+                .filter(|field| matches!(field, MediaField::Metadata(_))),
+        )
+    }
+
+    /// The field of the metadata entry `name`, which is a field of its own for e.g. the title.
+    pub fn from_metadata_name(name: MetadataName) -> Self {
+        match name {
+            MetadataName::XesamTitle => MediaField::Title,
+            MetadataName::XesamArtist => MediaField::Artist,
+            MetadataName::XesamAlbum => MediaField::Album,
+            MetadataName::MprisLength => MediaField::Length,
+            name => MediaField::Metadata(name),
+        }
+    }
+
+    /// The metadata entry that the field's value is from, if any.
+    pub fn metadata_name(&self) -> Option<MetadataName> {
+        match self {
+            MediaField::Title => Some(MetadataName::XesamTitle),
+            MediaField::Artist => Some(MetadataName::XesamArtist),
+            MediaField::Album => Some(MetadataName::XesamAlbum),
+            MediaField::Length => Some(MetadataName::MprisLength),
+            MediaField::Metadata(name) => Some(*name),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            MediaField::Extra(key) => key,
+            field => field
+                .static_name()
+                .expect("only extra fields have no static name"),
+        }
+    }
+
+    /// The name of the field, unless it's an extra metadata entry named by its key.
+    pub fn static_name(&self) -> Option<&'static str> {
+        Some(match self {
+            MediaField::Player => "player",
+            MediaField::Status => "status",
+            MediaField::Title => "title",
+            MediaField::Artist => "artist",
+            MediaField::Album => "album",
+            MediaField::Length => "length",
+            MediaField::Position => "position",
+            MediaField::Progress => "progress",
+            MediaField::Volume => "volume",
+            MediaField::Metadata(name) => name.field_name(),
+            MediaField::Extra(_) => return None,
+        })
+    }
+
+    /// Other names that are accepted for the field.
+    pub fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            MediaField::Status => &["playback_status"],
+            MediaField::Length => &["duration"],
+            _ => &[],
+        }
+    }
+
+    /// The unit of the field's value as it's read from the player, or `None` if it has none, e.g.
+    /// for strings and [counts](Self::is_integer).
+    pub fn unit(&self) -> Option<Unit> {
+        match self {
+            MediaField::Length | MediaField::Position => Some(Unit::MICRO_SECOND),
+            MediaField::Progress
+            | MediaField::Volume
+            | MediaField::Metadata(MetadataName::XesamAutoRating | MetadataName::XesamUserRating) => {
+                Some(Unit::FRACTION)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the field is a count or number without a unit, e.g. a track number.
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            MediaField::Metadata(
+                MetadataName::XesamAudioBpm
+                    | MetadataName::XesamDiscNumber
+                    | MetadataName::XesamTrackNumber
+                    | MetadataName::XesamUseCount
+            )
+        )
+    }
+
+    /// Whether the field changes while the player is playing, without the player signalling it.
+    pub fn follows_position(&self) -> bool {
+        matches!(self, MediaField::Position | MediaField::Progress)
+    }
+}
+
+impl FromStr for MediaField {
+    type Err = gi_core::Error;
+
+    /// Accepts the field's name or aliases, or the key of any metadata entry, e.g. `xesam:genre`
+    /// or `mpv:foo`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = MetadataName::from_key(s) {
+            return Ok(Self::from_metadata_name(name));
+        }
+        if let Some(field) =
+            Self::all().find(|field| field.as_str() == s || field.aliases().contains(&s))
+        {
+            return Ok(field);
+        }
+        // Metadata keys are namespaced, so that a misspelled field isn't taken for one.
+        match s.split_once(':') {
+            Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() => {
+                Ok(MediaField::Extra(s.to_string()))
+            }
+            _ => Err(gi_core::Error::InvalidInfoName {
+                name: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl Display for MediaField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for MediaField {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for MediaField {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        MediaField::from_str(&s).map_err(de::Error::custom)
+    }
+}
 
 pub fn cli() -> Command {
     Command::new("media")
         .about("Scripts for media player info")
         .common_args()
+        .format_args()
+        .mut_arg("template", |arg| arg.conflicts_with("fields"))
+        .arg(
+            Arg::new("fields")
+                .value_name("FIELD")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(MediaField))
+                .value_delimiter(',')
+                .default_value("player,status,artist,title")
+                .help("Specify which field(s) of the selected player to get (e.g. 'artist,title,length'). Any metadata entry can be a field, by its name (e.g. 'art_url') or key (e.g. 'mpris:artUrl', or 'kde:mediaSrc' for entries that aren't in the MPRIS spec)"),
+        )
+        .arg(
+            Arg::new("player")
+                .long("player")
+                .global(true)
+                .value_name("PATTERN")
+                .help("Only select players whose name matches PATTERN (e.g. 'spotify', or 'firefox*'), where '*' matches any characters and '?' a single character. Names are bus names without 'org.mpris.MediaPlayer2.'"),
+        )
+        .arg(
+            Arg::new("priority")
+                .long("priority")
+                .global(true)
+                .value_name("PATTERNS")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .help("Comma-separated player name patterns, in order of priority (e.g. 'spotify,firefox*'). Players matching none of them come last"),
+        )
+        .arg(
+            Arg::new("ignore")
+                .long("ignore")
+                .global(true)
+                .value_name("PATTERNS")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .help("Comma-separated player name patterns of players to never select (e.g. 'chromium*')"),
+        )
+        .arg(
+            Arg::new("latest")
+                .long("latest")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Select the player that most recently started playing, before --priority. By default, playing players are selected before paused ones after --priority"),
+        )
+        .subcommand(Command::new("play").about("Start or resume playback of the selected player"))
+        .subcommand(Command::new("pause").about("Pause the selected player"))
+        .subcommand(Command::new("play-pause").about("Pause the selected player if it's playing, or play it otherwise"))
+        .subcommand(Command::new("next").about("Skip to the next track"))
+        .subcommand(Command::new("previous").about("Skip to the previous track"))
+        .subcommand(Command::new("stop").about("Stop playback of the selected player"))
+        .subcommand(
+            Command::new("seek")
+                .about("Seek forwards or backwards in the current track")
+                .arg(
+                    Arg::new("offset")
+                        .value_name("OFFSET")
+                        .required(true)
+                        .allow_negative_numbers(true)
+                        .value_parser(parse_offset)
+                        .help("Seconds to seek by, or '[H:]M:S', negative to seek backwards (e.g. '10', '-5', or '+1:30')"),
+                ),
+        )
+        .subcommand(
+            Command::new("set-position")
+                .about("Seek to a position in the current track")
+                .arg(
+                    Arg::new("position")
+                        .value_name("POSITION")
+                        .required(true)
+                        .value_parser(parse_position)
+                        .help("Seconds into the track, or '[H:]M:S' (e.g. '90', or '1:30')"),
+                ),
+        )
+        .subcommand(
+            Command::new("volume")
+                .about("Set or change the volume of the selected player")
+                .arg(
+                    Arg::new("volume")
+                        .value_name("VOLUME")
+                        .required(true)
+                        .allow_negative_numbers(true)
+                        .value_parser(parse_volume)
+                        .help("Volume as a fraction or a percentage, changed by it if it starts with '+' or '-' (e.g. '0.5', '50%', or '+5%')"),
+                ),
+        )
+        .subcommand(
+            Command::new("shuffle")
+                .about("Turn shuffle on or off")
+                .arg(
+                    Arg::new("shuffle")
+                        .value_name("SHUFFLE")
+                        .value_parser(["on", "off", "toggle"])
+                        .default_value("toggle"),
+                ),
+        )
+        .subcommand(
+            Command::new("loop")
+                .about("Set the loop status")
+                .arg(
+                    Arg::new("loop")
+                        .value_name("LOOP")
+                        .required(true)
+                        .value_parser(["none", "track", "playlist"]),
+                ),
+        )
+}
+
+/// The control of a `getinfo media` subcommand.
+fn control_from_args(name: &str, args: &ArgMatches) -> Control {
+    match name {
+        "play" => Control::Play,
+        "pause" => Control::Pause,
+        "play-pause" => Control::PlayPause,
+        "next" => Control::Next,
+        "previous" => Control::Previous,
+        "stop" => Control::Stop,
+        "seek" => Control::Seek(*args.get_one("offset").expect("required")),
+        "set-position" => Control::SetPosition(*args.get_one("position").expect("required")),
+        "volume" => *args.get_one("volume").expect("required"),
+        "shuffle" => match args.get_one::<String>("shuffle").map(String::as_str) {
+            Some("on") => Control::SetShuffle(true),
+            Some("off") => Control::SetShuffle(false),
+            _ => Control::ToggleShuffle,
+        },
+        "loop" => Control::SetLoop(
+            match args.get_one::<String>("loop").expect("required").as_str() {
+                "track" => LoopStatus::Track,
+                "playlist" => LoopStatus::Playlist,
+                _ => LoopStatus::None,
+            },
+        ),
+        _ => unreachable!("every subcommand is handled"),
+    }
+}
+
+/// Parses `[+|-]SECONDS` or `[+|-][H:]M:S` into microseconds.
+fn parse_offset(s: &str) -> Result<i64, String> {
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+    Ok(sign * parse_position(rest)?)
+}
+
+/// Parses `SECONDS` or `[H:]M:S` into microseconds.
+fn parse_position(s: &str) -> Result<i64, String> {
+    let invalid = || format!("Invalid time \"{s}\". Expected seconds, or '[H:]M:S'");
+    let parts = s.split(':').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+    let mut seconds = 0.0;
+    for (i, part) in parts.iter().enumerate() {
+        let value = part.parse::<f64>().map_err(|_| invalid())?;
+        // Every part but the first is less than 60, e.g. '1:30'.
+        if !value.is_finite() || value < 0.0 || (i > 0 && value >= 60.0) {
+            return Err(invalid());
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Ok((seconds * 1_000_000.0).round() as i64)
+}
+
+/// Parses `[+|-]FRACTION` or `[+|-]PERCENT%` into a control that sets or changes the volume.
+fn parse_volume(s: &str) -> Result<Control, String> {
+    let invalid = || format!("Invalid volume \"{s}\". Expected e.g. '0.5', '50%', or '+5%'");
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => (Some(-1.0), rest),
+        None => match s.strip_prefix('+') {
+            Some(rest) => (Some(1.0), rest),
+            None => (None, s),
+        },
+    };
+    let volume = match rest.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().map_err(|_| invalid())? / 100.0,
+        None => rest.parse::<f64>().map_err(|_| invalid())?,
+    };
+    if !volume.is_finite() || volume < 0.0 {
+        return Err(invalid());
+    }
+    Ok(match sign {
+        Some(sign) => Control::AdjustVolume(sign * volume),
+        None => Control::SetVolume(volume),
+    })
+}
+
+/// Options for getting media info, resolved from the command line and the config.
+///
+/// Also sent to the daemon by `--connect` clients, so that the daemon can select a player and
+/// render output the same way as when running locally.
+#[derive(Serialize, Deserialize)]
+pub struct MediaContext {
+    pub policy: SelectionPolicy,
+    pub fields: Vec<MediaField>,
+    #[serde(flatten)]
+    pub format: FormatOptions,
+    pub diff: bool,
+}
+
+impl MediaContext {
+    pub fn from_args(args: &ArgMatches, config: &MediaConfig) -> Result<Self, Error> {
+        let format = FormatOptions::from_args(args, &config.common.format(), FIELD_NAMES)?;
+        let fields = match (&format.template, &config.fields) {
+            (Some(template), _) => {
+                let mut fields = Vec::new();
+                for name in template.field_names() {
+                    let field = MediaField::from_str(name)?;
+                    if !fields.contains(&field) {
+                        fields.push(field);
+                    }
+                }
+                fields
+            }
+            (None, Some(fields)) if !args.is_from_command_line("fields") => fields.clone(),
+            _ => args
+                .get_many::<MediaField>("fields")
+                .expect("has a default value")
+                .cloned()
+                .collect(),
+        };
+        let policy = selection_policy(args, config);
+        let diff = diff_from_args(args, config.common.diff.as_ref(), format.output)?;
+
+        Ok(Self {
+            policy,
+            fields,
+            format,
+            diff,
+        })
+    }
+
+    /// The fields of the selected player, or empty fields if no player is selected, so that e.g.
+    /// a bar clears its text.
+    pub fn get_output(&self, selection: &Selection) -> Output<'_> {
+        let now = Instant::now();
+        let player = selection.selected().zip(selection.selected_position());
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| {
+                let value = match player {
+                    Some((player, position)) => field_value(field, player, position.position(now)),
+                    None => FieldValue::String(String::new()),
+                };
+                Field::new(field.as_str(), value)
+            })
+            .collect();
+        Output::new(fields, &self.format)
+    }
+
+    fn follows_position(&self) -> bool {
+        self.fields.iter().any(MediaField::follows_position)
+    }
+}
+
+/// The error for when `policy` selects no player.
+pub fn not_found(policy: &SelectionPolicy) -> gi_core::Error {
+    match &policy.player {
+        Some(pattern) => gi_core::Error::MediaPlayerNotFound {
+            pattern: pattern.clone(),
+        },
+        None => gi_core::Error::NoMediaPlayersFound,
+    }
+}
+
+/// The selection policy from `--player`, `--priority`, `--ignore` and `--latest`, which are also
+/// accepted by the control subcommands.
+fn selection_policy(args: &ArgMatches, config: &MediaConfig) -> SelectionPolicy {
+    let patterns = |id: &str, config: &Option<Vec<String>>| {
+        if args.is_from_command_line(id) {
+            args.get_many::<String>(id)
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect()
+        } else {
+            config.clone().unwrap_or_default()
+        }
+    };
+    SelectionPolicy {
+        player: args.get_or_config::<String>("player", config.player.as_ref()),
+        priority: patterns("priority", &config.priority),
+        ignore: patterns("ignore", &config.ignore),
+        latest: args
+            .get_or_config::<bool>("latest", config.latest.as_ref())
+            .expect("has a default value"),
+    }
+}
+
+/// The value of `field` of `player`, whose position in microseconds is `position`, in the unit of
+/// [`MediaField::unit`]. Missing values are empty strings.
+pub fn field_value(field: &MediaField, player: &Player, position: i64) -> FieldValue {
+    let properties = &player.properties;
+    let metadata = &properties.metadata;
+    let quantity = |value: Option<f64>| match value {
+        Some(value) => {
+            let unit = field.unit().expect("numeric field has a unit");
+            FieldValue::Quantity(Quantity::new(value, unit))
+        }
+        None => FieldValue::String(String::new()),
+    };
+    let metadata_value = match field {
+        MediaField::Extra(key) => Some(metadata.get_key(key)),
+        field => field.metadata_name().map(|name| metadata.get(name)),
+    };
+    if let Some(value) = metadata_value {
+        return match value {
+            Some(value) => metadata_field_value(value, field.unit()),
+            None => FieldValue::String(String::new()),
+        };
+    }
+    match field {
+        MediaField::Player => FieldValue::String(selection::player_name(player).to_string()),
+        MediaField::Status => FieldValue::String(properties.playback_status.to_string()),
+        MediaField::Position => quantity(Some(position as f64)),
+        MediaField::Progress => quantity(
+            metadata
+                .length()
+                .filter(|length| *length > 0)
+                .map(|length| position as f64 / length as f64),
+        ),
+        MediaField::Volume => quantity(properties.volume),
+        MediaField::Title
+        | MediaField::Artist
+        | MediaField::Album
+        | MediaField::Length
+        | MediaField::Metadata(_)
+        | MediaField::Extra(_) => unreachable!("handled above"),
+    }
 }
 
-struct MediaContext<'a> {
-    battery_name: &'a str,
-    format_output: &'a FormatOutputType,
-    separator: &'a str,
-    output_as_json: bool,
+/// A metadata entry's value as a field value, which is numeric if it has a `unit`. Lists are
+/// joined with commas, e.g. multiple artists.
+pub fn metadata_field_value(value: MetadataValue, unit: Option<Unit>) -> FieldValue {
+    match (value, unit) {
+        (MetadataValue::Integer(value), Some(unit)) => {
+            FieldValue::Quantity(Quantity::new(value as f64, unit))
+        }
+        (MetadataValue::Float(value), Some(unit)) => {
+            FieldValue::Quantity(Quantity::new(value, unit))
+        }
+        (MetadataValue::Integer(value), None) => FieldValue::Number(value as f64),
+        (MetadataValue::Float(value), None) => FieldValue::Number(value),
+        (MetadataValue::String(value), _) => FieldValue::String(value),
+        (MetadataValue::Strings(values), _) => FieldValue::String(values.join(", ")),
+        (MetadataValue::Bool(value), _) => FieldValue::String(value.to_string()),
+    }
+}
+
+/// Follows the players on the session bus with a single [`PlayerRegistry`], and shares its events
+/// with any number of subscribers.
+pub struct MediaWatcher {
+    connection: Connection,
+    registry: PlayerRegistry,
+    subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<PlayerEvent>>>>,
+    task: JoinHandle<()>,
 }
 
-struct MediaSubcommand;
+impl MediaWatcher {
+    pub async fn new() -> Result<Self, Error> {
+        let connection = Connection::session().await?;
+        let (registry, events) = PlayerRegistry::watch(&connection).await?;
+        let subscribers = Arc::new(Mutex::new(Vec::<mpsc::UnboundedSender<_>>::new()));
+        let task_subscribers = subscribers.clone();
+        let task = tokio::spawn(async move {
+            let mut events = pin!(events);
+            while let Some(event) = events.next().await {
+                lock(&task_subscribers).retain(|sender| sender.send(event.clone()).is_ok());
+            }
+        });
+        Ok(Self {
+            connection,
+            registry,
+            subscribers,
+            task,
+        })
+    }
 
-impl MediaSubcommand {
-    fn new() -> Self {
-        todo!()
+    /// The connection to the session bus, and a stream of events starting with the players that
+    /// are already on it.
+    pub fn subscribe(&self) -> (Connection, impl Stream<Item = PlayerEvent> + use<>) {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        // Subscribed before listing the players, so that no change in between is missed. A
+        // change that's in both only updates the player again.
+        lock(&self.subscribers).push(sender);
+        let added = self
+            .registry
+            .players()
+            .into_iter()
+            .map(|player| PlayerEvent::Added(Box::new(player)));
+        let changes = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        (self.connection.clone(), stream::iter(added).chain(changes))
     }
 
-    fn watch(&mut self) {
-        todo!()
+    /// Whether any stream from [`subscribe`](Self::subscribe) is still alive. A dropped one is
+    /// only noticed on the next event.
+    pub fn has_subscribers(&self) -> bool {
+        lock(&self.subscribers)
+            .iter()
+            .any(|sender| !sender.is_closed())
     }
+}
 
-    fn poll(&self, milliseconds: u64) {
-        todo!()
+impl Drop for MediaWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
     }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .expect("never poisoned, as nothing panics while holding the lock")
+}
 
-    fn get_output_string(&self) -> String {
-        todo!()
+/// Output of a [`MediaContext`] for the selected player, whenever it changes while watching, or
+/// on every tick while polling.
+pub struct MediaOutputs<'a> {
+    context: &'a MediaContext,
+    connection: Connection,
+    events: Pin<Box<dyn Stream<Item = PlayerEvent> + Send + 'a>>,
+    selection: Selection,
+    /// Set when polling
+    scheduler: Option<Scheduler>,
+    differ: OutputDiffer<'a>,
+    notifier: Option<OnChangeNotifier<'a>>,
+    is_first: bool,
+}
+
+impl<'a> MediaOutputs<'a> {
+    /// Selects from the players that are already on the bus, which are the first `events`. With
+    /// [`RunMode::Once`], only the [`selection`](Self::selection) is of use.
+    pub async fn new(
+        context: &'a MediaContext,
+        connection: Connection,
+        events: impl Stream<Item = PlayerEvent> + Send + 'a,
+        mode: RunMode,
+    ) -> Result<Self, Error> {
+        let scheduler = match mode {
+            RunMode::Poll(options) => Some(options.scheduler()?),
+            RunMode::Watch | RunMode::Once => None,
+        };
+        let mut events = Box::pin(events);
+        let mut selection = Selection::new(context.policy.clone());
+        // The players that were already on the bus are ready right away.
+        update_ready(&mut selection, &mut events).await;
+        Ok(Self {
+            context,
+            connection,
+            events,
+            selection,
+            scheduler,
+            differ: OutputDiffer::new(mode, context.diff),
+            notifier: None,
+            is_first: true,
+        })
+    }
+
+    /// Also runs `on_change` with the fields of every output that changed.
+    pub fn with_on_change(mut self, on_change: Option<OnChange>) -> Self {
+        self.notifier = on_change.map(OnChange::start);
+        self
+    }
+
+    /// Waits for the next output. Returns `None` once the bus connection is closed.
+    pub async fn next(&mut self) -> Option<String> {
+        loop {
+            if let Some(scheduler) = &mut self.scheduler {
+                scheduler.tick().await;
+                update_ready(&mut self.selection, &mut self.events).await;
+            } else if !self.is_first && !self.wait_for_change().await {
+                return None;
+            }
+            self.is_first = false;
+
+            verify_position(self.context, &self.connection, &mut self.selection).await;
+            let output = self.context.get_output(&self.selection);
+            if let Some(notifier) = &mut self.notifier {
+                notifier.notify(&output);
+            }
+            if let Some(output) = self.differ.next(output) {
+                return Some(output.render());
+            }
+        }
+    }
+
+    /// Waits until the selected player, or what's selected, changes, or until the position
+    /// reaches its next second if it's shown. Returns `false` once there are no more events.
+    async fn wait_for_change(&mut self) -> bool {
+        let next_second = self
+            .selection
+            .selected_position()
+            .filter(|_| self.context.follows_position())
+            .and_then(|position| position.until_next_second(Instant::now()));
+        let tick = async {
+            match next_second {
+                Some(duration) => tokio::time::sleep(duration).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(tick);
+        loop {
+            tokio::select! {
+                event = self.events.next() => {
+                    let Some(event) = event else {
+                        return false;
+                    };
+                    if self.selection.update(event) {
+                        return true;
+                    }
+                }
+                () = &mut tick => return true,
+            }
+        }
+    }
+
+    /// The players as of the last output, or as of the start before the first one.
+    pub fn selection(&self) -> &Selection {
+        &self.selection
+    }
+}
+
+/// Gets the selected player's position again if it's shown and it's been a while since it
+/// was last gotten, to correct the interpolated position's drift.
+async fn verify_position(
+    context: &MediaContext,
+    connection: &Connection,
+    selection: &mut Selection,
+) {
+    let now = Instant::now();
+    if !context.follows_position()
+        || !selection
+            .selected_position()
+            .is_some_and(|position| position.needs_verification(now))
+    {
+        return;
+    }
+    let Some(bus_name) = selection.selected().map(|player| player.bus_name.clone()) else {
+        return;
+    };
+    // The player may have vanished, which its removal will reflect.
+    if let Ok(position) = registry::get_position(connection, &bus_name).await {
+        selection.verify_position(&bus_name, position, now);
     }
 }
 
-pub async fn exec(args: &ArgMatches) {
-    foo();
+/// Updates `selection` with the events that are ready without waiting for a signal.
+pub async fn update_ready(
+    selection: &mut Selection,
+    events: &mut (impl Stream<Item = PlayerEvent> + Unpin),
+) {
+    while let Some(Some(event)) = future::poll_once(events.next()).await {
+        selection.update(event);
+    }
+}
+
+/// Connects to the session bus, and selects from the players that are already on it.
+async fn select(
+    policy: SelectionPolicy,
+) -> Result<
+    (
+        Connection,
+        impl Stream<Item = PlayerEvent> + Send,
+        Selection,
+    ),
+    Error,
+> {
+    let connection = Connection::session().await?;
+    let (_, events) = PlayerRegistry::watch(&connection).await?;
+    let mut events = Box::pin(events);
+    let mut selection = Selection::new(policy);
+    // The players that were already on the bus are the first events, which are ready right away.
+    update_ready(&mut selection, &mut events).await;
+    Ok((connection, events, selection))
+}
+
+/// Sends the control of the `getinfo media` subcommand `name` to the selected player.
+async fn exec_control(name: &str, args: &ArgMatches, config: &MediaConfig) -> Result<(), Error> {
+    let control = control_from_args(name, args);
+    let policy = selection_policy(args, config);
+    let (connection, _, selection) = select(policy.clone()).await?;
+    let player = selection.selected().ok_or_else(|| not_found(&policy))?;
+    Ok(control.check(player)?.send(&connection, player).await?)
+}
+
+pub async fn exec(args: &ArgMatches, config: &MediaConfig) -> Result<(), Error> {
+    if let Some((name, control_args)) = args.subcommand() {
+        return exec_control(name, control_args, config).await;
+    }
+    let context = MediaContext::from_args(args, config)?;
+    let mode = RunMode::from_args(args, &config.common.run_mode());
+    let on_change = OnChange::from_args(args, &config.common.on_change())?;
+    if matches!(mode, RunMode::Once) && args.is_from_command_line("on_change") {
+        return Err(gi_core::Error::InvalidArgument {
+            message: "--on-change requires --watch or --poll".to_string(),
+        }
+        .into());
+    }
+
+    if let RunMode::Once = mode {
+        let (_, _, selection) = select(context.policy.clone()).await?;
+        if selection.selected().is_none() {
+            return Err(not_found(&context.policy).into());
+        }
+        println!("{}", context.get_output(&selection).render());
+        return Ok(());
+    }
+
+    let connection = Connection::session().await?;
+    let (_, events) = PlayerRegistry::watch(&connection).await?;
+    let mut outputs = MediaOutputs::new(&context, connection, events, mode)
+        .await?
+        .with_on_change(on_change);
+    let mut stdout = tokio::io::stdout();
+    while let Some(output) = outputs.next().await {
+        // While polling, ticks are missed instead of queued up while stdout is blocked
+        write_line(&mut stdout, &output).await?;
+    }
+    Ok(())
+}
+
+/// The fields of `getinfo media`.
+pub fn schema() -> ModuleSchema {
+    let fields = MediaField::all()
+        .filter_map(|field| {
+            let name = field.static_name()?;
+            Some(if field.is_integer() {
+                FieldSchema::integer(name, field.aliases(), true)
+            } else {
+                FieldSchema::new(name, field.aliases(), field.unit(), true)
+            })
+        })
+        .collect();
+    ModuleSchema::new(&cli(), fields).with_extra_fields("^[^:]+:.+$")
+}
+
+/// Resolves a field name in `--precision` or `--template`, which may be an alias.
+pub fn resolve_field_name(name: &str) -> Result<String, gi_core::Error> {
+    Ok(MediaField::from_str(name)?.as_str().to_string())
+}
+
+pub const FIELD_NAMES: FieldNames = FieldNames {
+    resolve: resolve_field_name,
+    is_numeric: |name| {
+        MediaField::from_str(name).is_ok_and(|field| field.unit().is_some() || field.is_integer())
+    },
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(parse_position("0"), Ok(0));
+        assert_eq!(parse_position("90"), Ok(90_000_000));
+        assert_eq!(parse_position("1.5"), Ok(1_500_000));
+        assert_eq!(parse_position("1:30"), Ok(90_000_000));
+        assert_eq!(parse_position("1:02:03"), Ok(3_723_000_000));
+        assert_eq!(parse_position("90:00"), Ok(5_400_000_000));
+    }
+
+    #[test]
+    fn rejects_invalid_positions() {
+        for position in [
+            "", "-1", "1:60", "1::2", "1:2:3:4", "a", "inf", "NaN", "1:-2",
+        ] {
+            assert_eq!(
+                parse_position(position),
+                Err(format!(
+                    "Invalid time \"{position}\". Expected seconds, or '[H:]M:S'"
+                )),
+            );
+        }
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_offset("10"), Ok(10_000_000));
+        assert_eq!(parse_offset("+10"), Ok(10_000_000));
+        assert_eq!(parse_offset("-1:30"), Ok(-90_000_000));
+        assert!(parse_offset("--10").is_err());
+        assert!(parse_offset("+-10").is_err());
+        assert!(parse_offset("-").is_err());
+    }
+
+    #[test]
+    fn parses_volumes() {
+        assert_eq!(parse_volume("0.5"), Ok(Control::SetVolume(0.5)));
+        assert_eq!(parse_volume("50%"), Ok(Control::SetVolume(0.5)));
+        assert_eq!(parse_volume("150%"), Ok(Control::SetVolume(1.5)));
+        assert_eq!(parse_volume("+5%"), Ok(Control::AdjustVolume(0.05)));
+        assert_eq!(parse_volume("-0.1"), Ok(Control::AdjustVolume(-0.1)));
+    }
+
+    #[test]
+    fn rejects_invalid_volumes() {
+        for volume in ["", "%", "loud", "5%%", "--5%", "inf", "-inf%", "+-1"] {
+            assert_eq!(
+                parse_volume(volume),
+                Err(format!(
+                    "Invalid volume \"{volume}\". Expected e.g. '0.5', '50%', or '+5%'"
+                )),
+            );
+        }
+    }
 }
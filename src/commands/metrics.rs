@@ -0,0 +1,477 @@
+//! `getinfo serve-metrics`, which exposes the numeric fields of every module in the OpenMetrics
+//! text format, so that they can be scraped by Prometheus.
+//!
+//! Every scrape reads new snapshots of every module. Metrics are named
+//! `getinfo_<module>_<field>_<unit>` in the base units that Prometheus recommends (e.g.
+//! `getinfo_battery_charge_now_ampere_hours`), and text fields are exposed as info metrics (e.g.
+//! `getinfo_battery_status_info{battery="BAT0",status="Discharging"} 1`). Media metrics have a
+//! sample for every player, labelled with its name (e.g.
+//! `getinfo_media_position_seconds{player="spotify"} 83.2`). `getinfo_module_up` is 0 for modules
+//! that failed to be read.
+
+use std::{fmt::Write, net::SocketAddr, sync::Arc};
+
+use clap::{Arg, ArgMatches, Command, value_parser};
+use gi_battery::{Batteries, BatteryInfoName};
+use gi_core::units::{BaseUnit, Dimension, Prefix, Quantity, Unit};
+use gi_media_player::{registry::PlayerRegistry, selection};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{
+    commands::{
+        FieldValue, battery,
+        http::{Request, Response},
+        media::{self, MediaField},
+    },
+    error::Error,
+};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Copy)]
+enum Module {
+    Battery,
+    Media,
+}
+
+const MODULES: [Module; 2] = [Module::Battery, Module::Media];
+
+pub fn cli() -> Command {
+    Command::new("serve-metrics")
+        .about("Serves numeric info in the OpenMetrics text format, for Prometheus to scrape")
+        .arg(
+            Arg::new("listen")
+                .short('l')
+                .long("listen")
+                .required(true)
+                .value_name("ADDRESS")
+                .value_parser(value_parser!(SocketAddr))
+                .help(
+                    "Address to listen on (e.g. '127.0.0.1:9184'). Metrics are served at /metrics",
+                ),
+        )
+}
+
+pub async fn exec(args: &ArgMatches) -> Result<(), Error> {
+    let address = *args.get_one::<SocketAddr>("listen").expect("is required");
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|err| Error::Listen {
+            address: address.to_string(),
+            source: Arc::new(err),
+        })?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            // Errors here are from the scraper disconnecting, which only ends its own request.
+            let _ = handle_scrape(stream).await;
+        });
+    }
+}
+
+async fn handle_scrape(mut stream: TcpStream) -> std::io::Result<()> {
+    let response = match Request::read(&mut stream).await {
+        Ok(request) => match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") => Response::new(200, CONTENT_TYPE, scrape().await),
+            (_, "/metrics") => Response::text(405, "Only GET is allowed\n"),
+            _ => Response::text(404, "Metrics are served at /metrics\n"),
+        },
+        Err(err) => Response::text(400, format!("{}\n", err)),
+    };
+    response.write(&mut stream).await
+}
+
+/// The metrics of every module, followed by whether each module could be read.
+async fn scrape() -> String {
+    let mut metrics = Metrics::default();
+    let mut up = MetricFamily::new(
+        "getinfo_module_up".to_string(),
+        MetricType::Gauge,
+        None,
+        "Whether the module's info could be read".to_string(),
+    );
+
+    for module in MODULES {
+        let is_up = match module.collect().await {
+            Ok(mut module_metrics) => {
+                metrics.families.append(&mut module_metrics.families);
+                true
+            }
+            Err(err) => {
+                eprintln!("getinfo: {}: {}", module.as_str(), err);
+                false
+            }
+        };
+        up.samples.push(Sample {
+            labels: vec![("module", module.as_str().to_string())],
+            value: if is_up { 1.0 } else { 0.0 },
+        });
+    }
+
+    metrics.families.push(up);
+    metrics.to_string()
+}
+
+impl Module {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Module::Battery => "battery",
+            Module::Media => "media",
+        }
+    }
+
+    /// The metrics of the module, or an error if its info couldn't be read.
+    async fn collect(self) -> Result<Metrics, Error> {
+        let mut metrics = Metrics::default();
+        match self {
+            // Reading sysfs blocks, which would hold up other scrapes until it's done.
+            Module::Battery => {
+                metrics = tokio::task::spawn_blocking(move || {
+                    collect_battery(&mut metrics).map(|()| metrics)
+                })
+                .await
+                .expect("reading batteries doesn't panic")?;
+            }
+            Module::Media => collect_media(&mut metrics).await?,
+        }
+        Ok(metrics)
+    }
+}
+
+fn collect_battery(metrics: &mut Metrics) -> Result<(), Error> {
+    let batteries = Batteries::init()?;
+    for battery in batteries.iter() {
+        let snapshot = battery.snapshot()?;
+        for info_name in BatteryInfoName::ALL {
+            let labels = vec![("battery", battery.name.clone())];
+            let value = battery::field_value(info_name, &snapshot);
+            metrics.add("battery", info_name.as_str(), value, labels);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the fields of every player on the session bus, except for the metadata entries that
+/// aren't in the spec, as each would be a metric of its own.
+async fn collect_media(metrics: &mut Metrics) -> Result<(), Error> {
+    let connection = zbus::Connection::session().await?;
+    let (registry, _) = PlayerRegistry::watch(&connection).await?;
+    let mut players = registry.players();
+    players.sort_by(|a, b| a.bus_name.cmp(&b.bus_name));
+
+    for player in &players {
+        let position = player.properties.position;
+        for field in MediaField::all() {
+            // The player's name is the label of its samples.
+            if field == MediaField::Player {
+                continue;
+            }
+            let name = field
+                .static_name()
+                .expect("only extra fields have no static name");
+            let value = media::field_value(&field, player, position);
+            // Missing values have no sample rather than an empty one.
+            if matches!(&value, FieldValue::String(value) if value.is_empty()) {
+                continue;
+            }
+            let labels = vec![("player", selection::player_name(player).to_string())];
+            metrics.add("media", name, value, labels);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Metrics {
+    families: Vec<MetricFamily>,
+}
+
+struct MetricFamily {
+    name: String,
+    metric_type: MetricType,
+    unit: Option<&'static str>,
+    help: String,
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MetricType {
+    Gauge,
+    /// Labels of text values, e.g. a battery's status, with a value of 1
+    Info,
+}
+
+struct Sample {
+    labels: Vec<(&'static str, String)>,
+    value: f64,
+}
+
+impl Metrics {
+    /// Adds `value` of the field `field` of `module` as a gauge, or as an info metric if it's text.
+    fn add(
+        &mut self,
+        module: &str,
+        field: &'static str,
+        value: FieldValue,
+        labels: Vec<(&'static str, String)>,
+    ) {
+        match value {
+            FieldValue::Quantity(quantity) => self.add_quantity(module, field, &quantity, labels),
+            FieldValue::Number(value) => self.add_number(module, field, value, labels),
+            FieldValue::String(value) => self.add_info(module, field, value, labels),
+        }
+    }
+
+    fn add_quantity(
+        &mut self,
+        module: &str,
+        field: &str,
+        quantity: &Quantity,
+        labels: Vec<(&'static str, String)>,
+    ) {
+        let (unit, unit_name) = base_unit(quantity.dimension());
+        let family = self.family(
+            format!("getinfo_{}_{}_{}", module, field, unit_name),
+            MetricType::Gauge,
+            Some(unit_name),
+            format!("`{}` of `getinfo {}`, in {}", field, module, unit_name),
+        );
+        family.samples.push(Sample {
+            labels,
+            value: quantity.value_in(unit),
+        });
+    }
+
+    fn add_number(
+        &mut self,
+        module: &str,
+        field: &str,
+        value: f64,
+        labels: Vec<(&'static str, String)>,
+    ) {
+        let family = self.family(
+            format!("getinfo_{}_{}", module, field),
+            MetricType::Gauge,
+            None,
+            format!("`{}` of `getinfo {}`", field, module),
+        );
+        family.samples.push(Sample { labels, value });
+    }
+
+    fn add_info(
+        &mut self,
+        module: &str,
+        field: &'static str,
+        value: String,
+        mut labels: Vec<(&'static str, String)>,
+    ) {
+        let family = self.family(
+            format!("getinfo_{}_{}", module, field),
+            MetricType::Info,
+            None,
+            format!("`{}` of `getinfo {}`", field, module),
+        );
+        labels.push((field, value));
+        family.samples.push(Sample { labels, value: 1.0 });
+    }
+
+    /// The family named `name`, which is added if it doesn't exist yet, as every sample of a
+    /// family has to be exposed together.
+    fn family(
+        &mut self,
+        name: String,
+        metric_type: MetricType,
+        unit: Option<&'static str>,
+        help: String,
+    ) -> &mut MetricFamily {
+        match self.families.iter().position(|family| family.name == name) {
+            Some(i) => &mut self.families[i],
+            None => {
+                self.families
+                    .push(MetricFamily::new(name, metric_type, unit, help));
+                self.families.last_mut().expect("was just pushed")
+            }
+        }
+    }
+}
+
+impl MetricFamily {
+    fn new(
+        name: String,
+        metric_type: MetricType,
+        unit: Option<&'static str>,
+        help: String,
+    ) -> Self {
+        Self {
+            name,
+            metric_type,
+            unit,
+            help,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for family in &self.families {
+            let metric_type = match family.metric_type {
+                MetricType::Gauge => "gauge",
+                MetricType::Info => "info",
+            };
+            writeln!(f, "# TYPE {} {}", family.name, metric_type)?;
+            if let Some(unit) = family.unit {
+                writeln!(f, "# UNIT {} {}", family.name, unit)?;
+            }
+            writeln!(f, "# HELP {} {}", family.name, escape(&family.help))?;
+            for sample in &family.samples {
+                f.write_str(&family.name)?;
+                if family.metric_type == MetricType::Info {
+                    f.write_str("_info")?;
+                }
+                if !sample.labels.is_empty() {
+                    let mut labels = String::new();
+                    for (i, (name, value)) in sample.labels.iter().enumerate() {
+                        if i != 0 {
+                            labels.push(',');
+                        }
+                        write!(labels, "{}=\"{}\"", name, escape(value))?;
+                    }
+                    write!(f, "{{{}}}", labels)?;
+                }
+                writeln!(f, " {}", format_value(sample.value))?;
+            }
+        }
+        writeln!(f, "# EOF")
+    }
+}
+
+/// The unit that Prometheus' naming conventions use for `dimension`, and its name as a metric
+/// name suffix.
+fn base_unit(dimension: Dimension) -> (Unit, &'static str) {
+    let (base, name) = match dimension {
+        Dimension::Charge => (BaseUnit::AmpHour, "ampere_hours"),
+        Dimension::Current => (BaseUnit::Amp, "amperes"),
+        Dimension::Energy => (BaseUnit::WattHour, "watt_hours"),
+        Dimension::Power => (BaseUnit::Watt, "watts"),
+        Dimension::Voltage => (BaseUnit::Volt, "volts"),
+        Dimension::Temperature => (BaseUnit::Celsius, "celsius"),
+        Dimension::Information => (BaseUnit::Byte, "bytes"),
+        Dimension::Ratio => (BaseUnit::Fraction, "ratio"),
+        Dimension::Duration => (BaseUnit::Second, "seconds"),
+    };
+    (Unit::new(Prefix::None, base), name)
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes label values and help text.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(value: &str) -> Vec<(&'static str, String)> {
+        vec![("battery", value.to_string())]
+    }
+
+    #[test]
+    fn quantities_are_in_base_units() {
+        let mut metrics = Metrics::default();
+        let charge = Quantity::new(3_000_000, Unit::MICRO_AMP_HOUR);
+        metrics.add(
+            "battery",
+            "charge_now",
+            FieldValue::Quantity(charge),
+            labels("BAT0"),
+        );
+        let charge = Quantity::new(1_500_000, Unit::MICRO_AMP_HOUR);
+        metrics.add(
+            "battery",
+            "charge_now",
+            FieldValue::Quantity(charge),
+            labels("BAT1"),
+        );
+
+        assert_eq!(
+            metrics.to_string(),
+            "# TYPE getinfo_battery_charge_now_ampere_hours gauge\n\
+             # UNIT getinfo_battery_charge_now_ampere_hours ampere_hours\n\
+             # HELP getinfo_battery_charge_now_ampere_hours `charge_now` of `getinfo battery`, in ampere_hours\n\
+             getinfo_battery_charge_now_ampere_hours{battery=\"BAT0\"} 3\n\
+             getinfo_battery_charge_now_ampere_hours{battery=\"BAT1\"} 1.5\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn numbers_have_no_unit() {
+        let mut metrics = Metrics::default();
+        let labels = vec![("player", "spotify".to_string())];
+        metrics.add("media", "track_number", FieldValue::Number(7.0), labels);
+
+        assert_eq!(
+            metrics.to_string(),
+            "# TYPE getinfo_media_track_number gauge\n\
+             # HELP getinfo_media_track_number `track_number` of `getinfo media`\n\
+             getinfo_media_track_number{player=\"spotify\"} 7\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn text_is_an_info_metric() {
+        let mut metrics = Metrics::default();
+        let status = FieldValue::String("Discharging".to_string());
+        metrics.add("battery", "status", status, labels("BAT0"));
+
+        assert_eq!(
+            metrics.to_string(),
+            "# TYPE getinfo_battery_status info\n\
+             # HELP getinfo_battery_status `status` of `getinfo battery`\n\
+             getinfo_battery_status_info{battery=\"BAT0\",status=\"Discharging\"} 1\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut metrics = Metrics::default();
+        let title = FieldValue::String("\"Quoted\" \\ and\nsplit".to_string());
+        let labels = vec![("player", "mpv".to_string())];
+        metrics.add("media", "title", title, labels);
+
+        let text = metrics.to_string();
+        assert!(
+            text.contains(r#"{player="mpv",title="\"Quoted\" \\ and\nsplit"} 1"#),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn empty_metrics_end_with_eof() {
+        assert_eq!(Metrics::default().to_string(), "# EOF\n");
+    }
+
+    #[test]
+    fn special_values() {
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(0.25), "0.25");
+    }
+}
@@ -1,18 +1,59 @@
-use std::{fmt::Display, str::FromStr};
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
 
-use clap::{Arg, ArgAction, Command, value_parser};
-use gi_core::Timestamp;
-use serde::{ser::SerializeMap, Serialize};
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use gi_core::{
+    AsTimestamp, Error, Seconds, Timestamp, datetime,
+    duration::DurationFormat,
+    scheduler::{Alignment, MissedTick, Scheduler},
+    units::{Quantity, Unit, Units},
+};
+use serde::{Deserialize, Serialize, ser::SerializeMap};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    commands::{
+        format::{Precision, Rounding, Template, TemplateContext},
+        threshold::{FieldThreshold, State, Thresholds, field_state},
+    },
+    config::{ArgMatchesExt, FormatConfig, RunModeConfig},
+};
 
 pub mod battery;
+pub mod daemon;
+pub mod encode;
+pub mod format;
+pub mod hook;
+pub mod http;
 pub mod media;
+pub mod metrics;
+pub mod schema;
+pub mod threshold;
 
 pub trait SubCommandExt {
     fn arg_watch(self) -> Self;
     fn arg_poll(self) -> Self;
+    fn arg_align(self) -> Self;
+    fn arg_missed_tick(self) -> Self;
     fn arg_separator(self) -> Self;
     fn arg_json(self) -> Self;
+    fn arg_output(self) -> Self;
+    fn arg_diff(self) -> Self;
+    fn arg_timestamp(self) -> Self;
+    fn arg_on_change(self) -> Self;
+    fn arg_format_output(self) -> Self;
+    fn arg_units(self) -> Self;
+    fn arg_precision(self) -> Self;
+    fn arg_rounding(self) -> Self;
+    fn arg_template(self) -> Self;
+    fn arg_duration_format(self) -> Self;
+    fn arg_threshold(self) -> Self;
     fn common_args(self) -> Self;
+    /// Args for rendering numbers, for subcommands that output numeric fields.
+    fn format_args(self) -> Self;
 }
 
 impl SubCommandExt for Command {
@@ -33,12 +74,34 @@ impl SubCommandExt for Command {
                 .short('p')
                 .long("poll")
                 .conflicts_with("watch")
-                .value_parser(value_parser!(u64))
+                .value_parser(value_parser!(u64).range(1..))
                 .value_name("MILLISECONDS")
                 .help("Outputs after every interval"),
         )
     }
 
+    fn arg_align(self) -> Self {
+        self.arg(
+            Arg::new("align")
+                .long("align")
+                .value_parser(value_parser!(Alignment))
+                .value_name("BOUNDARY")
+                .default_value("none")
+                .help("When polling, align outputs to the wall clock's 'second', 'minute', or 'hour' boundaries. The interval should evenly divide the boundary"),
+        )
+    }
+
+    fn arg_missed_tick(self) -> Self {
+        self.arg(
+            Arg::new("missed_tick")
+                .long("missed-tick")
+                .value_parser(value_parser!(MissedTick))
+                .value_name("BEHAVIOR")
+                .default_value("skip")
+                .help("When polling, what to do with outputs missed while stdout was blocked: 'skip' them, 'delay' the following ones, or 'burst' to catch up"),
+        )
+    }
+
     fn arg_separator(self) -> Self {
         self.arg(
             Arg::new("separator")
@@ -62,19 +125,228 @@ impl SubCommandExt for Command {
         )
     }
 
+    fn arg_output(self) -> Self {
+        self.arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .conflicts_with("json")
+                .value_parser(value_parser!(OutputFormat))
+                .value_name("FORMAT")
+                .default_value("text")
+                .help("Output as 'text', 'json', 'ndjson', 'csv' or 'tsv' (with a header row), 'yaml' (a document per output), 'waybar' (for custom modules with \"return-type\": \"json\"), or 'i3bar' (a block of the i3bar protocol)"),
+        )
+    }
+
+    fn arg_diff(self) -> Self {
+        self.arg(
+            Arg::new("diff")
+                .short('d')
+                .long("diff")
+                .action(ArgAction::SetTrue)
+                .help("When watching or polling, only output the fields that changed, labelled with their names. With --json, outputs JSON Merge Patches"),
+        )
+    }
+
+    fn arg_timestamp(self) -> Self {
+        self.arg(
+            Arg::new("timestamp")
+                .long("timestamp")
+                .action(ArgAction::SetTrue)
+                .help("Adds a 'timestamp' field to every output, with the time it was output in UTC as RFC 3339 (e.g. '2025-06-01T12:34:56Z')"),
+        )
+    }
+
+    fn arg_on_change(self) -> Self {
+        self.arg(
+            Arg::new("on_change")
+                .long("on-change")
+                .value_name("COMMAND")
+                .help("When watching or polling, runs COMMAND with `sh -c` whenever fields change, with the changed fields as GETINFO_<FIELD> environment variables, their names in GETINFO_CHANGED, and a JSON object of them on stdin"),
+        )
+        .arg(
+            Arg::new("on_change_debounce")
+                .long("on-change-debounce")
+                .value_parser(value_parser!(u64))
+                .value_name("MILLISECONDS")
+                .default_value("250")
+                .help(format!("How long fields have to stay unchanged before --on-change runs, so that a burst of changes runs it once. Changes that don't stop still run it every {} times this", hook::MAX_DEBOUNCES)),
+        )
+        .arg(
+            Arg::new("on_change_limit")
+                .long("on-change-limit")
+                .value_parser(value_parser!(usize))
+                .value_name("COUNT")
+                .default_value("1")
+                .help("How many --on-change commands can run at the same time. Changes while at the limit are merged and run once one finishes"),
+        )
+    }
+
+    fn arg_format_output(self) -> Self {
+        self.arg(
+            Arg::new("format_output")
+                .short('f')
+                .long("format-output")
+                .value_parser(value_parser!(FormatOutputType))
+                .value_name("FORMAT_TYPE")
+                .default_value("no_symbols")
+                .help("Specify how the output fields should be formatted"),
+        )
+    }
+
+    fn arg_units(self) -> Self {
+        self.arg(
+            Arg::new("units")
+                .short('u')
+                .long("units")
+                .value_parser(value_parser!(Units))
+                .value_name("UNITS")
+                .default_value("")
+                .hide_default_value(true)
+                .help("Comma-separated units to output values in (e.g. 'Ah,W'), and/or 'si' or 'iec' to pick prefixes automatically. Defaults to mAh, mA, Wh, W, V, °C, B, and %. Not used by '--format-output raw', which outputs values as they were read"),
+        )
+    }
+
+    fn arg_precision(self) -> Self {
+        self.arg(
+            Arg::new("precision")
+                .long("precision")
+                .value_parser(value_parser!(Precision))
+                .value_name("PRECISION")
+                .help("Number of decimal places for every number, and/or for specific fields (e.g. '1,capacity=0')"),
+        )
+    }
+
+    fn arg_rounding(self) -> Self {
+        self.arg(
+            Arg::new("rounding")
+                .long("rounding")
+                .value_parser(value_parser!(Rounding))
+                .value_name("ROUNDING")
+                .default_value("nearest")
+                .help("How numbers are rounded to their precision: 'nearest', 'down', 'up', or 'truncate'"),
+        )
+    }
+
+    fn arg_template(self) -> Self {
+        self.arg(
+            Arg::new("template")
+                .short('t')
+                .long("template")
+                .conflicts_with_all(["json", "diff"])
+                .value_parser(value_parser!(Template))
+                .value_name("TEMPLATE")
+                .help("Format string for the output, with fields written as '{name}' or '{name:[[fill]align][width][.precision]}' (e.g. '{capacity:>3.0}% {status}')"),
+        )
+    }
+
+    fn arg_duration_format(self) -> Self {
+        self.arg(
+            Arg::new("duration_format")
+                .long("duration-format")
+                .value_parser(value_parser!(DurationFormat))
+                .value_name("FORMAT")
+                .default_value("clock")
+                .help("How durations are formatted: 'clock' (01:23:45), 'compact' (1h 23m), 'long' (1 hour 23 minutes), 'iso8601' (PT1H23M45S), or a pattern of %d, %H, %M, and %S (e.g. '%H:%M'), with lowercase for no zero-padding"),
+        )
+    }
+
+    fn arg_threshold(self) -> Self {
+        self.arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(FieldThreshold))
+                .value_name("FIELD=WARNING:CRITICAL[:DIRECTION]")
+                .help("Warning and critical thresholds of a numeric field, in the field's default unit (e.g. 'capacity=30:15'). The direction is 'below' or 'above', and defaults to 'above' if CRITICAL is greater than WARNING, otherwise 'below'. Can be passed multiple times"),
+        )
+    }
+
     fn common_args(self) -> Self {
-        self.arg_watch().arg_poll().arg_separator().arg_json()
+        self.arg_watch()
+            .arg_poll()
+            .arg_align()
+            .arg_missed_tick()
+            .arg_separator()
+            .arg_json()
+            .arg_output()
+            .arg_diff()
+            .arg_timestamp()
+            .arg_on_change()
+    }
+
+    fn format_args(self) -> Self {
+        self.arg_format_output()
+            .arg_units()
+            .arg_precision()
+            .arg_rounding()
+            .arg_template()
+            .arg_duration_format()
+            .arg_threshold()
+    }
+}
+
+/// How often a subcommand outputs, resolved from `--watch`/`--poll` and the config.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunMode {
+    Once,
+    Watch,
+    Poll(PollOptions),
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct PollOptions {
+    pub milliseconds: u64,
+    pub align: Alignment,
+    pub missed_tick: MissedTick,
+}
+
+impl RunMode {
+    /// `--watch` and `--poll` on the command line take precedence over both of them in the config.
+    pub fn from_args(args: &ArgMatches, config: &RunModeConfig) -> Self {
+        let (watch, poll) =
+            if args.is_from_command_line("watch") || args.is_from_command_line("poll") {
+                (args.get_flag("watch"), args.get_one::<u64>("poll").copied())
+            } else {
+                (config.watch.unwrap_or(false), config.poll)
+            };
+
+        match (watch, poll) {
+            (true, _) => Self::Watch,
+            (false, Some(milliseconds)) => Self::Poll(PollOptions {
+                milliseconds,
+                align: args
+                    .get_or_config::<Alignment>("align", config.align.as_ref())
+                    .expect("has a default value"),
+                missed_tick: args
+                    .get_or_config::<MissedTick>("missed_tick", config.missed_tick.as_ref())
+                    .expect("has a default value"),
+            }),
+            (false, None) => Self::Once,
+        }
+    }
+}
+
+impl PollOptions {
+    pub fn scheduler(&self) -> Result<Scheduler, Error> {
+        Scheduler::new(
+            Duration::from_millis(self.milliseconds),
+            self.align,
+            self.missed_tick,
+        )
     }
 }
 
+#[derive(Clone, PartialEq)]
 pub enum FieldValue {
-    I32(i32),
-    U64(u64),
-    F32(f32),
     String(String),
-    Timestamp(Timestamp),
+    Quantity(Quantity),
+    /// A number without a unit, e.g. a track number
+    Number(f64),
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Field<'a> {
     pub label: &'a str,
     pub value: FieldValue,
@@ -86,13 +358,200 @@ impl<'a> Field<'a> {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FormatOutputType {
+    /// Values in the units they were read in, e.g. µAh from sysfs
     Raw,
+    /// Values in the selected units, without the units' symbols
     NoSymbols,
+    /// Values in the selected units, with the units' symbols
     Formatted,
 }
 
+/// How the values of an [`Output`] are rendered.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormatOptions {
+    pub format_output: FormatOutputType,
+    pub units: Units,
+    pub precision: Precision,
+    pub rounding: Rounding,
+    /// Used for durations that don't have a unit selected with `--units`.
+    pub duration_format: DurationFormat,
+    /// Replaces the separator when set, except in labelled outputs.
+    pub template: Option<Template>,
+    pub separator: String,
+    pub output: OutputFormat,
+    pub thresholds: Thresholds,
+    /// Add the time of rendering as the first field.
+    pub timestamp: bool,
+}
+
+/// A module's field names, for resolving the names in options such as `--precision`.
+#[derive(Clone, Copy)]
+pub struct FieldNames {
+    /// The canonical name of the field named `name`, which may be an alias.
+    pub resolve: fn(&str) -> Result<String, Error>,
+    /// Whether the field with the canonical name `name` is numeric, and so can have thresholds.
+    pub is_numeric: fn(&str) -> bool,
+}
+
+impl FormatOptions {
+    /// Resolves the options of [`SubCommandExt::common_args`] and [`SubCommandExt::format_args`]
+    /// that affect rendering, with `field_names` resolving the field names in them.
+    pub fn from_args(
+        args: &ArgMatches,
+        config: &FormatConfig,
+        field_names: FieldNames,
+    ) -> Result<Self, Error> {
+        let format_output = args
+            .get_or_config::<FormatOutputType>("format_output", config.format_output)
+            .expect("has a default value");
+        let units = args
+            .get_or_config::<Units>("units", config.units)
+            .expect("has a default value");
+        let precision = args
+            .get_or_config::<Precision>("precision", config.precision)
+            .unwrap_or_default()
+            .resolve_names(field_names.resolve)?;
+        let duration_format = args
+            .get_or_config::<DurationFormat>("duration_format", config.duration_format)
+            .expect("has a default value");
+        let rounding = args
+            .get_or_config::<Rounding>("rounding", config.rounding)
+            .expect("has a default value");
+        // A template from the config shouldn't be used if `--json` or `--diff` was passed, and
+        // vice versa.
+        let template = if args.is_from_command_line("json") || args.is_from_command_line("diff") {
+            None
+        } else {
+            args.get_or_config::<Template>("template", config.template)
+        }
+        .map(|template| template.resolve_names(field_names.resolve))
+        .transpose()?;
+        let output = match output_format(args, config.output, config.json) {
+            OutputFormat::Json | OutputFormat::Ndjson
+                if args.is_from_command_line("template") && args.is_from_command_line("output") =>
+            {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "--template cannot be used with --output {}",
+                        args.get_one::<OutputFormat>("output").expect("has a value")
+                    ),
+                });
+            }
+            // `--template` on the command line is for text output, over JSON output in the config
+            OutputFormat::Json | OutputFormat::Ndjson if args.is_from_command_line("template") => {
+                OutputFormat::Text
+            }
+            output => output,
+        };
+        let mut thresholds = Thresholds::new();
+        for (field, threshold) in config.thresholds {
+            thresholds.insert((field_names.resolve)(field)?, threshold.clone());
+        }
+        for FieldThreshold { field, threshold } in args
+            .get_many::<FieldThreshold>("threshold")
+            .into_iter()
+            .flatten()
+            .cloned()
+        {
+            let name = (field_names.resolve)(&field)?;
+            if !(field_names.is_numeric)(&name) {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "Thresholds can only be set on numeric fields, not {}",
+                        field
+                    ),
+                });
+            }
+            thresholds.insert(name, threshold);
+        }
+        // A separator from the config shouldn't be used if `--json` was passed
+        let separator = if args.is_from_command_line("json") {
+            args.get_one::<String>("separator").cloned()
+        } else {
+            args.get_or_config::<String>("separator", config.separator)
+        }
+        .expect("has a default value");
+        let timestamp = args
+            .get_or_config::<bool>("timestamp", config.timestamp)
+            .expect("has a default value");
+
+        Ok(Self {
+            format_output,
+            units,
+            precision,
+            rounding,
+            duration_format,
+            template,
+            separator,
+            output,
+            thresholds,
+            timestamp,
+        })
+    }
+}
+
+/// `--diff` and the config's `diff`, which can't be used with `--template` on the command line,
+/// or with outputs that need every field.
+pub fn diff_from_args(
+    args: &ArgMatches,
+    config: Option<&bool>,
+    output: OutputFormat,
+) -> Result<bool, Error> {
+    let diff = !args.is_from_command_line("template")
+        && args
+            .get_or_config::<bool>("diff", config)
+            .expect("has a default value");
+    if diff && !output.supports_diff() {
+        return Err(Error::InvalidArgument {
+            message: format!("--diff cannot be used with --output {}", output),
+        });
+    }
+    Ok(diff)
+}
+
+/// What an [`Output`] is rendered as, selected with `--output`.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Values joined by the separator, or rendered with the template
+    #[default]
+    Text,
+    Json,
+    /// One JSON object per line, the same as [`OutputFormat::Json`]
+    Ndjson,
+    /// Values as comma-separated values, with a header row of the labels before the first output
+    Csv,
+    /// Values as tab-separated values, with a header row of the labels before the first output
+    Tsv,
+    /// A YAML document per output
+    Yaml,
+    /// JSON for waybar's custom modules, with the text output as `text` and the state as `class`
+    Waybar,
+    /// A block of the i3bar protocol, with the text output as `full_text`, which is `urgent` when
+    /// critical
+    I3bar,
+}
+
+/// A [`FieldValue`] rendered with [`FormatOptions`].
+enum RenderedValue<'a> {
+    /// Already rounded to `precision`, if set
+    Number {
+        value: f64,
+        precision: Option<usize>,
+    },
+    /// Already rounded to `precision`, if set
+    NumberWithUnit {
+        value: f64,
+        precision: Option<usize>,
+        unit: Unit,
+    },
+    String(std::borrow::Cow<'a, str>),
+    Timestamp(Timestamp),
+}
+
 impl FromStr for FormatOutputType {
     type Err = String;
 
@@ -106,34 +565,371 @@ impl FromStr for FormatOutputType {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, PartialEq)]
 pub struct Output<'a> {
     pub fields: Vec<Field<'a>>,
-    pub separator: Option<&'a str>,
+    pub format: &'a FormatOptions,
+    /// Prefix each value with its label (e.g. `capacity=83`), for when fields can't be told apart
+    /// by their position.
+    pub labelled: bool,
+    /// Start with a header row of the labels, for CSV and TSV.
+    pub header: bool,
 }
 
 impl<'a> Output<'a> {
-    pub fn new(fields: Vec<Field<'a>>, separator: Option<&'a str>) -> Self {
-        Self { fields, separator }
+    pub fn new(fields: Vec<Field<'a>>, format: &'a FormatOptions) -> Self {
+        Self {
+            fields,
+            format,
+            labelled: false,
+            header: true,
+        }
+    }
+
+    /// The state of every field that has a threshold.
+    pub fn states(&self) -> impl Iterator<Item = (&'a str, State)> {
+        self.fields.iter().filter_map(|field| {
+            field_state(&self.format.thresholds, field).map(|state| (field.label, state))
+        })
+    }
+
+    /// The worst state of any field, or `None` if no field has a threshold.
+    pub fn state(&self) -> Option<State> {
+        self.states().map(|(_, state)| state).max()
+    }
+
+    pub fn render(&self) -> String {
+        if self.format.timestamp {
+            let mut output = self.clone();
+            let timestamp = datetime::to_rfc3339(SystemTime::now());
+            output
+                .fields
+                .insert(0, Field::new("timestamp", FieldValue::String(timestamp)));
+            output.render_fields()
+        } else {
+            self.render_fields()
+        }
+    }
+
+    fn render_fields(&self) -> String {
+        match self.format.output {
+            OutputFormat::Text => self.to_string(),
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                serde_json::to_string(self).expect("always valid")
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                let record = match self.format.output {
+                    OutputFormat::Csv => encode::csv_record,
+                    _ => encode::tsv_record,
+                };
+                let values = self
+                    .fields
+                    .iter()
+                    .map(|field| self.format.render(field, None).to_string())
+                    .collect::<Vec<_>>();
+                let row = record(&values.iter().map(String::as_str).collect::<Vec<_>>());
+                if self.header {
+                    let labels = self.fields.iter().map(|field| field.label);
+                    format!("{}\n{}", record(&labels.collect::<Vec<_>>()), row)
+                } else {
+                    row
+                }
+            }
+            OutputFormat::Yaml => {
+                encode::yaml_document(&serde_json::to_value(self).expect("always valid"))
+            }
+            OutputFormat::Waybar => {
+                let tooltip = self
+                    .fields
+                    .iter()
+                    .map(|field| format!("{}: {}", field.label, self.format.render(field, None)))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let mut waybar = serde_json::json!({
+                    "text": self.to_string(),
+                    "tooltip": tooltip,
+                });
+                if let Some(state) = self.state() {
+                    // e.g. ["warning", "capacity-warning"], for styling by either
+                    let classes = std::iter::once(state.to_string())
+                        .chain(
+                            self.states()
+                                .map(|(label, state)| format!("{}-{}", label, state)),
+                        )
+                        .collect::<Vec<_>>();
+                    waybar["class"] = classes.into();
+                    waybar["alt"] = state.as_str().into();
+                }
+                waybar.to_string()
+            }
+            OutputFormat::I3bar => serde_json::json!({
+                "full_text": self.to_string(),
+                "urgent": self.state() == Some(State::Critical),
+            })
+            .to_string(),
+        }
+    }
+
+    /// Only the fields that are rendered differently from, or are missing in, `previous`. When
+    /// serialized, this is a JSON Merge Patch (RFC 7396) from `previous` to `self`.
+    pub fn diff(&self, previous: &Output<'a>) -> Output<'a> {
+        let fields = self
+            .fields
+            .iter()
+            .filter(|field| {
+                !previous
+                    .fields
+                    .iter()
+                    .any(|previous_field| self.renders_same(previous_field, field))
+            })
+            .cloned()
+            .collect();
+
+        Output {
+            fields,
+            format: self.format,
+            labelled: true,
+            header: self.header,
+        }
+    }
+
+    /// Whether `a` and `b` are shown the same, so values that only differ beyond the precision or
+    /// units they're rendered with count as unchanged.
+    fn renders_same(&self, a: &Field, b: &Field) -> bool {
+        let thresholds = &self.format.thresholds;
+        a.label == b.label
+            && self.format.render(a, None).to_string() == self.format.render(b, None).to_string()
+            && field_state(thresholds, a) == field_state(thresholds, b)
+    }
+}
+
+/// `--output` and `--json` on the command line take precedence over both of them in the config.
+/// JSON output from the config isn't used if `--separator` was passed.
+pub fn output_format(
+    args: &ArgMatches,
+    config: Option<&OutputFormat>,
+    config_json: Option<&bool>,
+) -> OutputFormat {
+    if args.get_flag("json") {
+        return OutputFormat::Json;
+    }
+    if args.is_from_command_line("output") {
+        return *args.get_one::<OutputFormat>("output").expect("has a value");
+    }
+
+    let config = config
+        .copied()
+        .or_else(|| (config_json == Some(&true)).then_some(OutputFormat::Json));
+    match config {
+        Some(OutputFormat::Json) if args.is_from_command_line("separator") => OutputFormat::Text,
+        Some(output) => output,
+        None => *args
+            .get_one::<OutputFormat>("output")
+            .expect("has a default value"),
+    }
+}
+
+/// Writes `line` and a newline, waiting for as long as `writer` is blocked.
+pub async fn write_line(writer: &mut (impl AsyncWrite + Unpin), line: &str) -> std::io::Result<()> {
+    let mut line = line.to_string();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Keeps the previous output of a stream, to skip outputs where nothing changed.
+pub struct OutputDiffer<'a> {
+    previous_output: Option<Output<'a>>,
+    skip_unchanged: bool,
+    diff: bool,
+}
+
+impl<'a> OutputDiffer<'a> {
+    /// Unchanged outputs are skipped when watching, or when polling with `diff` set. If `diff` is
+    /// set, outputs only contain the fields that changed, except for the first one which contains
+    /// every field.
+    pub fn new(mode: RunMode, diff: bool) -> Self {
+        Self {
+            previous_output: None,
+            skip_unchanged: !matches!(mode, RunMode::Poll(_)) || diff,
+            diff,
+        }
+    }
+
+    /// Returns what should be output for `output`, or `None` if it should be skipped.
+    pub fn next(&mut self, mut output: Output<'a>) -> Option<Output<'a>> {
+        output.labelled = self.diff;
+        let is_first = self.previous_output.is_none();
+        let mut next_output = match &self.previous_output {
+            Some(previous_output)
+                if self.skip_unchanged
+                    && previous_output.render_fields() == output.render_fields() =>
+            {
+                return None;
+            }
+            Some(previous_output) if self.diff => output.diff(previous_output),
+            _ => output.clone(),
+        };
+        next_output.header = is_first;
+        self.previous_output = Some(output);
+        Some(next_output)
+    }
+}
+
+impl FormatOptions {
+    /// Renders `field`'s value, with `precision` taking precedence over `--precision`.
+    fn render<'a>(&self, field: &'a Field, precision: Option<usize>) -> RenderedValue<'a> {
+        let precision = precision.or_else(|| self.precision.for_field(field.label));
+        let round = |value: f64| match precision {
+            Some(precision) => self.rounding.round(value, precision),
+            None => value,
+        };
+        let quantity = match &field.value {
+            FieldValue::String(v) => return RenderedValue::String(v.into()),
+            FieldValue::Number(value) => {
+                return RenderedValue::Number {
+                    value: round(*value),
+                    precision,
+                };
+            }
+            FieldValue::Quantity(quantity) => quantity,
+        };
+        if self.format_output == FormatOutputType::Raw {
+            return RenderedValue::Number {
+                value: round(quantity.value),
+                precision,
+            };
+        }
+
+        match (self.units.unit_for(quantity), self.format_output) {
+            (Some(unit), FormatOutputType::Formatted) => RenderedValue::NumberWithUnit {
+                value: round(quantity.value_in(unit)),
+                precision,
+                unit,
+            },
+            (Some(unit), _) => RenderedValue::Number {
+                value: round(quantity.value_in(unit)),
+                precision,
+            },
+            // Clock durations stay serialized as `{"h":1,"m":23,"s":45}` without symbols.
+            (None, FormatOutputType::NoSymbols)
+                if self.duration_format == DurationFormat::Clock =>
+            {
+                RenderedValue::Timestamp(as_seconds(quantity).as_timestamp())
+            }
+            (None, _) => {
+                RenderedValue::String(self.duration_format.format(as_seconds(quantity)).into())
+            }
+        }
+    }
+}
+
+fn as_seconds(duration: &Quantity) -> Seconds {
+    // Discard fractions of a second
+    duration.value_in(Unit::SECOND) as Seconds
+}
+
+impl<'a> RenderedValue<'a> {
+    fn is_number(&self) -> bool {
+        matches!(
+            self,
+            RenderedValue::Number { .. } | RenderedValue::NumberWithUnit { .. }
+        )
+    }
+}
+
+/// Writes `value` with exactly `precision` decimal places if set, so that e.g. `--precision 1`
+/// renders 83 as `83.0` and outputs keep the same width.
+fn write_number(
+    f: &mut std::fmt::Formatter<'_>,
+    value: f64,
+    precision: Option<usize>,
+) -> std::fmt::Result {
+    match precision {
+        Some(precision) => write!(f, "{:.*}", precision, value),
+        None => write!(f, "{}", value),
+    }
+}
+
+impl<'a> Display for RenderedValue<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderedValue::Number { value, precision } => write_number(f, *value, *precision),
+            RenderedValue::NumberWithUnit {
+                value,
+                precision,
+                unit,
+            } => {
+                write_number(f, *value, *precision)?;
+                write!(f, "{}", unit)
+            }
+            RenderedValue::String(v) => write!(f, "{}", v),
+            RenderedValue::Timestamp(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl<'a> Serialize for RenderedValue<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            // Whole numbers are serialized as integers, e.g. `3000` rather than `3000.0`
+            RenderedValue::Number { value, .. }
+                if value.fract() == 0.0 && value.abs() < 2f64.powi(53) =>
+            {
+                serializer.serialize_i64(*value as i64)
+            }
+            RenderedValue::Number { value, .. } => serializer.serialize_f64(*value),
+            RenderedValue::NumberWithUnit { .. } => serializer.collect_str(self),
+            RenderedValue::String(v) => serializer.serialize_str(v),
+            RenderedValue::Timestamp(v) => v.serialize(serializer),
+        }
+    }
+}
+
+impl<'a> TemplateContext for Output<'a> {
+    fn field(&self, name: &str, precision: Option<usize>) -> Option<(String, bool)> {
+        let field = self.fields.iter().find(|field| field.label == name)?;
+        let value = self.format.render(field, precision);
+        Some((value.to_string(), value.is_number()))
+    }
+
+    fn state(&self, name: Option<&str>) -> Option<State> {
+        match name {
+            Some(name) => self
+                .states()
+                .find(|(label, _)| *label == name)
+                .map(|(_, state)| state),
+            None => self.state(),
+        }
     }
 }
 
 impl<'a> Display for Output<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(template) = &self.format.template
+            && !self.labelled
+        {
+            return write!(f, "{}", template.render(self));
+        }
+
         write!(
             f,
             "{}",
             self.fields
                 .iter()
-                .map(|f| match &f.value {
-                    FieldValue::I32(v) => v.to_string(),
-                    FieldValue::U64(v) => v.to_string(),
-                    FieldValue::F32(v) => v.to_string(),
-                    FieldValue::String(v) => v.to_string(),
-                    FieldValue::Timestamp(timestamp) => timestamp.to_string(),
+                .map(|f| {
+                    let value = self.format.render(f, None);
+                    if self.labelled {
+                        format!("{}={}", f.label, value)
+                    } else {
+                        value.to_string()
+                    }
                 })
                 .collect::<Vec<_>>()
-                .join(self.separator.expect("should always have a separator"))
+                .join(&self.format.separator)
         )
     }
 }
@@ -143,17 +939,232 @@ impl<'a> Serialize for Output<'a> {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_map(Some(self.fields.len()))?;
+        let mut state = serializer.serialize_map(None)?;
         for field in &self.fields {
-            match &field.value {
-                // TODO: fix duplicate code
-                FieldValue::I32(v) => state.serialize_entry(field.label, v)?,
-                FieldValue::U64(v) => state.serialize_entry(field.label, v)?,
-                FieldValue::F32(v) => state.serialize_entry(field.label, v)?,
-                FieldValue::String(v) => state.serialize_entry(field.label, v)?,
-                FieldValue::Timestamp(v) => state.serialize_entry(field.label, v)?,
+            state.serialize_entry(field.label, &self.format.render(field, None))?;
+        }
+        let states = self.states().collect::<Vec<_>>();
+        if !states.is_empty() {
+            state.serialize_entry("states", &States(&states))?;
+            // Fields missing from a diff don't count towards it
+            if !self.labelled {
+                state.serialize_entry("state", &self.state())?;
             }
         }
         state.end()
     }
 }
+
+/// Serializes states as a map in the order of the fields.
+struct States<'a>(&'a [(&'a str, State)]);
+
+impl<'a> Serialize for States<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(label, state)| (label, state)))
+    }
+}
+
+impl OutputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Waybar => "waybar",
+            OutputFormat::I3bar => "i3bar",
+        }
+    }
+
+    /// Whether `--diff` can be used, as the other formats need every field in every output.
+    pub fn supports_diff(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Text | OutputFormat::Json | OutputFormat::Ndjson
+        )
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            "tsv" => Ok(Self::Tsv),
+            "yaml" => Ok(Self::Yaml),
+            "waybar" => Ok(Self::Waybar),
+            "i3bar" => Ok(Self::I3bar),
+            _ => Err(Self::Err::InvalidArgument {
+                message: format!(
+                    "Invalid output format \"{}\". Expected \"text\", \"json\", \"ndjson\", \"csv\", \"tsv\", \"yaml\", \"waybar\", or \"i3bar\"",
+                    s
+                ),
+            }),
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommonConfig;
+
+    fn format_options(precision: Option<usize>, duration_format: DurationFormat) -> FormatOptions {
+        FormatOptions {
+            format_output: FormatOutputType::Formatted,
+            units: Units::default(),
+            precision: Precision {
+                default: precision,
+                fields: Vec::new(),
+            },
+            rounding: Rounding::default(),
+            duration_format,
+            template: None,
+            separator: " ".to_string(),
+            output: OutputFormat::Text,
+            thresholds: Thresholds::default(),
+            timestamp: false,
+        }
+    }
+
+    fn output<'a>(format: &'a FormatOptions, values: &[(&'a str, f64)]) -> Output<'a> {
+        let fields = values
+            .iter()
+            .map(|(label, value)| Field::new(label, FieldValue::Number(*value)))
+            .collect();
+        Output::new(fields, format)
+    }
+
+    fn rendered(output: Option<Output>) -> Option<String> {
+        output.map(|output| output.to_string())
+    }
+
+    #[test]
+    fn changes_beyond_precision_are_skipped() {
+        let format = format_options(Some(0), DurationFormat::default());
+        let mut differ = OutputDiffer::new(RunMode::Watch, false);
+
+        assert_eq!(
+            rendered(differ.next(output(&format, &[("capacity", 83.2)]))),
+            Some("83".to_string())
+        );
+        assert_eq!(
+            rendered(differ.next(output(&format, &[("capacity", 83.4)]))),
+            None
+        );
+        assert_eq!(
+            rendered(differ.next(output(&format, &[("capacity", 83.6)]))),
+            Some("84".to_string())
+        );
+    }
+
+    #[test]
+    fn changes_hidden_by_the_duration_format_are_skipped() {
+        let format = format_options(None, DurationFormat::Compact);
+        let duration = |seconds: u32| {
+            let value = FieldValue::Quantity(Quantity::new(seconds, Unit::SECOND));
+            Output::new(vec![Field::new("position", value)], &format)
+        };
+        let mut differ = OutputDiffer::new(RunMode::Watch, false);
+
+        assert!(differ.next(duration(3600)).is_some());
+        assert!(differ.next(duration(3630)).is_none());
+        assert!(differ.next(duration(7200)).is_some());
+    }
+
+    #[test]
+    fn diff_only_has_fields_shown_differently() {
+        let format = format_options(Some(0), DurationFormat::default());
+        let mut differ = OutputDiffer::new(RunMode::Watch, true);
+
+        assert_eq!(
+            rendered(differ.next(output(&format, &[("capacity", 83.2), ("power", 1.0)]))),
+            Some("capacity=83 power=1".to_string())
+        );
+        assert_eq!(
+            rendered(differ.next(output(&format, &[("capacity", 83.4), ("power", 2.0)]))),
+            Some("power=2".to_string())
+        );
+        assert_eq!(
+            rendered(differ.next(output(&format, &[("capacity", 83.3), ("power", 2.1)]))),
+            None
+        );
+    }
+
+    #[test]
+    fn diff_is_against_the_last_output() {
+        let format = format_options(None, DurationFormat::default());
+        let previous = output(&format, &[("capacity", 83.0), ("power", 1.0)]);
+        let current = output(&format, &[("capacity", 83.0), ("power", 2.0)]);
+
+        let diff = current.diff(&previous);
+        assert_eq!(diff.to_string(), "power=2");
+        assert!(previous.diff(&previous).fields.is_empty());
+    }
+
+    #[test]
+    fn polling_outputs_unchanged_values_without_diff() {
+        let format = format_options(Some(0), DurationFormat::default());
+        let poll = PollOptions {
+            milliseconds: 1000,
+            align: Alignment::default(),
+            missed_tick: MissedTick::default(),
+        };
+        let mut differ = OutputDiffer::new(RunMode::Poll(poll), false);
+
+        assert!(
+            differ
+                .next(output(&format, &[("capacity", 83.0)]))
+                .is_some()
+        );
+        assert!(
+            differ
+                .next(output(&format, &[("capacity", 83.0)]))
+                .is_some()
+        );
+    }
+
+    fn format_options_from(args: &[&str], config: &CommonConfig) -> Result<FormatOptions, Error> {
+        let args = battery::cli().get_matches_from([&["battery"], args].concat());
+        FormatOptions::from_args(&args, &config.format(), battery::FIELD_NAMES)
+    }
+
+    #[test]
+    fn templates_are_not_used_with_json_output() {
+        for output in ["json", "ndjson"] {
+            let err = format_options_from(
+                &["--output", output, "--template", "{capacity}"],
+                &CommonConfig::default(),
+            )
+            .err()
+            .expect("is rejected");
+            assert_eq!(
+                err.to_string(),
+                format!("--template cannot be used with --output {output}")
+            );
+        }
+
+        let config = CommonConfig {
+            output: Some(OutputFormat::Json),
+            ..CommonConfig::default()
+        };
+        let format = format_options_from(&["--template", "{capacity}"], &config).unwrap();
+        assert!(format.output == OutputFormat::Text);
+        assert!(format.template.is_some());
+    }
+}
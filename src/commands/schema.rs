@@ -0,0 +1,433 @@
+//! `getinfo schema`, which describes the fields of every module, generated from the same
+//! definitions that the modules use to parse and output them.
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use gi_core::{
+    Error,
+    units::{Dimension, Unit},
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::commands::{battery, media};
+
+/// The fields of a subcommand.
+#[derive(Serialize)]
+pub struct ModuleSchema {
+    pub name: String,
+    pub description: String,
+    pub fields: Vec<FieldSchema>,
+    /// A regex of the names of other fields that can be output, e.g. metadata entries.
+    pub extra_fields: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    /// Other names that are accepted for the field, e.g. in `--precision` and templates.
+    pub aliases: &'static [&'static str],
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    /// What the field's unit measures, if it's numeric.
+    pub dimension: Option<&'static str>,
+    /// The unit it's output in when `--units` doesn't select one.
+    pub unit: Option<String>,
+    /// The unit it's read in, and output in with `--format-output raw`.
+    pub raw_unit: Option<String>,
+    /// Whether `--watch` outputs when the field changes, rather than only alongside other fields.
+    pub watchable: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Number,
+    Integer,
+    Duration,
+    String,
+}
+
+impl ModuleSchema {
+    pub fn new(command: &Command, fields: Vec<FieldSchema>) -> Self {
+        Self {
+            name: command.get_name().to_string(),
+            description: command
+                .get_about()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            fields,
+            extra_fields: None,
+        }
+    }
+
+    /// Allows fields named by the regex `pattern`, which may be numbers or strings.
+    pub fn with_extra_fields(mut self, pattern: &'static str) -> Self {
+        self.extra_fields = Some(pattern);
+        self
+    }
+
+    /// A JSON Schema (draft 2020-12) of the subcommand's `--json` output.
+    pub fn json_schema(&self) -> Value {
+        let mut properties = serde_json::Map::new();
+        for field in &self.fields {
+            properties.insert(field.name.to_string(), field.json_schema());
+        }
+
+        let mut schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": format!("getinfo {} --json", self.name),
+            "description": self.description,
+            "type": "object",
+            "additionalProperties": false,
+        });
+        let numeric_fields = self
+            .fields
+            .iter()
+            .filter(|field| field.field_type != FieldType::String)
+            .map(|field| field.name)
+            .collect::<Vec<_>>();
+        if !numeric_fields.is_empty() {
+            schema["$defs"] = json!({ "state": { "enum": ["good", "warning", "critical"] } });
+            properties.insert(
+                "states".to_string(),
+                json!({
+                    "description": "The state of every field that has a threshold",
+                    "type": "object",
+                    "propertyNames": { "enum": numeric_fields },
+                    "additionalProperties": { "$ref": "#/$defs/state" },
+                }),
+            );
+            properties.insert(
+                "state".to_string(),
+                json!({
+                    "description": "The worst state of any field. Not set in diffs",
+                    "$ref": "#/$defs/state",
+                }),
+            );
+        }
+
+        properties.insert(
+            "timestamp".to_string(),
+            json!({
+                "description": "When the output was made, in UTC. Only set with `--timestamp`",
+                "type": "string",
+                "format": "date-time",
+            }),
+        );
+
+        schema["properties"] = properties.into();
+        if let Some(pattern) = self.extra_fields {
+            schema["patternProperties"] = json!({
+                pattern: {
+                    "description": "A field that isn't listed, e.g. a metadata entry. An empty string when it's unknown",
+                    "type": ["number", "string"],
+                },
+            });
+        }
+        schema
+    }
+}
+
+impl FieldSchema {
+    /// `raw_unit` is `None` for fields that aren't numeric.
+    pub fn new(
+        name: &'static str,
+        aliases: &'static [&'static str],
+        raw_unit: Option<Unit>,
+        watchable: bool,
+    ) -> Self {
+        let dimension = raw_unit.map(|unit| unit.dimension());
+        Self {
+            name,
+            aliases,
+            field_type: match dimension {
+                Some(Dimension::Duration) => FieldType::Duration,
+                Some(_) => FieldType::Number,
+                None => FieldType::String,
+            },
+            dimension: dimension.map(|dimension| dimension.as_str()),
+            unit: dimension
+                .and_then(|dimension| dimension.default_unit())
+                .map(unit_name),
+            raw_unit: raw_unit.map(unit_name),
+            watchable,
+        }
+    }
+
+    /// A count or other whole number without a unit, e.g. a track number.
+    pub fn integer(name: &'static str, aliases: &'static [&'static str], watchable: bool) -> Self {
+        Self {
+            name,
+            aliases,
+            field_type: FieldType::Integer,
+            dimension: None,
+            unit: None,
+            raw_unit: None,
+            watchable,
+        }
+    }
+
+    fn json_schema(&self) -> Value {
+        match self.field_type {
+            FieldType::Number => json!({
+                "description": format!(
+                    "In {} by default, or {} with `--format-output raw`. A string with the unit's symbol with `--format-output formatted`, or an empty string when it's unknown",
+                    self.unit.as_deref().unwrap_or_default(),
+                    self.raw_unit.as_deref().unwrap_or_default(),
+                ),
+                "type": ["number", "string"],
+            }),
+            FieldType::Integer => json!({
+                "description": "An empty string when it's unknown",
+                "anyOf": [{ "type": "integer" }, { "const": "" }],
+            }),
+            FieldType::Duration => json!({
+                "description": format!(
+                    "An object of hours, minutes, and seconds by default, a number of {} with `--format-output raw` or a unit selected with `--units`, otherwise a string in the `--duration-format`. An empty string when it's unknown",
+                    self.raw_unit.as_deref().unwrap_or_default(),
+                ),
+                "anyOf": [
+                    {
+                        "type": "object",
+                        "properties": {
+                            "h": { "type": "integer", "minimum": 0 },
+                            "m": { "type": "integer", "minimum": 0, "maximum": 59 },
+                            "s": { "type": "integer", "minimum": 0, "maximum": 59 },
+                        },
+                        "required": ["h", "m", "s"],
+                        "additionalProperties": false,
+                    },
+                    { "type": "number" },
+                    { "type": "string" },
+                ],
+            }),
+            FieldType::String => json!({ "type": "string" }),
+        }
+    }
+}
+
+/// The unit's symbol, or `fraction` for ratios where 1 is the whole, which don't have one.
+fn unit_name(unit: Unit) -> String {
+    match unit.to_string() {
+        symbol if symbol.is_empty() => "fraction".to_string(),
+        symbol => symbol,
+    }
+}
+
+pub fn cli() -> Command {
+    Command::new("schema")
+        .about("Lists the fields of every module, or outputs a JSON Schema of a module's `--json` output")
+        .arg(
+            Arg::new("module")
+                .value_name("MODULE")
+                .help("Only describe this module"),
+        )
+        .arg(
+            Arg::new("json")
+                .short('j')
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Formats output into json"),
+        )
+        .arg(
+            Arg::new("json_schema")
+                .long("json-schema")
+                .requires("module")
+                .conflicts_with("json")
+                .action(ArgAction::SetTrue)
+                .help("Outputs a JSON Schema of the module's `--json` output"),
+        )
+}
+
+/// Every subcommand that outputs fields.
+pub fn modules() -> Vec<ModuleSchema> {
+    vec![battery::schema(), media::schema()]
+}
+
+pub fn exec(args: &ArgMatches) -> Result<(), Error> {
+    let modules = match args.get_one::<String>("module") {
+        Some(name) => {
+            let module = modules()
+                .into_iter()
+                .find(|module| module.name == *name)
+                .ok_or_else(|| Error::InvalidArgument {
+                    message: format!(
+                        "Invalid module \"{}\". Expected one of: {}",
+                        name,
+                        modules()
+                            .iter()
+                            .map(|module| module.name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                })?;
+            vec![module]
+        }
+        None => modules(),
+    };
+
+    if args.get_flag("json_schema") {
+        let schema = modules
+            .first()
+            .expect("`--json-schema` requires a module")
+            .json_schema();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema).expect("always valid")
+        );
+    } else if args.get_flag("json") {
+        println!("{}", serde_json::to_string(&modules).expect("always valid"));
+    } else {
+        for (i, module) in modules.iter().enumerate() {
+            if i != 0 {
+                println!();
+            }
+            print_module(module);
+        }
+    }
+    Ok(())
+}
+
+fn print_module(module: &ModuleSchema) {
+    println!("{}: {}", module.name, module.description);
+    if module.fields.is_empty() {
+        println!("  (no fields)");
+        return;
+    }
+
+    let rows = module
+        .fields
+        .iter()
+        .map(|field| {
+            [
+                field.name.to_string(),
+                match field.field_type {
+                    FieldType::Number => "number",
+                    FieldType::Integer => "integer",
+                    FieldType::Duration => "duration",
+                    FieldType::String => "string",
+                }
+                .to_string(),
+                field.unit.clone().unwrap_or_else(|| "-".to_string()),
+                field.raw_unit.clone().unwrap_or_else(|| "-".to_string()),
+                if field.watchable { "yes" } else { "no" }.to_string(),
+                field.aliases.join(", "),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["FIELD", "TYPE", "UNIT", "RAW UNIT", "WATCHABLE", "ALIASES"].map(String::from);
+    let mut widths = [0; 6];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("  {}", line.trim_end());
+    }
+    if let Some(pattern) = module.extra_fields {
+        println!("  Also any field matching `{}`", pattern);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(name: &str) -> ModuleSchema {
+        modules()
+            .into_iter()
+            .find(|module| module.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn every_field_is_a_property() {
+        for module in modules() {
+            let schema = module.json_schema();
+            assert_eq!(schema["additionalProperties"], false);
+            for field in &module.fields {
+                assert!(
+                    schema["properties"].get(field.name).is_some(),
+                    "{} has no {}",
+                    module.name,
+                    field.name,
+                );
+            }
+            for name in ["states", "state", "timestamp"] {
+                assert!(schema["properties"].get(name).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn states_are_of_numeric_fields() {
+        let schema = module("battery").json_schema();
+        let names = &schema["properties"]["states"]["propertyNames"]["enum"];
+        assert!(names.as_array().unwrap().contains(&json!("capacity")));
+        assert!(!names.as_array().unwrap().contains(&json!("status")));
+    }
+
+    #[test]
+    fn media_allows_metadata_entries() {
+        let schema = module("media").json_schema();
+        let pattern = schema["patternProperties"]
+            .as_object()
+            .expect("media fields can be metadata entries");
+        assert_eq!(pattern.keys().collect::<Vec<_>>(), ["^[^:]+:.+$"]);
+
+        let battery = module("battery").json_schema();
+        assert!(battery.get("patternProperties").is_none());
+    }
+
+    #[test]
+    fn integers_can_be_unknown() {
+        let schema = module("media").json_schema();
+        assert_eq!(
+            schema["properties"]["track_number"]["anyOf"],
+            json!([{ "type": "integer" }, { "const": "" }]),
+        );
+    }
+
+    #[test]
+    fn field_types() {
+        let schema = module("battery").json_schema();
+        assert_eq!(
+            schema["properties"]["capacity"]["type"],
+            json!(["number", "string"])
+        );
+        assert_eq!(schema["properties"]["status"]["type"], "string");
+        assert_eq!(
+            schema["properties"]["time_remaining"]["anyOf"][0]["required"],
+            json!(["h", "m", "s"]),
+        );
+        assert_eq!(schema["properties"]["timestamp"]["format"], "date-time");
+    }
+
+    #[test]
+    fn numeric_fields_have_units() {
+        let battery = module("battery");
+        let capacity = battery
+            .fields
+            .iter()
+            .find(|field| field.name == "capacity")
+            .unwrap();
+        assert!(capacity.field_type == FieldType::Number);
+        assert_eq!(capacity.raw_unit.as_deref(), Some("fraction"));
+
+        let media = module("media");
+        let track_number = media
+            .fields
+            .iter()
+            .find(|field| field.name == "track_number")
+            .unwrap();
+        assert!(track_number.field_type == FieldType::Integer);
+        assert_eq!(track_number.unit, None);
+    }
+}
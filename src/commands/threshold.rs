@@ -0,0 +1,391 @@
+//! Maps numeric fields into states, so that bars can style a widget differently when a value is
+//! low or high.
+//!
+//! Thresholds are compared against a field's value in its default unit, regardless of `--units`
+//! and `--format-output` (e.g. % for capacity, mAh for charge, and seconds for durations).
+
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use gi_core::{
+    Error,
+    units::{Quantity, Unit},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::commands::{Field, FieldValue};
+
+/// Ordered from best to worst, so that the state of an output is the maximum of its fields'.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Good,
+    Warning,
+    Critical,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ThresholdSpec")]
+pub struct Threshold {
+    pub warning: f64,
+    pub critical: f64,
+    pub direction: ThresholdDirection,
+}
+
+/// A [`Threshold`] as written in the config file, where the direction can be left out to be
+/// inferred like in `--threshold`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThresholdSpec {
+    warning: f64,
+    critical: f64,
+    direction: Option<ThresholdDirection>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ThresholdDirection {
+    /// The state worsens as the value goes down (e.g. battery capacity).
+    Below,
+    /// The state worsens as the value goes up (e.g. temperature).
+    Above,
+}
+
+/// A threshold for a field, from `--threshold FIELD=WARNING:CRITICAL[:DIRECTION]`.
+///
+/// The direction defaults to `above` if `critical` is greater than `warning`, otherwise `below`.
+#[derive(Clone)]
+pub struct FieldThreshold {
+    pub field: String,
+    pub threshold: Threshold,
+}
+
+/// Thresholds by field name.
+pub type Thresholds = HashMap<String, Threshold>;
+
+impl State {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Good => "good",
+            State::Warning => "warning",
+            State::Critical => "critical",
+        }
+    }
+}
+
+impl FromStr for State {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "good" => Ok(Self::Good),
+            "warning" => Ok(Self::Warning),
+            "critical" => Ok(Self::Critical),
+            _ => Err(Self::Err::InvalidArgument {
+                message: format!(
+                    "Invalid state \"{}\". Expected \"good\", \"warning\", or \"critical\"",
+                    s
+                ),
+            }),
+        }
+    }
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl From<ThresholdSpec> for Threshold {
+    fn from(spec: ThresholdSpec) -> Self {
+        Self {
+            warning: spec.warning,
+            critical: spec.critical,
+            direction: spec
+                .direction
+                .unwrap_or_else(|| ThresholdDirection::infer(spec.warning, spec.critical)),
+        }
+    }
+}
+
+impl Threshold {
+    pub fn state(&self, value: f64) -> State {
+        let reached = |threshold: f64| match self.direction {
+            ThresholdDirection::Below => value <= threshold,
+            ThresholdDirection::Above => value >= threshold,
+        };
+        if reached(self.critical) {
+            State::Critical
+        } else if reached(self.warning) {
+            State::Warning
+        } else {
+            State::Good
+        }
+    }
+
+    pub fn validate(&self, key: &str) -> Result<(), String> {
+        let is_ordered = match self.direction {
+            ThresholdDirection::Below => self.critical <= self.warning,
+            ThresholdDirection::Above => self.critical >= self.warning,
+        };
+        if is_ordered {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: `critical` must be {} `warning` when direction is \"{}\"",
+                key,
+                match self.direction {
+                    ThresholdDirection::Below => "less than or equal to",
+                    ThresholdDirection::Above => "greater than or equal to",
+                },
+                self.direction
+            ))
+        }
+    }
+}
+
+/// The state of `field`, or `None` if it has no threshold or isn't numeric.
+pub fn field_state(thresholds: &Thresholds, field: &Field) -> Option<State> {
+    let threshold = thresholds.get(field.label)?;
+    match &field.value {
+        FieldValue::Quantity(quantity) => Some(threshold.state(threshold_value(quantity))),
+        FieldValue::Number(value) => Some(threshold.state(*value)),
+        FieldValue::String(_) => None,
+    }
+}
+
+fn threshold_value(quantity: &Quantity) -> f64 {
+    let unit = quantity.dimension().default_unit().unwrap_or(Unit::SECOND);
+    quantity.value_in(unit)
+}
+
+impl ThresholdDirection {
+    /// The direction of a threshold that doesn't specify one: `above` if `critical` is greater
+    /// than `warning`, otherwise `below`.
+    pub fn infer(warning: f64, critical: f64) -> Self {
+        if critical > warning {
+            ThresholdDirection::Above
+        } else {
+            ThresholdDirection::Below
+        }
+    }
+}
+
+impl Display for ThresholdDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThresholdDirection::Below => write!(f, "below"),
+            ThresholdDirection::Above => write!(f, "above"),
+        }
+    }
+}
+
+impl FromStr for ThresholdDirection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "below" => Ok(Self::Below),
+            "above" => Ok(Self::Above),
+            _ => Err(Self::Err::InvalidArgument {
+                message: format!(
+                    "Invalid threshold direction \"{}\". Expected \"below\" or \"above\"",
+                    s
+                ),
+            }),
+        }
+    }
+}
+
+impl FromStr for FieldThreshold {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidArgument {
+            message: format!(
+                "Invalid threshold \"{}\". Expected \"FIELD=WARNING:CRITICAL[:DIRECTION]\", e.g. \"capacity=30:15\"",
+                s
+            ),
+        };
+
+        let (field, values) = s.split_once('=').ok_or_else(invalid)?;
+        let mut values = values.split(':');
+        let mut next_value = || {
+            values
+                .next()
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .ok_or_else(invalid)
+        };
+        let warning = next_value()?;
+        let critical = next_value()?;
+        let direction = match values.next() {
+            Some(direction) => ThresholdDirection::from_str(direction.trim())?,
+            None => ThresholdDirection::infer(warning, critical),
+        };
+        if values.next().is_some() {
+            return Err(invalid());
+        }
+
+        let field = field.trim().to_string();
+        let threshold = Threshold {
+            warning,
+            critical,
+            direction,
+        };
+        threshold
+            .validate(&field)
+            .map_err(|message| Error::InvalidArgument { message })?;
+        Ok(Self { field, threshold })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_config(toml: &str) -> Threshold {
+        toml::from_str(toml).expect("valid threshold")
+    }
+
+    #[test]
+    fn config_infers_direction_like_the_command_line() {
+        for (toml, arg) in [
+            ("warning = 30\ncritical = 15", "capacity=30:15"),
+            ("warning = 60\ncritical = 80", "capacity=60:80"),
+            ("warning = 20\ncritical = 20", "capacity=20:20"),
+        ] {
+            let from_arg = FieldThreshold::from_str(arg)
+                .expect("valid threshold")
+                .threshold;
+            assert_eq!(from_config(toml).direction, from_arg.direction, "{arg}");
+        }
+        assert_eq!(
+            from_config("warning = 60\ncritical = 80").direction,
+            ThresholdDirection::Above
+        );
+    }
+
+    #[test]
+    fn config_direction_overrides_inference() {
+        let threshold = from_config("warning = 60\ncritical = 80\ndirection = \"below\"");
+        assert_eq!(threshold.direction, ThresholdDirection::Below);
+        assert!(threshold.validate("capacity").is_err());
+    }
+
+    fn parse(arg: &str) -> Threshold {
+        FieldThreshold::from_str(arg)
+            .expect("valid threshold")
+            .threshold
+    }
+
+    fn states(threshold: &Threshold, values: &[f64]) -> Vec<&'static str> {
+        values
+            .iter()
+            .map(|value| threshold.state(*value).as_str())
+            .collect()
+    }
+
+    #[test]
+    fn direction_is_inferred_from_the_order() {
+        assert_eq!(parse("capacity=30:15").direction, ThresholdDirection::Below);
+        assert_eq!(
+            parse("temperature=60:80").direction,
+            ThresholdDirection::Above
+        );
+        // Equal values can't tell, so they fall back to below
+        assert_eq!(parse("capacity=20:20").direction, ThresholdDirection::Below);
+        assert_eq!(
+            parse("capacity=-5:-10").direction,
+            ThresholdDirection::Below
+        );
+        assert_eq!(
+            parse("capacity=0.5:1.5").direction,
+            ThresholdDirection::Above
+        );
+    }
+
+    #[test]
+    fn explicit_direction_is_kept() {
+        let threshold = parse(" capacity = 30 : 15 : below ");
+        assert_eq!(threshold.direction, ThresholdDirection::Below);
+        assert_eq!(threshold.warning, 30.0);
+        assert_eq!(threshold.critical, 15.0);
+        assert_eq!(
+            parse("charge_now=20:20:above").direction,
+            ThresholdDirection::Above
+        );
+        assert_eq!(
+            FieldThreshold::from_str("capacity=30:15").unwrap().field,
+            "capacity"
+        );
+    }
+
+    #[test]
+    fn misordered_thresholds_are_rejected() {
+        for arg in ["capacity=15:30:below", "temperature=80:60:above"] {
+            assert!(
+                matches!(
+                    FieldThreshold::from_str(arg),
+                    Err(Error::InvalidArgument { .. })
+                ),
+                "{arg} should be rejected"
+            );
+        }
+
+        let misordered = Threshold {
+            warning: 15.0,
+            critical: 30.0,
+            direction: ThresholdDirection::Below,
+        };
+        let message = misordered.validate("capacity").unwrap_err();
+        assert!(message.starts_with("capacity: "), "{message}");
+        assert!(message.contains("less than or equal to"), "{message}");
+    }
+
+    #[test]
+    fn malformed_thresholds_are_rejected() {
+        for arg in [
+            "capacity",
+            "capacity=30",
+            "capacity=30:",
+            "capacity=a:15",
+            "capacity=30:15:sideways",
+            "capacity=30:15:below:extra",
+        ] {
+            assert!(
+                matches!(
+                    FieldThreshold::from_str(arg),
+                    Err(Error::InvalidArgument { .. })
+                ),
+                "{arg} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn below_state_boundaries() {
+        let threshold = parse("capacity=30:15");
+        assert_eq!(
+            states(&threshold, &[100.0, 30.1, 30.0, 15.1, 15.0, 0.0]),
+            ["good", "good", "warning", "warning", "critical", "critical"]
+        );
+    }
+
+    #[test]
+    fn above_state_boundaries() {
+        let threshold = parse("temperature=60:80");
+        assert_eq!(
+            states(&threshold, &[0.0, 59.9, 60.0, 79.9, 80.0, 100.0]),
+            ["good", "good", "warning", "warning", "critical", "critical"]
+        );
+    }
+
+    #[test]
+    fn equal_thresholds_skip_warning() {
+        let threshold = parse("capacity=20:20");
+        assert_eq!(
+            states(&threshold, &[20.1, 20.0, 19.9]),
+            ["good", "critical", "critical"]
+        );
+    }
+}
@@ -0,0 +1,527 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::{ArgMatches, parser::ValueSource};
+use gi_battery::BatteryInfoName;
+use gi_core::{
+    duration::DurationFormat,
+    scheduler::{Alignment, MissedTick},
+    units::Units,
+};
+use serde::{Deserialize, Deserializer, de};
+
+use crate::{
+    commands::{
+        FieldNames, FormatOutputType, OutputFormat, battery,
+        format::{Precision, Rounding, Template},
+        media::{self, MediaField},
+        threshold::Thresholds,
+    },
+    error::Error,
+};
+
+const DEFAULT_PROFILE_NAME: &str = "default";
+
+/// Contents of `$XDG_CONFIG_HOME/getinfo/config.toml`.
+///
+/// ```toml
+/// default-profile = "bar"
+///
+/// [profiles.bar.battery]
+/// fields = ["capacity", "status"]
+/// format-output = "formatted"
+/// units = "si,W"
+/// separator = " | "
+/// poll = 60000
+///
+/// # The direction can be left out, as it's inferred like in `--threshold`.
+/// [profiles.bar.battery.thresholds.capacity]
+/// warning = 30
+/// critical = 15
+/// direction = "below"
+///
+/// [profiles.bar.media]
+/// fields = ["artist", "title"]
+/// priority = ["spotify", "firefox*"]
+/// ignore = ["chromium*"]
+/// watch = true
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    default_profile: Option<String>,
+    profiles: HashMap<String, Profile>,
+}
+
+/// A named set of module configs, selected with `--profile`.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub battery: BatteryConfig,
+    pub media: MediaConfig,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BatteryConfig {
+    #[serde(deserialize_with = "from_str_seq")]
+    pub fields: Option<Vec<BatteryInfoName>>,
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub common: CommonConfig,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MediaConfig {
+    #[serde(deserialize_with = "from_str_seq")]
+    pub fields: Option<Vec<MediaField>>,
+    pub player: Option<String>,
+    pub priority: Option<Vec<String>>,
+    pub ignore: Option<Vec<String>>,
+    pub latest: Option<bool>,
+    #[serde(flatten)]
+    pub common: CommonConfig,
+}
+
+/// The options of every module, which are set alongside the module's own options.
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CommonConfig {
+    #[serde(deserialize_with = "from_str")]
+    pub format_output: Option<FormatOutputType>,
+    pub units: Option<Units>,
+    pub precision: Option<Precision>,
+    #[serde(deserialize_with = "from_str")]
+    pub rounding: Option<Rounding>,
+    pub template: Option<Template>,
+    pub duration_format: Option<DurationFormat>,
+    pub watch: Option<bool>,
+    pub poll: Option<u64>,
+    #[serde(deserialize_with = "from_str")]
+    pub align: Option<Alignment>,
+    #[serde(deserialize_with = "from_str")]
+    pub missed_tick: Option<MissedTick>,
+    pub separator: Option<String>,
+    pub json: Option<bool>,
+    #[serde(deserialize_with = "from_str")]
+    pub output: Option<OutputFormat>,
+    pub diff: Option<bool>,
+    pub timestamp: Option<bool>,
+    pub on_change: Option<String>,
+    pub on_change_debounce: Option<u64>,
+    pub on_change_limit: Option<usize>,
+    pub thresholds: Thresholds,
+}
+
+/// The config options for [`OnChange::from_args`].
+///
+/// [`OnChange::from_args`]: crate::commands::hook::OnChange::from_args
+pub struct OnChangeConfig {
+    pub command: Option<String>,
+    pub debounce: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// The config options for [`FormatOptions::from_args`].
+///
+/// [`FormatOptions::from_args`]: crate::commands::FormatOptions::from_args
+pub struct FormatConfig<'a> {
+    pub format_output: Option<&'a FormatOutputType>,
+    pub units: Option<&'a Units>,
+    pub precision: Option<&'a Precision>,
+    pub rounding: Option<&'a Rounding>,
+    pub template: Option<&'a Template>,
+    pub duration_format: Option<&'a DurationFormat>,
+    pub separator: Option<&'a String>,
+    pub json: Option<&'a bool>,
+    pub output: Option<&'a OutputFormat>,
+    pub timestamp: Option<&'a bool>,
+    pub thresholds: &'a Thresholds,
+}
+
+/// The config options for [`RunMode::from_args`].
+///
+/// [`RunMode::from_args`]: crate::commands::RunMode::from_args
+pub struct RunModeConfig {
+    pub watch: Option<bool>,
+    pub poll: Option<u64>,
+    pub align: Option<Alignment>,
+    pub missed_tick: Option<MissedTick>,
+}
+
+impl Config {
+    /// Loads the config file at `path`, or the default location if `path` is `None`.
+    ///
+    /// A missing file at the default location is not an error and results in an empty config.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let (path, is_explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_config_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !is_explicit => {
+                return Ok(Self::default());
+            }
+            Err(err) => return Err(gi_core::Error::read_file(&path, err).into()),
+        };
+
+        let config: Config = toml::from_str(&contents).map_err(|err| Error::InvalidConfig {
+            path: path.display().to_string(),
+            message: err.to_string(),
+        })?;
+
+        config.validate().map_err(|message| Error::InvalidConfig {
+            path: path.display().to_string(),
+            message,
+        })?;
+
+        Ok(config)
+    }
+
+    /// Removes and returns the profile named `name`, or the default profile if `name` is `None`.
+    pub fn into_profile(mut self, name: Option<&str>) -> Result<Profile, Error> {
+        match name {
+            Some(name) => self
+                .profiles
+                .remove(name)
+                .ok_or_else(|| Error::ProfileNotFound {
+                    name: name.to_string(),
+                }),
+            None => {
+                let name = self
+                    .default_profile
+                    .as_deref()
+                    .unwrap_or(DEFAULT_PROFILE_NAME);
+                Ok(self.profiles.remove(name).unwrap_or_default())
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(name) = &self.default_profile
+            && !self.profiles.contains_key(name)
+        {
+            return Err(format!(
+                "default-profile: profile \"{}\" is not defined under [profiles]",
+                name
+            ));
+        }
+
+        for (profile_name, profile) in &self.profiles {
+            validate_module(
+                &format!("profiles.{}.battery", profile_name),
+                &profile.battery.common,
+                battery::FIELD_NAMES,
+            )?;
+            validate_module(
+                &format!("profiles.{}.media", profile_name),
+                &profile.media.common,
+                media::FIELD_NAMES,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl CommonConfig {
+    pub fn run_mode(&self) -> RunModeConfig {
+        RunModeConfig {
+            watch: self.watch,
+            poll: self.poll,
+            align: self.align,
+            missed_tick: self.missed_tick,
+        }
+    }
+
+    pub fn on_change(&self) -> OnChangeConfig {
+        OnChangeConfig {
+            command: self.on_change.clone(),
+            debounce: self.on_change_debounce,
+            limit: self.on_change_limit,
+        }
+    }
+
+    pub fn format(&self) -> FormatConfig<'_> {
+        FormatConfig {
+            format_output: self.format_output.as_ref(),
+            units: self.units.as_ref(),
+            precision: self.precision.as_ref(),
+            rounding: self.rounding.as_ref(),
+            template: self.template.as_ref(),
+            duration_format: self.duration_format.as_ref(),
+            separator: self.separator.as_ref(),
+            json: self.json.as_ref(),
+            output: self.output.as_ref(),
+            timestamp: self.timestamp.as_ref(),
+            thresholds: &self.thresholds,
+        }
+    }
+}
+
+/// Validates the options every module shares, mirroring the conflicts in
+/// [`SubCommandExt::common_args`] and [`SubCommandExt::format_args`].
+///
+/// [`SubCommandExt::common_args`]: crate::commands::SubCommandExt::common_args
+/// [`SubCommandExt::format_args`]: crate::commands::SubCommandExt::format_args
+fn validate_module(
+    key: &str,
+    config: &CommonConfig,
+    field_names: FieldNames,
+) -> Result<(), String> {
+    let (run_mode, format, on_change) = (config.run_mode(), config.format(), config.on_change());
+    let diff = config.diff;
+    let json = format.json.copied();
+    if json == Some(true) && format.output.is_some() {
+        return Err(format!("{}: `json` and `output` cannot both be set", key));
+    }
+    let output = format
+        .output
+        .copied()
+        .or((json == Some(true)).then_some(OutputFormat::Json));
+    if run_mode.watch == Some(true) && run_mode.poll.is_some() {
+        return Err(format!("{}: `watch` and `poll` cannot both be set", key));
+    }
+    if run_mode.poll == Some(0) {
+        return Err(format!("{}.poll: interval must be greater than 0", key));
+    }
+    if output == Some(OutputFormat::Json) && format.separator.is_some() {
+        return Err(format!(
+            "{}: JSON output and `separator` cannot both be set",
+            key
+        ));
+    }
+    if diff == Some(true) && !output.is_none_or(|output| output.supports_diff()) {
+        return Err(format!(
+            "{}: `diff` can only be used with text, JSON, or NDJSON output",
+            key
+        ));
+    }
+    if on_change.limit == Some(0) {
+        return Err(format!(
+            "{}.on-change-limit: limit must be greater than 0",
+            key
+        ));
+    }
+    if format.template.is_some()
+        && (matches!(output, Some(OutputFormat::Json | OutputFormat::Ndjson)) || diff == Some(true))
+    {
+        return Err(format!(
+            "{}: `template` cannot be set together with JSON output or `diff`",
+            key
+        ));
+    }
+    if let Some(precision) = format.precision {
+        precision
+            .clone()
+            .resolve_names(field_names.resolve)
+            .map_err(|err| format!("{}.precision: {}", key, err))?;
+    }
+    if let Some(template) = format.template {
+        template
+            .clone()
+            .resolve_names(field_names.resolve)
+            .map_err(|err| format!("{}.template: {}", key, err))?;
+    }
+    for (field, threshold) in format.thresholds {
+        let field_key = format!("{}.thresholds.{}", key, field);
+        let name = (field_names.resolve)(field).map_err(|err| format!("{}: {}", field_key, err))?;
+        if !(field_names.is_numeric)(&name) {
+            return Err(format!(
+                "{}: thresholds can only be set on numeric fields",
+                field_key
+            ));
+        }
+        threshold.validate(&field_key)?;
+    }
+    Ok(())
+}
+
+/// `$XDG_CONFIG_HOME/getinfo/config.toml`, falling back to `$HOME/.config/getinfo/config.toml`.
+fn default_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("getinfo").join("config.toml"))
+}
+
+fn from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let s = String::deserialize(deserializer)?;
+    T::from_str(&s).map(Some).map_err(de::Error::custom)
+}
+
+fn from_str_seq<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| T::from_str(s).map_err(de::Error::custom))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+pub trait ArgMatchesExt {
+    /// Returns the value of `id` if it was explicitly passed on the command line, otherwise the
+    /// config value, otherwise clap's default value.
+    fn get_or_config<T>(&self, id: &str, config: Option<&T>) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static;
+
+    fn is_from_command_line(&self, id: &str) -> bool;
+}
+
+impl ArgMatchesExt for ArgMatches {
+    fn get_or_config<T>(&self, id: &str, config: Option<&T>) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        if self.is_from_command_line(id) {
+            return self.get_one::<T>(id).cloned();
+        }
+        config.or_else(|| self.get_one::<T>(id)).cloned()
+    }
+
+    fn is_from_command_line(&self, id: &str) -> bool {
+        self.value_source(id) == Some(ValueSource::CommandLine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Config, String> {
+        let config = toml::from_str::<Config>(contents).map_err(|err| err.message().to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn error(contents: &str) -> String {
+        match parse(contents) {
+            Ok(_) => panic!("expected an error for:\n{}", contents),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn modules_share_the_common_options() {
+        let mut config = parse(
+            r#"
+            default-profile = "bar"
+
+            [profiles.bar.battery]
+            fields = ["capacity", "status"]
+            name = "BAT1"
+            poll = 60000
+            separator = " | "
+            precision = 2
+
+            [profiles.bar.battery.thresholds.capacity]
+            warning = 30
+            critical = 15
+
+            [profiles.bar.media]
+            fields = ["artist", "title"]
+            priority = ["spotify"]
+            watch = true
+            output = "csv"
+            on-change = "notify-send changed"
+            "#,
+        )
+        .unwrap();
+        let profile = config.profiles.remove("bar").unwrap();
+
+        let battery = &profile.battery;
+        assert_eq!(battery.name.as_deref(), Some("BAT1"));
+        assert_eq!(battery.common.poll, Some(60000));
+        assert_eq!(battery.common.separator.as_deref(), Some(" | "));
+        assert_eq!(battery.common.thresholds["capacity"].warning, 30.0);
+        assert_eq!(battery.common.watch, None);
+
+        let media = &profile.media;
+        assert_eq!(media.priority, Some(vec!["spotify".to_string()]));
+        assert_eq!(media.common.watch, Some(true));
+        assert!(media.common.output == Some(OutputFormat::Csv));
+        assert_eq!(
+            media.common.on_change().command.as_deref(),
+            Some("notify-send changed")
+        );
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        assert_eq!(
+            error("[profiles.bar.battery]\npol = 1000"),
+            "unknown field `pol`"
+        );
+        assert_eq!(
+            error("[profiles.bar.battery]\npriority = [\"spotify\"]"),
+            "unknown field `priority`"
+        );
+        assert!(error("[profiles.bar.media]\npoll = \"1000\"").contains("expected u64"));
+    }
+
+    #[test]
+    fn validation_errors_name_the_key() {
+        for (contents, message) in [
+            (
+                "[profiles.bar.battery]\npoll = 0",
+                "profiles.bar.battery.poll: interval must be greater than 0",
+            ),
+            (
+                "[profiles.bar.media]\nwatch = true\npoll = 1000",
+                "profiles.bar.media: `watch` and `poll` cannot both be set",
+            ),
+            (
+                "[profiles.bar.media]\non-change-limit = 0",
+                "profiles.bar.media.on-change-limit: limit must be greater than 0",
+            ),
+            (
+                "[profiles.bar.battery]\njson = true\nseparator = \",\"",
+                "profiles.bar.battery: JSON output and `separator` cannot both be set",
+            ),
+            (
+                "[profiles.bar.battery]\noutput = \"csv\"\ndiff = true",
+                "profiles.bar.battery: `diff` can only be used with text, JSON, or NDJSON output",
+            ),
+            (
+                "[profiles.bar.battery.thresholds.status]\nwarning = 1\ncritical = 2",
+                "profiles.bar.battery.thresholds.status: thresholds can only be set on numeric fields",
+            ),
+            (
+                "default-profile = \"bar\"",
+                "default-profile: profile \"bar\" is not defined under [profiles]",
+            ),
+        ] {
+            assert_eq!(error(contents), message);
+        }
+
+        assert!(
+            error("[profiles.bar.media]\nprecision = \"nope=2\"")
+                .starts_with("profiles.bar.media.precision: ")
+        );
+        assert!(
+            error("[profiles.bar.battery]\ntemplate = \"{nope}\"")
+                .starts_with("profiles.bar.battery.template: ")
+        );
+    }
+}
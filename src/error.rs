@@ -0,0 +1,198 @@
+//! Errors of the CLI, the config file, and the daemon and the services it runs, on top of the
+//! errors of the modules.
+
+use std::{io, sync::Arc};
+
+use gi_core::exit_code;
+use thiserror::Error;
+
+/// Cloneable like [`gi_core::Error`], which it wraps.
+#[derive(Error, Debug, Clone)]
+pub enum Error {
+    #[error(transparent)]
+    Core(#[from] gi_core::Error),
+
+    #[error("Invalid config file {}: {}", .path, .message)]
+    InvalidConfig { path: String, message: String },
+
+    #[error("Profile \"{}\" not found in config file", .name)]
+    ProfileNotFound { name: String },
+
+    #[error("Environment variable {} is not set", .name)]
+    EnvVarNotSet { name: String },
+
+    #[error("A daemon is already listening on {}", .path)]
+    DaemonAlreadyRunning { path: String },
+
+    #[error("Module \"{}\" is not supported by the daemon", .name)]
+    UnsupportedByDaemon { name: String },
+
+    #[error("Failed to connect to the daemon at {}: {}", .path, .source)]
+    ConnectDaemon {
+        path: String,
+        source: Arc<io::Error>,
+    },
+
+    #[error("Failed to listen on {}: {}", .address, .source)]
+    Listen {
+        address: String,
+        source: Arc<io::Error>,
+    },
+
+    #[error("Invalid daemon message: {}", .message)]
+    InvalidDaemonMessage { message: String },
+
+    /// An error that the daemon reported, with the exit code it would have had locally.
+    #[error("{}", .message)]
+    Daemon { message: String, exit_code: u8 },
+
+    #[error("D-Bus error: {}", .message)]
+    DBus { message: String },
+}
+
+impl Error {
+    /// See the [exit codes](gi_core#exit-codes) of `gi_core`.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Core(err) => err.exit_code(),
+            Error::ConnectDaemon { source, .. } | Error::Listen { source, .. } => {
+                match source.kind() {
+                    io::ErrorKind::NotFound => exit_code::NOT_FOUND,
+                    io::ErrorKind::PermissionDenied => exit_code::PERMISSION_DENIED,
+                    io::ErrorKind::Unsupported => exit_code::UNSUPPORTED,
+                    _ => exit_code::FAILURE,
+                }
+            }
+            Error::ProfileNotFound { .. } => exit_code::NOT_FOUND,
+            Error::UnsupportedByDaemon { .. } => exit_code::UNSUPPORTED,
+            Error::InvalidConfig { .. } => exit_code::USAGE,
+            Error::Daemon { exit_code, .. } => *exit_code,
+            Error::EnvVarNotSet { .. }
+            | Error::DaemonAlreadyRunning { .. }
+            | Error::InvalidDaemonMessage { .. }
+            | Error::DBus { .. } => exit_code::FAILURE,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Core(value.into())
+    }
+}
+
+impl From<zbus::Error> for Error {
+    fn from(value: zbus::Error) -> Self {
+        Error::DBus {
+            message: value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io_error(kind: io::ErrorKind) -> Arc<io::Error> {
+        Arc::new(io::Error::from(kind))
+    }
+
+    #[test]
+    fn module_errors_keep_their_exit_codes() {
+        let not_found = Error::from(gi_core::Error::NoMediaPlayersFound);
+        assert_eq!(not_found.exit_code(), exit_code::NOT_FOUND);
+        let usage = Error::from(gi_core::Error::InvalidArgument {
+            message: "nope".to_string(),
+        });
+        assert_eq!(usage.exit_code(), exit_code::USAGE);
+        let io = Error::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(io.exit_code(), exit_code::PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn socket_errors_map_by_kind() {
+        let connect = |kind| Error::ConnectDaemon {
+            path: "getinfo.sock".to_string(),
+            source: io_error(kind),
+        };
+        assert_eq!(
+            connect(io::ErrorKind::NotFound).exit_code(),
+            exit_code::NOT_FOUND
+        );
+        assert_eq!(
+            connect(io::ErrorKind::PermissionDenied).exit_code(),
+            exit_code::PERMISSION_DENIED
+        );
+        assert_eq!(
+            connect(io::ErrorKind::ConnectionRefused).exit_code(),
+            exit_code::FAILURE
+        );
+        let listen = Error::Listen {
+            address: "127.0.0.1:80".to_string(),
+            source: io_error(io::ErrorKind::PermissionDenied),
+        };
+        assert_eq!(listen.exit_code(), exit_code::PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn cli_and_daemon_errors() {
+        let cases = [
+            (
+                Error::InvalidConfig {
+                    path: "config.toml".to_string(),
+                    message: "bad".to_string(),
+                },
+                exit_code::USAGE,
+            ),
+            (
+                Error::ProfileNotFound {
+                    name: "bar".to_string(),
+                },
+                exit_code::NOT_FOUND,
+            ),
+            (
+                Error::UnsupportedByDaemon {
+                    name: "schema".to_string(),
+                },
+                exit_code::UNSUPPORTED,
+            ),
+            (
+                Error::EnvVarNotSet {
+                    name: "XDG_RUNTIME_DIR".to_string(),
+                },
+                exit_code::FAILURE,
+            ),
+            (
+                Error::DaemonAlreadyRunning {
+                    path: "getinfo.sock".to_string(),
+                },
+                exit_code::FAILURE,
+            ),
+            (
+                Error::InvalidDaemonMessage {
+                    message: "eof".to_string(),
+                },
+                exit_code::FAILURE,
+            ),
+            (
+                Error::DBus {
+                    message: "no bus".to_string(),
+                },
+                exit_code::FAILURE,
+            ),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.exit_code(), code, "{err}");
+        }
+    }
+
+    #[test]
+    fn daemon_errors_keep_the_daemons_exit_code() {
+        let err = Error::Daemon {
+            message: "No batteries found".to_string(),
+            exit_code: exit_code::NOT_FOUND,
+        };
+        assert_eq!(err.exit_code(), exit_code::NOT_FOUND);
+        assert_eq!(err.to_string(), "No batteries found");
+    }
+}
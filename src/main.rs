@@ -1,21 +1,216 @@
-use crate::commands::{battery, media};
-use clap::command;
+use std::{path::PathBuf, process::ExitCode};
+
+use crate::commands::{OutputFormat, battery, daemon, media, metrics, output_format, schema};
+use crate::config::{Config, Profile};
+use crate::error::Error;
+use clap::{Arg, ArgAction, ArgMatches, command, value_parser};
 
 mod commands;
+mod config;
+mod error;
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
+async fn main() -> ExitCode {
     let matches = command!()
         .propagate_version(true)
         .subcommand_required(true)
         .arg_required_else_help(true)
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .global(true)
+                .value_name("PATH")
+                .value_parser(value_parser!(PathBuf))
+                .help("Path to the config file. Defaults to $XDG_CONFIG_HOME/getinfo/config.toml"),
+        )
+        .arg(
+            Arg::new("profile")
+                .short('P')
+                .long("profile")
+                .global(true)
+                .value_name("NAME")
+                .help("Config profile to use. Defaults to `default-profile`, or \"default\""),
+        )
+        .arg(
+            Arg::new("connect")
+                .long("connect")
+                .value_name("SOCKET")
+                .num_args(0..=1)
+                .require_equals(true)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .help("Get info from a running `getinfo daemon` instead. Defaults to $XDG_RUNTIME_DIR/getinfo.sock"),
+        )
         .subcommand(battery::cli())
         .subcommand(media::cli())
+        .subcommand(daemon::cli())
+        .subcommand(schema::cli())
+        .subcommand(metrics::cli())
+        .after_help(EXIT_CODES_HELP)
         .get_matches();
 
-    match matches.subcommand() {
-        Some(("battery", sub_matches)) => battery::exec(sub_matches),
-        Some(("media", sub_matches)) => media::exec(sub_matches).await,
-        _ => unreachable!("Exhausted list of subcommands and subcommand_required prevents `None`"),
+    let profile = match Config::load(matches.get_one::<PathBuf>("config").map(PathBuf::as_path))
+        .and_then(|config| {
+            config.into_profile(matches.get_one::<String>("profile").map(String::as_str))
+        }) {
+        Ok(profile) => profile,
+        Err(err) => return report_error(&err, &matches, None),
+    };
+
+    let result = if matches.contains_id("connect") {
+        daemon::connect(
+            matches.get_one::<PathBuf>("connect").map(PathBuf::as_path),
+            &matches,
+            &profile,
+        )
+        .await
+    } else {
+        match matches.subcommand() {
+            Some(("battery", sub_matches)) => battery::exec(sub_matches, &profile.battery)
+                .await
+                .map_err(Error::from),
+            Some(("media", sub_matches)) => media::exec(sub_matches, &profile.media).await,
+            Some(("daemon", sub_matches)) => daemon::exec(sub_matches).await,
+            Some(("schema", sub_matches)) => schema::exec(sub_matches).map_err(Error::from),
+            Some(("serve-metrics", sub_matches)) => metrics::exec(sub_matches).await,
+            _ => unreachable!(
+                "Exhausted list of subcommands and subcommand_required prevents `None`"
+            ),
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => report_error(&err, &matches, Some(&profile)),
+    }
+}
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Any other error
+  2  Invalid arguments or config file
+  3  Not found, e.g. there is no battery, or no battery with the given name
+  4  Permission denied
+  5  Unsupported, e.g. the battery doesn't report the requested info";
+
+/// Prints `err` to stdout in the output format if it's read by a program, so that e.g. a bar can
+/// show that there was an error, otherwise to stderr.
+fn report_error(err: &Error, matches: &ArgMatches, profile: Option<&Profile>) -> ExitCode {
+    let output = match matches.subcommand() {
+        Some(("battery", sub_matches)) => output_format(
+            sub_matches,
+            profile.and_then(|p| p.battery.common.output.as_ref()),
+            profile.and_then(|p| p.battery.common.json.as_ref()),
+        ),
+        Some(("media", sub_matches)) => output_format(
+            sub_matches,
+            profile.and_then(|p| p.media.common.output.as_ref()),
+            profile.and_then(|p| p.media.common.json.as_ref()),
+        ),
+        _ => OutputFormat::Text,
+    };
+
+    match error_line(err, &output) {
+        ErrorLine::Stdout(line) => println!("{}", line),
+        ErrorLine::Stderr(line) => eprintln!("getinfo: {}", line),
+    }
+    ExitCode::from(err.exit_code())
+}
+
+/// Where [`report_error`] prints an error, and what.
+#[derive(Debug, PartialEq)]
+enum ErrorLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+fn error_line(err: &Error, output: &OutputFormat) -> ErrorLine {
+    let message = err.to_string();
+    match output {
+        // A row with the error would be mistaken for a record
+        OutputFormat::Text | OutputFormat::Csv | OutputFormat::Tsv => ErrorLine::Stderr(message),
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            ErrorLine::Stdout(serde_json::json!({ "error": message }).to_string())
+        }
+        OutputFormat::Yaml => ErrorLine::Stdout(commands::encode::yaml_document(
+            &serde_json::json!({ "error": message }),
+        )),
+        OutputFormat::Waybar => ErrorLine::Stdout(
+            serde_json::json!({ "text": "error", "tooltip": message, "class": ["error"] })
+                .to_string(),
+        ),
+        OutputFormat::I3bar => ErrorLine::Stdout(
+            serde_json::json!({ "full_text": message, "urgent": true }).to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn not_found() -> Error {
+        Error::ProfileNotFound {
+            name: "bar".to_string(),
+        }
+    }
+
+    #[test]
+    fn errors_are_printed_in_the_output_format() {
+        let message = r#"Profile "bar" not found in config file"#;
+        let stdout = |output| match error_line(&not_found(), &output) {
+            ErrorLine::Stdout(line) => serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            ErrorLine::Stderr(line) => panic!("printed to stderr: {line}"),
+        };
+        assert_eq!(
+            stdout(OutputFormat::Json),
+            serde_json::json!({ "error": message })
+        );
+        assert_eq!(
+            stdout(OutputFormat::Ndjson),
+            serde_json::json!({ "error": message })
+        );
+        assert_eq!(
+            stdout(OutputFormat::Waybar),
+            serde_json::json!({ "text": "error", "tooltip": message, "class": ["error"] })
+        );
+        assert_eq!(
+            stdout(OutputFormat::I3bar),
+            serde_json::json!({ "full_text": message, "urgent": true })
+        );
+    }
+
+    #[test]
+    fn json_errors_are_one_line() {
+        let err = Error::Daemon {
+            message: "first\nsecond".to_string(),
+            exit_code: 1,
+        };
+        assert_eq!(
+            error_line(&err, &OutputFormat::Ndjson),
+            ErrorLine::Stdout(r#"{"error":"first\nsecond"}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn yaml_errors_are_a_document() {
+        assert_eq!(
+            error_line(&not_found(), &OutputFormat::Yaml),
+            ErrorLine::Stdout(
+                "---\nerror: \"Profile \\\"bar\\\" not found in config file\"".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn errors_are_not_printed_as_rows() {
+        for output in [OutputFormat::Text, OutputFormat::Csv, OutputFormat::Tsv] {
+            assert_eq!(
+                error_line(&not_found(), &output),
+                ErrorLine::Stderr(r#"Profile "bar" not found in config file"#.to_string())
+            );
+        }
     }
 }